@@ -0,0 +1,47 @@
+// Cost of the miss path. Run with `cargo bench --bench background`; add
+// `--features stats` to also count the rays that reach the background.
+use ray_trace_rs::background::Background;
+use ray_trace_rs::camera::Camera;
+use ray_trace_rs::vec3::Vec3;
+use ray_trace_rs::world::HittableList;
+use std::hint::black_box;
+use std::time::Instant;
+
+const LOOKUPS: usize = 10_000_000;
+
+fn time_lookups(name: &str, background: &Background) {
+    let start = Instant::now();
+    let mut total = Vec3::EMPTY;
+    for i in 0..LOOKUPS {
+        // Sweep directions from straight down to straight up
+        let y = i as f64 / LOOKUPS as f64 * 2.0 - 1.0;
+        total += background.color(black_box(Vec3::new(0.3, y, -1.0)));
+    }
+    black_box(total);
+    let elapsed = start.elapsed();
+    println!(
+        "{:<10} {:>6.1} ns per lookup",
+        name,
+        elapsed.as_nanos() as f64 / LOOKUPS as f64
+    );
+}
+
+fn main() {
+    time_lookups("gradient", &Background::default());
+    time_lookups("solid", &Background::SolidColor(Vec3::new(0.2, 0.2, 0.2)));
+
+    // With nothing to hit, every camera ray ends in exactly one background lookup
+    let camera = Camera::new()
+        .with_resolution(200, 100)
+        .with_samples(10)
+        .with_seed(1);
+    let (_, stats) = camera.render_with_stats(&HittableList::new(), 1).unwrap();
+    let misses: u64 = 200 * 100 * 10;
+    println!(
+        "empty scene: {} misses in {:.1} ms",
+        misses,
+        stats.elapsed.as_secs_f64() * 1e3
+    );
+    #[cfg(feature = "stats")]
+    assert_eq!(stats.rays_cast, misses);
+}
@@ -0,0 +1,64 @@
+// Ray-sphere throughput on a field of spheres. Run with `cargo bench --bench sphere`
+// and again with `--features simd` to compare the two Vec3 layouts.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use ray_trace_rs::interval::Interval;
+use ray_trace_rs::material::{Lambertian, Material};
+use ray_trace_rs::vec3::Vec3;
+use ray_trace_rs::world::{HitResult, Hittable, Ray, Sphere};
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::Instant;
+
+const SPHERES: usize = 100;
+const RAYS: usize = 200_000;
+const RUNS: usize = 3;
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let material: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    let spheres: Vec<Sphere> = (0..SPHERES)
+        .map(|_| {
+            let center = Vec3::new(
+                rng.random_range(-10.0..10.0),
+                rng.random_range(-10.0..10.0),
+                rng.random_range(-30.0..-5.0),
+            );
+            Sphere::new(center, rng.random_range(0.2..1.5), &material)
+        })
+        .collect();
+    let rays: Vec<Ray> = (0..RAYS)
+        .map(|_| {
+            let dir = Vec3::new(
+                rng.random_range(-0.5..0.5),
+                rng.random_range(-0.5..0.5),
+                -1.0,
+            );
+            Ray::new(Vec3::EMPTY, dir)
+        })
+        .collect();
+
+    println!(
+        "Vec3 is {} bytes, aligned to {}",
+        std::mem::size_of::<Vec3>(),
+        std::mem::align_of::<Vec3>()
+    );
+    for run in 0..RUNS {
+        let start = Instant::now();
+        let mut hits = 0;
+        for ray in &rays {
+            for sphere in &spheres {
+                if let HitResult::Hit(_) = sphere.hit(black_box(ray), Interval::ALMOST_FORWARD) {
+                    hits += 1;
+                }
+            }
+        }
+        black_box(hits);
+        let tests = (RAYS * SPHERES) as f64;
+        println!(
+            "run {}: {:.1} M ray-sphere tests/s",
+            run + 1,
+            tests / start.elapsed().as_secs_f64() / 1e6
+        );
+    }
+}
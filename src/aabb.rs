@@ -0,0 +1,107 @@
+use crate::interval::Interval;
+use crate::vec3::Vec3;
+use crate::world::Ray;
+
+// Axis-aligned bounding box, one interval per axis
+#[derive(Copy, Clone)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+impl Aabb {
+    pub const fn new(x: Interval, y: Interval, z: Interval) -> Self {
+        return Self { x, y, z };
+    }
+
+    // Box with `a` and `b` as opposite corners, in any order
+    pub fn from_points(a: Vec3, b: Vec3) -> Self {
+        return Self::new(
+            Interval::from_bounds(a.x(), b.x()),
+            Interval::from_bounds(a.y(), b.y()),
+            Interval::from_bounds(a.z(), b.z()),
+        );
+    }
+
+    // Smallest box enclosing both boxes
+    pub fn surrounding(a: &Aabb, b: &Aabb) -> Self {
+        return Self::new(
+            Interval::union(&a.x, &b.x),
+            Interval::union(&a.y, &b.y),
+            Interval::union(&a.z, &b.z),
+        );
+    }
+
+    pub fn axis_interval(&self, axis: usize) -> &Interval {
+        return match axis {
+            0 => &self.x,
+            1 => &self.y,
+            _ => &self.z,
+        };
+    }
+
+    // Copy with every axis at least a small width, so flat primitives still have a
+    // volume the slab test can hit
+    pub fn padded(&self) -> Self {
+        let delta = 0.0001;
+        let pad = |i: Interval| {
+            return if i.size() < delta { i.expand(delta) } else { i };
+        };
+        return Self::new(pad(self.x), pad(self.y), pad(self.z));
+    }
+
+    // Same box moved by `offset`
+    pub fn translated(&self, offset: Vec3) -> Self {
+        let shift = |i: Interval, d: f64| Interval::new(i.min + d, i.max + d);
+        return Self::new(
+            shift(self.x, offset.x()),
+            shift(self.y, offset.y()),
+            shift(self.z, offset.z()),
+        );
+    }
+
+    // Index of the axis along which the box is widest
+    pub fn longest_axis(&self) -> usize {
+        let (x, y, z) = (self.x.size(), self.y.size(), self.z.size());
+        return if x > y && x > z {
+            0
+        } else if y > z {
+            1
+        } else {
+            2
+        };
+    }
+
+    // Slab test: whether the ray passes through the box within `interval`
+    pub fn hit(&self, ray: &Ray, interval: Interval) -> bool {
+        let mut t_min = interval.min;
+        let mut t_max = interval.max;
+
+        for axis in 0..3 {
+            let ax = self.axis_interval(axis);
+            let inv_d = 1.0 / ray.dir[axis];
+
+            let t0 = (ax.min - ray.origin[axis]) * inv_d;
+            let t1 = (ax.max - ray.origin[axis]) * inv_d;
+
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    // Unbounded primitives such as planes have infinite extent along some axis
+    pub fn is_infinite(&self) -> bool {
+        return [self.x, self.y, self.z]
+            .iter()
+            .any(|i| i.min.is_infinite() || i.max.is_infinite());
+    }
+
+    pub const EMPTY: Aabb = Aabb::new(Interval::EMPTY, Interval::EMPTY, Interval::EMPTY);
+    pub const UNIVERSE: Aabb = Aabb::new(Interval::MAX, Interval::MAX, Interval::MAX);
+}
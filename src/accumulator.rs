@@ -0,0 +1,152 @@
+use crate::buffer::PixelBuffer;
+use crate::vec3::Vec3;
+
+// Running per-pixel radiance sums and sample counts, kept in linear space
+#[derive(Clone)]
+pub struct Accumulator {
+    pub width: i64,
+    pub height: i64,
+    sums: Vec<Vec3>,
+    counts: Vec<i64>,
+}
+
+impl Accumulator {
+    pub fn new(width: i64, height: i64) -> Self {
+        let n = (width * height) as usize;
+        return Self {
+            width,
+            height,
+            sums: vec![Vec3::EMPTY; n],
+            counts: vec![0; n],
+        };
+    }
+
+    fn index(&self, x: i64, y: i64) -> usize {
+        assert!(
+            0 <= x && x < self.width && 0 <= y && y < self.height,
+            "pixel ({}, {}) outside {}x{} accumulator",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        return (y * self.width + x) as usize;
+    }
+
+    pub fn add_sample(&mut self, x: i64, y: i64, color: Vec3) {
+        let i = self.index(x, y);
+        self.sums[i] += color;
+        self.counts[i] += 1;
+    }
+
+    pub fn samples(&self, x: i64, y: i64) -> i64 {
+        return self.counts[self.index(x, y)];
+    }
+
+    // Mean radiance of a pixel, black if it has no samples yet
+    pub fn average(&self, x: i64, y: i64) -> Vec3 {
+        let i = self.index(x, y);
+        return if self.counts[i] > 0 {
+            self.sums[i] / (self.counts[i] as f64)
+        } else {
+            Vec3::EMPTY
+        };
+    }
+
+    // Folds another pass of the same image into this one. Sums and counts add up, so the
+    // result is the sample-weighted average of both passes.
+    pub fn merge(&mut self, other: &Accumulator) {
+        assert!(
+            self.width == other.width && self.height == other.height,
+            "cannot merge a {}x{} accumulator into {}x{}",
+            other.width,
+            other.height,
+            self.width,
+            self.height
+        );
+        for i in 0..self.sums.len() {
+            self.sums[i] += other.sums[i];
+            self.counts[i] += other.counts[i];
+        }
+    }
+
+    // Per-pixel averages as an image
+    pub fn to_buffer(&self) -> PixelBuffer {
+        let mut buf = PixelBuffer::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                buf.set(x, y, self.average(x, y));
+            }
+        }
+        return buf;
+    }
+
+    pub fn to_ppm(&self) -> String {
+        return self.to_buffer().to_ppm();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::material::{Lambertian, Material};
+    use crate::world::{HittableList, Sphere};
+    use std::sync::Arc;
+
+    #[test]
+    fn merge_weights_passes_by_sample_count() {
+        let mut a = Accumulator::new(1, 1);
+        a.add_sample(0, 0, Vec3::new(1.0, 1.0, 1.0));
+        let mut b = Accumulator::new(1, 1);
+        for _ in 0..3 {
+            b.add_sample(0, 0, Vec3::EMPTY);
+        }
+        a.merge(&b);
+        assert_eq!(a.samples(0, 0), 4);
+        let average = a.average(0, 0);
+        assert_eq!((average.x(), average.y(), average.z()), (0.25, 0.25, 0.25));
+    }
+
+    #[test]
+    fn merging_two_half_sample_renders_matches_a_full_render() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, -2.0),
+            1.0,
+            &material,
+        )));
+        // Same view as the default camera, sampled on an 8x8 grid
+        let mut camera = Camera::new();
+        let upper_left = camera.pixel00_loc - (camera.pixel_delta_u + camera.pixel_delta_v) * 0.5;
+        let scale = camera.image_width as f64 / 8.0;
+        camera.image_width = 8;
+        camera.image_height = 8;
+        camera.pixel_delta_u *= scale;
+        camera.pixel_delta_v *= scale;
+        camera.pixel00_loc = upper_left + (camera.pixel_delta_u + camera.pixel_delta_v) * 0.5;
+        camera.max_depth = 4;
+
+        let mut full = Accumulator::new(8, 8);
+        camera.samples_per_pixel = 64;
+        camera.accumulate(&world, &mut full);
+        let mut merged = Accumulator::new(8, 8);
+        camera.samples_per_pixel = 32;
+        for _ in 0..2 {
+            let mut pass = Accumulator::new(8, 8);
+            camera.accumulate(&world, &mut pass);
+            merged.merge(&pass);
+        }
+
+        let mut error = 0.0;
+        for y in 0..8 {
+            for x in 0..8 {
+                assert_eq!(merged.samples(x, y), 64);
+                error += (merged.average(x, y) - full.average(x, y)).length();
+            }
+        }
+        let mean_error = error / 64.0;
+        assert!(mean_error < 0.05, "mean error {}", mean_error);
+    }
+}
@@ -0,0 +1,108 @@
+use crate::texture::{ImageTexture, Texture};
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// What a ray sees when it misses every object
+#[derive(Clone)]
+pub enum Background {
+    SolidColor(Vec3),
+    // Vertical blend from the first color straight down to the second straight up
+    Gradient(Vec3, Vec3),
+    // Equirectangular image wrapped around the scene at infinity
+    Environment(Arc<ImageTexture>),
+}
+
+// Ends of the default sky gradient, straight down and straight up
+pub const SKY_BOTTOM: Vec3 = Vec3::new(1.0, 1.0, 1.0);
+pub const SKY_TOP: Vec3 = Vec3::new(0.5, 0.7, 1.0);
+
+impl Background {
+    // Called for every ray that escapes, so each case does only the work it needs
+    pub fn color(&self, dir: Vec3) -> Vec3 {
+        return match self {
+            Background::SolidColor(color) => *color,
+            Background::Gradient(bottom, top) => {
+                let t = 0.5 * (dir.unit().y() + 1.0);
+                Vec3::lerp(*bottom, *top, t)
+            }
+            Background::Environment(image) => {
+                let unit_dir = dir.unit();
+                let (u, v) = equirect_uv(unit_dir);
+                image.value(u, v, unit_dir)
+            }
+        };
+    }
+}
+
+// The white-to-sky-blue gradient
+impl Default for Background {
+    fn default() -> Self {
+        return Background::Gradient(SKY_BOTTOM, SKY_TOP);
+    }
+}
+
+// Equirectangular coordinates of a unit direction: u is the longitude, starting from -x
+// and turning through +z, and v the latitude, from 0 straight down to 1 straight up
+pub fn equirect_uv(dir: Vec3) -> (f64, f64) {
+    let u = 0.5 + (-dir.z()).atan2(dir.x()) / (2.0 * PI);
+    let v = 0.5 + dir.y().clamp(-1.0, 1.0).asin() / PI;
+    return (u, v);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::PixelBuffer;
+    use crate::texture::WrapMode;
+
+    #[test]
+    fn gradient_follows_the_true_unit_direction() {
+        let (bottom, top) = (Vec3::new(1.0, 1.0, 1.0), Vec3::new(0.5, 0.7, 1.0));
+        let sky = Background::Gradient(bottom, top);
+        // Halfway up the sky the height of the unit direction is 1/sqrt(2), not 1
+        let t = 0.5 * (0.5f64.sqrt() + 1.0);
+        let expected = bottom * (1.0 - t) + top * t;
+        for dir in [Vec3::new(0.0, 1.0, -1.0), Vec3::new(0.0, 5.0, -5.0)] {
+            assert!((sky.color(dir) - expected).length() < 1e-12);
+        }
+        assert_eq!(sky.color(Vec3::new(0.0, 2.0, 0.0)), top);
+        assert_eq!(sky.color(Vec3::new(0.0, -0.5, 0.0)), bottom);
+    }
+
+    #[test]
+    fn environment_maps_straight_up_to_the_top_row() {
+        let (red, blue) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let mut map = PixelBuffer::new(4, 2);
+        for x in 0..4 {
+            map.set(x, 0, red);
+            map.set(x, 1, blue);
+        }
+        let map = ImageTexture::from_buffer(map).with_wrap(WrapMode::RepeatU);
+        let env = Background::Environment(Arc::new(map));
+        assert_eq!(env.color(Vec3::new(0.0, 1.0, 0.0)), red);
+        assert_eq!(env.color(Vec3::new(0.0, -3.0, 0.0)), blue);
+
+        // At the u = 0 seam, straight along -x, the first and last columns blend
+        let mut ring = PixelBuffer::new(4, 1);
+        ring.set(0, 0, red);
+        ring.set(3, 0, blue);
+        let ring = ImageTexture::from_buffer(ring).with_wrap(WrapMode::RepeatU);
+        let env = Background::Environment(Arc::new(ring));
+        assert_eq!(
+            env.color(Vec3::new(-1.0, 0.0, 0.0)),
+            Vec3::new(0.5, 0.0, 0.5)
+        );
+    }
+
+    #[test]
+    fn equirect_uv_covers_longitude_and_latitude() {
+        let close = |(u, v): (f64, f64), (eu, ev): (f64, f64)| {
+            return (u - eu).abs() < 1e-12 && (v - ev).abs() < 1e-12;
+        };
+        assert!(close(equirect_uv(Vec3::new(1.0, 0.0, 0.0)), (0.5, 0.5)));
+        assert!(close(equirect_uv(Vec3::new(0.0, 0.0, 1.0)), (0.25, 0.5)));
+        assert_eq!(equirect_uv(Vec3::new(0.0, 1.0, 0.0)).1, 1.0);
+        assert_eq!(equirect_uv(Vec3::new(0.0, -1.0, 0.0)).1, 0.0);
+    }
+}
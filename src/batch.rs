@@ -0,0 +1,311 @@
+use crate::aabb::Aabb;
+use crate::interval::Interval;
+use crate::stats::{self, Counter};
+use crate::world::{HitRecord, HitResult, Hittable, Ray, Triangle};
+
+// Triangles stored as structure-of-arrays: one contiguous slice per component of the
+// first vertex and of both edges. Testing a ray against all of them then streams through
+// memory in order, and the inner loop has no pointer chasing for the compiler to trip
+// over. Full hit records still come from the matching `Triangle`.
+pub struct TriangleBatch {
+    triangles: Vec<Triangle>,
+    ax: Vec<f64>,
+    ay: Vec<f64>,
+    az: Vec<f64>,
+    e1x: Vec<f64>,
+    e1y: Vec<f64>,
+    e1z: Vec<f64>,
+    e2x: Vec<f64>,
+    e2y: Vec<f64>,
+    e2z: Vec<f64>,
+    cull_backfaces: Vec<bool>,
+    bbox: Aabb,
+}
+
+impl TriangleBatch {
+    pub fn new(triangles: Vec<Triangle>) -> Self {
+        let column = |f: &dyn Fn(&Triangle) -> f64| triangles.iter().map(f).collect::<Vec<f64>>();
+        let bbox = triangles.iter().fold(Aabb::EMPTY, |b, t| {
+            return Aabb::surrounding(&b, &t.bounding_box());
+        });
+        return Self {
+            ax: column(&|t| t.a.x()),
+            ay: column(&|t| t.a.y()),
+            az: column(&|t| t.a.z()),
+            e1x: column(&|t| t.b.x() - t.a.x()),
+            e1y: column(&|t| t.b.y() - t.a.y()),
+            e1z: column(&|t| t.b.z() - t.a.z()),
+            e2x: column(&|t| t.c.x() - t.a.x()),
+            e2y: column(&|t| t.c.y() - t.a.y()),
+            e2z: column(&|t| t.c.z() - t.a.z()),
+            cull_backfaces: triangles.iter().map(|t| t.cull_backfaces).collect(),
+            bbox,
+            triangles,
+        };
+    }
+
+    pub fn triangles(&self) -> &[Triangle] {
+        return &self.triangles;
+    }
+
+    // Splits `triangles` into batches of at most `max_len`, halving them along the longest
+    // axis of their centroids until each half fits, so every batch covers a compact part
+    // of the mesh and makes a tight BVH leaf
+    pub fn grouped(mut triangles: Vec<Triangle>, max_len: usize) -> Vec<Self> {
+        let mut batches = Vec::new();
+        Self::group(&mut triangles, max_len.max(1), &mut batches);
+        return batches;
+    }
+
+    fn group(triangles: &mut [Triangle], max_len: usize, batches: &mut Vec<Self>) {
+        if triangles.len() <= max_len {
+            if !triangles.is_empty() {
+                batches.push(Self::new(triangles.to_vec()));
+            }
+            return;
+        }
+        let centroid = |t: &Triangle| (t.a + t.b + t.c) / 3.0;
+        let bounds = triangles.iter().fold(Aabb::EMPTY, |b, t| {
+            return Aabb::surrounding(&b, &Aabb::from_points(centroid(t), centroid(t)));
+        });
+        let axis = bounds.longest_axis();
+        triangles.sort_by(|a, b| centroid(a)[axis].total_cmp(&centroid(b)[axis]));
+        let (lo, hi) = triangles.split_at_mut(triangles.len() / 2);
+        Self::group(lo, max_len, batches);
+        Self::group(hi, max_len, batches);
+    }
+
+    // Index and distance of the nearest triangle the ray hits within `interval`
+    fn nearest(&self, ray: &Ray, interval: Interval) -> Option<(usize, f64)> {
+        let mut nearest = None;
+        let mut closest = interval.max;
+        stats::record(Counter::RayTriangleTests, self.triangles.len() as u64);
+
+        for i in 0..self.triangles.len() {
+            if let Some(t) = self.intersect(i, ray, interval.min, closest) {
+                closest = t;
+                nearest = Some((i, t));
+            }
+        }
+        return nearest;
+    }
+
+    // Whether the ray hits any triangle within `interval`, stopping at the first one
+    fn any(&self, ray: &Ray, interval: Interval) -> bool {
+        for i in 0..self.triangles.len() {
+            if self.intersect(i, ray, interval.min, interval.max).is_some() {
+                stats::record(Counter::RayTriangleTests, i as u64 + 1);
+                return true;
+            }
+        }
+        stats::record(Counter::RayTriangleTests, self.triangles.len() as u64);
+        return false;
+    }
+
+    // Distance along the ray to triangle `i` if it is hit within [min, max], using
+    // Möller–Trumbore on the component slices
+    #[inline(always)]
+    fn intersect(&self, i: usize, ray: &Ray, min: f64, max: f64) -> Option<f64> {
+        let (ox, oy, oz) = (ray.origin.x(), ray.origin.y(), ray.origin.z());
+        let (dx, dy, dz) = (ray.dir.x(), ray.dir.y(), ray.dir.z());
+        let (e1x, e1y, e1z) = (self.e1x[i], self.e1y[i], self.e1z[i]);
+        let (e2x, e2y, e2z) = (self.e2x[i], self.e2y[i], self.e2z[i]);
+
+        // p = dir x e2
+        let (px, py, pz) = (
+            dy * e2z - dz * e2y,
+            dz * e2x - dx * e2z,
+            dx * e2y - dy * e2x,
+        );
+        let det = e1x * px + e1y * py + e1z * pz;
+        if self.cull_backfaces[i] && det < 0.0 {
+            return None;
+        }
+        if det.abs() < 1e-12 {
+            // Parallel to the triangle
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let (sx, sy, sz) = (ox - self.ax[i], oy - self.ay[i], oz - self.az[i]);
+        let u = (sx * px + sy * py + sz * pz) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        // q = s x e1
+        let (qx, qy, qz) = (
+            sy * e1z - sz * e1y,
+            sz * e1x - sx * e1z,
+            sx * e1y - sy * e1x,
+        );
+        let v = (dx * qx + dy * qy + dz * qz) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = (e2x * qx + e2y * qy + e2z * qz) * inv_det;
+        if t < min || t > max {
+            return None;
+        }
+        return Some(t);
+    }
+}
+
+// Nearest hit among all triangles in `tris` within `interval`. Same result as testing
+// each `Triangle` in turn, but the search runs over the packed component slices.
+pub fn hit_triangles_batch(
+    ray: &Ray,
+    tris: &TriangleBatch,
+    interval: Interval,
+) -> Option<HitRecord> {
+    let (index, _) = tris.nearest(ray, interval)?;
+    return match tris.triangles[index].hit_uncounted(ray, interval) {
+        HitResult::Hit(rec) => Some(rec),
+        HitResult::Miss => None,
+    };
+}
+
+impl Hittable for TriangleBatch {
+    fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
+        return match hit_triangles_batch(ray, self, interval) {
+            Some(rec) => HitResult::Hit(rec),
+            None => HitResult::Miss,
+        };
+    }
+
+    fn hit_any(&self, ray: &Ray, interval: Interval) -> bool {
+        return self.any(ray, interval);
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        return self.bbox;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, Material};
+    use crate::vec3::Vec3;
+    use crate::world::HittableList;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::sync::Arc;
+
+    #[test]
+    fn batch_agrees_with_a_list_of_the_same_triangles() {
+        let gray: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+        let mut rng = StdRng::seed_from_u64(3);
+        let triangles: Vec<Triangle> = (0..50)
+            .map(|_| {
+                let a = Vec3::random_range(&mut rng, -2.0, 2.0);
+                let b = a + Vec3::random_range(&mut rng, -1.0, 1.0);
+                let c = a + Vec3::random_range(&mut rng, -1.0, 1.0);
+                return Triangle::new(a, b, c, &gray);
+            })
+            .collect();
+        let mut list = HittableList::new();
+        for t in &triangles {
+            list.add(Arc::new(t.clone()));
+        }
+        let batch = TriangleBatch::new(triangles);
+
+        let mut hits = 0;
+        for _ in 0..500 {
+            let origin = Vec3::random_range(&mut rng, -4.0, 4.0);
+            let ray = Ray::new(origin, -origin + Vec3::random_range(&mut rng, -1.0, 1.0));
+            match (
+                batch.hit(&ray, Interval::ALMOST_FORWARD),
+                list.hit(&ray, Interval::ALMOST_FORWARD),
+            ) {
+                (HitResult::Hit(a), HitResult::Hit(b)) => {
+                    assert_eq!(a.t, b.t);
+                    assert_eq!(a.normal, b.normal);
+                    hits += 1;
+                }
+                (HitResult::Miss, HitResult::Miss) => {}
+                _ => panic!("batch and list disagree on a ray from {:?}", origin),
+            }
+        }
+        assert!(hits > 0);
+    }
+
+    fn random_point(rng: &mut StdRng) -> Vec3 {
+        return Vec3::new(
+            rng.random_range(-2.0..2.0),
+            rng.random_range(-2.0..2.0),
+            rng.random_range(-6.0..-2.0),
+        );
+    }
+
+    fn random_triangles(rng: &mut StdRng, n: usize) -> Vec<Triangle> {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+        return (0..n)
+            .map(|i| {
+                let a = random_point(rng);
+                let b = a + random_point(rng) * 0.2;
+                let c = a + random_point(rng) * 0.2;
+                let mut triangle = Triangle::new(a, b, c, &material);
+                triangle.cull_backfaces = i % 3 == 0;
+                triangle
+            })
+            .collect();
+    }
+
+    #[test]
+    fn batch_matches_testing_each_triangle() {
+        let mut rng = StdRng::seed_from_u64(9);
+        let triangles = random_triangles(&mut rng, 60);
+        let mut scalar = HittableList::new();
+        for t in &triangles {
+            scalar.add(Arc::new(t.clone()));
+        }
+        let batch = TriangleBatch::new(triangles);
+
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let dir = random_point(&mut rng);
+            let ray = Ray::new(Vec3::new(0.0, 0.0, 1.0), dir);
+            let interval = Interval::new(0.001, rng.random_range(0.5..2.0));
+            let expected = match scalar.hit(&ray, interval) {
+                HitResult::Hit(rec) => Some(rec),
+                HitResult::Miss => None,
+            };
+            match (hit_triangles_batch(&ray, &batch, interval), expected) {
+                (Some(got), Some(want)) => {
+                    assert!((got.t - want.t).abs() < 1e-9);
+                    assert!((got.normal - want.normal).length() < 1e-9);
+                    hits += 1;
+                }
+                (None, None) => {}
+                _ => panic!("batch and scalar disagree on {:?}", dir),
+            }
+            assert_eq!(
+                batch.hit_any(&ray, interval),
+                scalar.hit_any(&ray, interval)
+            );
+        }
+        assert!(hits > 50, "only {} hits", hits);
+    }
+
+    #[test]
+    fn grouped_batches_hold_every_triangle_once() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let triangles = random_triangles(&mut rng, 37);
+        let key = |t: &Triangle| (t.a.x(), t.a.y(), t.a.z());
+        let batches = TriangleBatch::grouped(triangles.clone(), 8);
+        assert!(batches
+            .iter()
+            .all(|b| !b.triangles().is_empty() && b.triangles().len() <= 8));
+
+        let mut grouped: Vec<_> = batches
+            .iter()
+            .flat_map(|b| b.triangles().iter().map(key))
+            .collect();
+        let mut original: Vec<_> = triangles.iter().map(key).collect();
+        grouped.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        original.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(grouped, original);
+    }
+}
@@ -0,0 +1,581 @@
+use crate::util::{
+    exr_io_error, process_rgb16_with, process_rgb_with, srgb_to_linear, write_ppm_with, ToneMap,
+};
+use crate::vec3::Vec3;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Linear RGB float image, stored row-major with three channels per pixel
+#[derive(Clone)]
+pub struct PixelBuffer {
+    pub width: i64,
+    pub height: i64,
+    data: Vec<f64>,
+}
+
+impl PixelBuffer {
+    // Panics if either side is negative
+    pub fn new(width: i64, height: i64) -> Self {
+        assert!(
+            width >= 0 && height >= 0,
+            "buffer size {}x{} is negative",
+            width,
+            height
+        );
+        let len = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|n| n.checked_mul(3))
+            .expect("buffer size overflows usize");
+        return Self {
+            width,
+            height,
+            data: vec![0.0; len],
+        };
+    }
+
+    fn index(&self, x: i64, y: i64) -> usize {
+        assert!(
+            0 <= x && x < self.width && 0 <= y && y < self.height,
+            "pixel ({}, {}) outside {}x{} buffer",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        return ((y * self.width + x) * 3) as usize;
+    }
+
+    pub fn get(&self, x: i64, y: i64) -> Vec3 {
+        let i = self.index(x, y);
+        let mut color = Vec3::EMPTY;
+        for c in 0..3 {
+            color[c] = self.data[i + c];
+        }
+        return color;
+    }
+
+    pub fn set(&mut self, x: i64, y: i64, color: Vec3) {
+        let i = self.index(x, y);
+        for c in 0..3 {
+            self.data[i + c] = color[c];
+        }
+    }
+
+    // Copies all of `rows` into this buffer, starting at scanline `y`
+    pub fn copy_rows(&mut self, y: i64, rows: &PixelBuffer) {
+        assert_eq!(self.width, rows.width, "row width mismatch");
+        let start = self.index(0, y);
+        self.data[start..start + rows.data.len()].copy_from_slice(&rows.data);
+    }
+
+    // Copies all of `src` into this buffer with its top-left corner at (x, y)
+    pub fn paste(&mut self, x: i64, y: i64, src: &PixelBuffer) {
+        assert!(
+            x + src.width <= self.width && y + src.height <= self.height,
+            "{}x{} image at ({}, {}) overflows {}x{} buffer",
+            src.width,
+            src.height,
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        let row_len = (src.width * 3) as usize;
+        if row_len == 0 {
+            return;
+        }
+        for (row, src_row) in src.data.chunks(row_len).enumerate() {
+            let start = self.index(x, y + row as i64);
+            self.data[start..start + row_len].copy_from_slice(src_row);
+        }
+    }
+
+    // Row-major samples, three per pixel, so disjoint runs of rows can be filled in
+    // parallel
+    pub fn samples_mut(&mut self) -> &mut [f64] {
+        return &mut self.data;
+    }
+
+    // Copies this buffer into `rows`, the samples of whole scanlines `width` pixels wide,
+    // with its top-left corner at column `x` of the first
+    pub fn paste_into(&self, rows: &mut [f64], width: i64, x: i64) {
+        assert!(
+            x + self.width <= width && (width * self.height * 3) as usize <= rows.len(),
+            "{}x{} image at column {} overflows rows {} pixels wide",
+            self.width,
+            self.height,
+            x,
+            width
+        );
+        let row_len = (self.width * 3) as usize;
+        if row_len == 0 {
+            return;
+        }
+        for (row, src_row) in self.data.chunks(row_len).enumerate() {
+            let start = ((row as i64 * width + x) * 3) as usize;
+            rows[start..start + row_len].copy_from_slice(src_row);
+        }
+    }
+
+    // Nearest-neighbor copy at `width` x `height`, for showing a small image larger
+    pub fn resized(&self, width: i64, height: i64) -> PixelBuffer {
+        let mut out = PixelBuffer::new(width, height);
+        for y in 0..height {
+            let src_y = (y * self.height / height.max(1)).min(self.height - 1);
+            for x in 0..width {
+                let src_x = (x * self.width / width.max(1)).min(self.width - 1);
+                out.set(x, y, self.get(src_x, src_y));
+            }
+        }
+        return out;
+    }
+
+    // Half the width and height, rounded down but at least 1, with each pixel the mean of
+    // the 2x2 block it covers. An odd last row or column reuses the edge pixels.
+    pub fn downsampled(&self) -> PixelBuffer {
+        let mut half = PixelBuffer::new((self.width / 2).max(1), (self.height / 2).max(1));
+        let (max_x, max_y) = (self.width - 1, self.height - 1);
+        for y in 0..half.height {
+            for x in 0..half.width {
+                let (x0, y0) = (2 * x, 2 * y);
+                let (x1, y1) = ((x0 + 1).min(max_x), (y0 + 1).min(max_y));
+                let sum = self.get(x0, y0) + self.get(x1, y0) + self.get(x0, y1) + self.get(x1, y1);
+                half.set(x, y, sum / 4.0);
+            }
+        }
+        return half;
+    }
+
+    // Gamma-corrected 8-bit RGB bytes, row-major
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        return self.to_rgb8_with(ToneMap::Gamma(2.0));
+    }
+
+    // Like `to_rgb8` with an opaque alpha byte after each pixel, the layout canvas
+    // ImageData expects: row-major, top row first, 4 bytes per pixel in R, G, B, A order
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        return self.to_rgba8_with(ToneMap::Gamma(2.0));
+    }
+
+    pub fn to_rgba8_with(&self, tonemap: ToneMap) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity((self.width * self.height * 4) as usize);
+        for rgb in self.to_rgb8_with(tonemap).chunks(3) {
+            bytes.extend_from_slice(rgb);
+            bytes.push(255);
+        }
+        return bytes;
+    }
+
+    pub fn to_rgb8_with(&self, tonemap: ToneMap) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.data.len());
+        for y in 0..self.height {
+            for x in 0..self.width {
+                bytes.extend_from_slice(&process_rgb_with(self.get(x, y), tonemap));
+            }
+        }
+        return bytes;
+    }
+
+    // Like `to_rgb8_with` at 16 bits per channel
+    pub fn to_rgb16_with(&self, tonemap: ToneMap) -> Vec<u16> {
+        let mut samples = Vec::with_capacity(self.data.len());
+        for y in 0..self.height {
+            for x in 0..self.width {
+                samples.extend_from_slice(&process_rgb16_with(self.get(x, y), tonemap));
+            }
+        }
+        return samples;
+    }
+
+    // Loads a plain (P3) or binary (P6) PPM file. Values are decoded to linear by squaring,
+    // undoing the `Gamma(2.0)` curve `save_ppm` and `save_png` write with, so a saved render
+    // loads back as it was up to 8-bit rounding.
+    pub fn read_ppm(path: impl AsRef<Path>) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        return Self::from_ppm(&bytes).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "not a supported PPM image")
+        });
+    }
+
+    // Loads a PNG or JPEG file through the `image` crate, taking its 8- or 16-bit values
+    // as sRGB-encoded as those formats specify and decoding them to linear
+    pub fn read_image(path: impl AsRef<Path>) -> io::Result<Self> {
+        let image = image::open(path)
+            .map_err(|e| match e {
+                image::ImageError::IoError(e) => e,
+                other => io::Error::new(io::ErrorKind::InvalidData, other),
+            })?
+            .into_rgb32f();
+        let mut buffer = Self::new(image.width() as i64, image.height() as i64);
+        for (value, &sample) in buffer.data.iter_mut().zip(image.as_raw()) {
+            *value = srgb_to_linear(sample as f64);
+        }
+        return Ok(buffer);
+    }
+
+    fn from_ppm(bytes: &[u8]) -> Option<Self> {
+        let mut pos = 0;
+        // Next whitespace-separated header token, skipping `#` comments
+        let mut token = || -> Option<&[u8]> {
+            loop {
+                while bytes.get(pos)?.is_ascii_whitespace() {
+                    pos += 1;
+                }
+                if bytes[pos] != b'#' {
+                    break;
+                }
+                while *bytes.get(pos)? != b'\n' {
+                    pos += 1;
+                }
+            }
+            let start = pos;
+            while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            return Some(&bytes[start..pos]);
+        };
+        let number = |t: &[u8]| std::str::from_utf8(t).ok()?.parse::<u32>().ok();
+
+        let magic = token()?;
+        let width = number(token()?)? as i64;
+        let height = number(token()?)? as i64;
+        let max = number(token()?)?;
+        if width == 0 || height == 0 || max == 0 {
+            return None;
+        }
+
+        let count = (width as usize)
+            .checked_mul(height as usize)?
+            .checked_mul(3)?;
+        let samples: Vec<u32> = match magic {
+            b"P3" => (0..count)
+                .map(|_| number(token()?))
+                .collect::<Option<_>>()?,
+            b"P6" => {
+                // A single whitespace byte separates the header from the raster
+                let raster = bytes.get(pos + 1..)?;
+                if max < 256 {
+                    raster.get(..count)?.iter().map(|&b| b as u32).collect()
+                } else {
+                    raster
+                        .get(..count.checked_mul(2)?)?
+                        .chunks(2)
+                        .map(|b| u16::from_be_bytes([b[0], b[1]]) as u32)
+                        .collect()
+                }
+            }
+            _ => return None,
+        };
+
+        let mut buffer = Self::new(width, height);
+        for (value, sample) in buffer.data.iter_mut().zip(samples) {
+            *value = (sample.min(max) as f64 / max as f64).powi(2);
+        }
+        return Some(buffer);
+    }
+
+    // Loads the first layer of an OpenEXR image, such as `util::write_exr` produces, in
+    // any compression the exr crate reads. It needs R, G and B channels, whose values are
+    // taken as linear, as EXR stores them.
+    pub fn read_exr(path: impl AsRef<Path>) -> io::Result<Self> {
+        let image = exr::prelude::read_first_rgba_layer_from_file(
+            path,
+            |size, _| Self::new(size.width() as i64, size.height() as i64),
+            |buffer, at, (r, g, b, _): (f32, f32, f32, f32)| {
+                let color = Vec3::new(r as f64, g as f64, b as f64);
+                buffer.set(at.x() as i64, at.y() as i64, color);
+            },
+        )
+        .map_err(exr_io_error)?;
+        return Ok(image.layer_data.channel_data.pixels);
+    }
+
+    // Loads a Radiance RGBE (.hdr) image, whose values are already linear and may
+    // exceed 1
+    pub fn read_hdr(path: impl AsRef<Path>) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        return Self::from_hdr(&bytes).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a supported Radiance HDR image",
+            )
+        });
+    }
+
+    fn from_hdr(bytes: &[u8]) -> Option<Self> {
+        let mut pos = 0;
+        let mut line = || -> Option<&str> {
+            let len = bytes.get(pos..)?.iter().position(|&b| b == b'\n')?;
+            let text = std::str::from_utf8(&bytes[pos..pos + len]).ok()?;
+            pos += len + 1;
+            return Some(text);
+        };
+
+        if !line()?.starts_with("#?") {
+            return None;
+        }
+        // Header variables run up to a blank line
+        loop {
+            let text = line()?;
+            if text.is_empty() {
+                break;
+            }
+            if text.starts_with("FORMAT=") && text != "FORMAT=32-bit_rle_rgbe" {
+                return None;
+            }
+        }
+        // Only the standard top-to-bottom, left-to-right orientation
+        let size: Vec<&str> = line()?.split_whitespace().collect();
+        let (height, width) = match size[..] {
+            ["-Y", h, "+X", w] => (h.parse::<i64>().ok()?, w.parse::<i64>().ok()?),
+            _ => return None,
+        };
+        if width <= 0 || height <= 0 {
+            return None;
+        }
+        // Every scanline has to be in the file, so a header claiming more pixels than the
+        // rest of it can hold is malformed. The smallest line is flat RGBE at 4 bytes a
+        // pixel or, for widths that allow run-length encoding, a 4-byte marker then one
+        // 2-byte run per channel for every 127 pixels. Checking before allocating keeps
+        // a short file from claiming gigabytes.
+        let min_line = if (8..0x8000).contains(&width) {
+            4 + 4 * 2 * (width as u64).div_ceil(127)
+        } else {
+            4 * width as u64
+        };
+        if (height as u64).checked_mul(min_line)? > (bytes.len() - pos) as u64 {
+            return None;
+        }
+
+        let mut buffer = Self::new(width, height);
+        let mut rgbe = vec![[0u8; 4]; width as usize];
+        for y in 0..height {
+            pos = Self::read_hdr_scanline(bytes, pos, &mut rgbe)?;
+            for (x, &[r, g, b, e]) in rgbe.iter().enumerate() {
+                let color = if e == 0 {
+                    Vec3::EMPTY
+                } else {
+                    let scale = 2f64.powi(e as i32 - (128 + 8));
+                    Vec3::new(r as f64, g as f64, b as f64) * scale
+                };
+                buffer.set(x as i64, y, color);
+            }
+        }
+        return Some(buffer);
+    }
+
+    // Reads one scanline starting at `pos` and returns the position after it. Lines are
+    // either flat RGBE pixels or, when they start with 2 2, run-length encoded one
+    // channel at a time.
+    fn read_hdr_scanline(bytes: &[u8], mut pos: usize, out: &mut [[u8; 4]]) -> Option<usize> {
+        let width = out.len();
+        let head = bytes.get(pos..pos + 4)?;
+        let encoded = (8..0x8000).contains(&width)
+            && head[0] == 2
+            && head[1] == 2
+            && ((head[2] as usize) << 8 | head[3] as usize) == width;
+
+        if !encoded {
+            for pixel in out.iter_mut() {
+                pixel.copy_from_slice(bytes.get(pos..pos + 4)?);
+                pos += 4;
+            }
+            return Some(pos);
+        }
+
+        pos += 4;
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = *bytes.get(pos)? as usize;
+                pos += 1;
+                if count > 128 {
+                    // Run of one repeated value
+                    let run = count - 128;
+                    let value = *bytes.get(pos)?;
+                    pos += 1;
+                    for pixel in out.get_mut(x..x + run)? {
+                        pixel[channel] = value;
+                    }
+                    x += run;
+                } else {
+                    // Literal values
+                    if count == 0 {
+                        return None;
+                    }
+                    let values = bytes.get(pos..pos + count)?;
+                    pos += count;
+                    for (pixel, &value) in out.get_mut(x..x + count)?.iter_mut().zip(values) {
+                        pixel[channel] = value;
+                    }
+                    x += count;
+                }
+            }
+        }
+        return Some(pos);
+    }
+
+    // Plain-text PPM image
+    pub fn to_ppm(&self) -> String {
+        return self.to_ppm_with(ToneMap::Gamma(2.0));
+    }
+
+    pub fn to_ppm_with(&self, tonemap: ToneMap) -> String {
+        let mut bytes = Vec::new();
+        write_ppm_with(self, tonemap, &mut bytes).expect("writing to a Vec cannot fail");
+        // PPM text is all ASCII
+        return String::from_utf8(bytes).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_round_trip_through_set_and_get() {
+        let mut buf = PixelBuffer::new(3, 2);
+        let pixels = [
+            (0, 0, Vec3::new(0.1, 0.2, 0.3)),
+            (2, 0, Vec3::new(1.0, 0.0, 0.5)),
+            (1, 1, Vec3::new(4.0, 5.0, 6.0)),
+        ];
+        for (x, y, color) in pixels {
+            buf.set(x, y, color);
+        }
+        let channels = |c: Vec3| (c.x(), c.y(), c.z());
+        for (x, y, color) in pixels {
+            assert_eq!(channels(buf.get(x, y)), channels(color));
+        }
+        assert_eq!(channels(buf.get(2, 1)), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn paste_into_fills_the_matching_rows_of_a_band() {
+        let mut tile = PixelBuffer::new(2, 2);
+        tile.set(0, 0, Vec3::new(1.0, 1.0, 1.0));
+        tile.set(1, 1, Vec3::new(2.0, 2.0, 2.0));
+        let mut image = PixelBuffer::new(4, 3);
+        // The band below the first row, as par_chunks_mut would hand it out
+        tile.paste_into(&mut image.samples_mut()[12..], 4, 1);
+
+        let mut pasted = PixelBuffer::new(4, 3);
+        pasted.paste(1, 1, &tile);
+        assert_eq!(image.data, pasted.data);
+    }
+
+    fn hdr(size: &str, pixels: &[u8]) -> Vec<u8> {
+        let mut bytes = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{}\n", size).into_bytes();
+        bytes.extend_from_slice(pixels);
+        return bytes;
+    }
+
+    #[test]
+    fn hdr_reads_flat_and_run_length_encoded_scanlines() {
+        let flat =
+            PixelBuffer::from_hdr(&hdr("-Y 1 +X 2", &[128, 64, 0, 129, 9, 9, 9, 0])).unwrap();
+        assert_eq!(flat.get(0, 0), Vec3::new(1.0, 0.5, 0.0));
+        assert_eq!(flat.get(1, 0), Vec3::EMPTY);
+
+        // One run of 8 per channel
+        let rle = [2, 2, 0, 8, 136, 128, 136, 64, 136, 0, 136, 128];
+        let encoded = PixelBuffer::from_hdr(&hdr("-Y 1 +X 8", &rle)).unwrap();
+        for x in 0..8 {
+            assert_eq!(encoded.get(x, 0), Vec3::new(0.5, 0.25, 0.0));
+        }
+    }
+
+    #[test]
+    fn oversized_hdr_headers_are_rejected_before_allocating() {
+        // A million by a million pixels in a few dozen bytes
+        assert!(PixelBuffer::from_hdr(&hdr("-Y 1000000 +X 1000000", &[2, 2, 0, 8])).is_none());
+        assert!(PixelBuffer::from_hdr(&hdr("-Y 3 +X 2", &[0; 20])).is_none());
+    }
+
+    #[test]
+    fn saved_ppms_read_back_with_the_curve_they_were_written_with() {
+        let mut image = PixelBuffer::new(4, 1);
+        for (x, v) in [0.0, 0.05, 0.25, 0.8].into_iter().enumerate() {
+            image.set(x as i64, 0, Vec3::new(v, v, v));
+        }
+        let read = PixelBuffer::from_ppm(image.to_ppm().as_bytes()).unwrap();
+        for x in 0..4 {
+            let (a, b) = (image.get(x, 0).x(), read.get(x, 0).x());
+            // Within one 8-bit step of the gamma-encoded value
+            assert!((a - b).abs() < 0.01, "{} read back as {}", a, b);
+        }
+    }
+
+    #[test]
+    fn oversized_16_bit_ppm_headers_are_rejected() {
+        // Three samples per pixel fit in a usize, but two bytes per sample do not
+        assert!(PixelBuffer::from_ppm(b"P6 2147483648 2147483648 65535\n\0\0").is_none());
+        assert!(PixelBuffer::from_ppm(b"P6 2 1 65535\n\0\0\0\0").is_none());
+    }
+
+    #[test]
+    fn rgb8_bytes_are_row_major() {
+        let mut buf = PixelBuffer::new(2, 2);
+        buf.set(1, 0, Vec3::new(1.0, 0.0, 0.0));
+        buf.set(0, 1, Vec3::new(0.0, 0.0, 1.0));
+        let bytes = buf.to_rgb8();
+        assert_eq!(bytes.len(), 12);
+        assert_eq!(&bytes[3..6], &[255, 0, 0]);
+        assert_eq!(&bytes[6..9], &[0, 0, 255]);
+    }
+
+    #[test]
+    #[should_panic(expected = "pixel (3, 0) outside 3x2 buffer")]
+    fn get_outside_the_buffer_panics() {
+        PixelBuffer::new(3, 2).get(3, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "buffer size -3x4 is negative")]
+    fn new_rejects_negative_sizes() {
+        PixelBuffer::new(-3, 4);
+    }
+
+    fn temp_exr(name: &str) -> std::path::PathBuf {
+        return std::env::temp_dir().join(format!("{}-{}.exr", name, std::process::id()));
+    }
+
+    #[test]
+    fn written_exr_files_read_back_as_the_same_buffer() {
+        let mut image = PixelBuffer::new(3, 2);
+        // Values past 1 and below it, as a linear render has them, all exact in f32
+        image.set(0, 0, Vec3::new(0.25, 4.0, 1.5));
+        image.set(1, 0, Vec3::new(0.0078125, 16.0, 0.125));
+        image.set(2, 1, Vec3::new(100.0, 0.0, 0.5));
+        let path = temp_exr("round-trip");
+        crate::util::write_exr(&image, &path).unwrap();
+        let read = PixelBuffer::read_exr(&path);
+        fs::remove_file(&path).unwrap();
+
+        let read = read.unwrap();
+        assert_eq!((read.width, read.height), (3, 2));
+        assert_eq!(read.data, image.data);
+    }
+
+    #[test]
+    fn truncated_and_oversized_exr_files_are_rejected() {
+        let path = temp_exr("corrupt");
+        crate::util::write_exr(&PixelBuffer::new(2, 2), &path).unwrap();
+        let exr = fs::read(&path).unwrap();
+
+        let mut oversized = exr.clone();
+        let key = b"dataWindow\0box2i\0";
+        let at = exr.windows(key.len()).position(|w| w == key).unwrap() + key.len() + 4;
+        // A window of 2^31 by 2^31 pixels in a file of a few hundred bytes
+        for (i, v) in [0, 0, i32::MAX - 1, i32::MAX - 1].iter().enumerate() {
+            oversized[at + i * 4..at + i * 4 + 4].copy_from_slice(&v.to_le_bytes());
+        }
+        for bytes in [&exr[..8], &exr[..exr.len() - 1], &oversized[..]] {
+            fs::write(&path, bytes).unwrap();
+            let kind = PixelBuffer::read_exr(&path).map_err(|e| e.kind());
+            assert_eq!(kind.err(), Some(io::ErrorKind::InvalidData));
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,266 @@
+use crate::aabb::Aabb;
+use crate::interval::Interval;
+use crate::world::{HitResult, Hittable, HittableList, Ray};
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+// Bounding volume hierarchy node. Rays skip any subtree whose box they miss.
+// Only a tree over zero or one object has a node with a single child; `right` is then
+// an empty list.
+pub struct BvhNode {
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    pub fn new(list: HittableList) -> Self {
+        // Unbounded objects would inflate every box they touch to infinity, making the
+        // tree useless. Build over the bounded objects and test the rest on every ray.
+        let (mut bounded, unbounded): (Vec<_>, Vec<_>) = list
+            .vec
+            .into_iter()
+            .partition(|o| !o.bounding_box().is_infinite());
+        if bounded.is_empty() || unbounded.is_empty() {
+            bounded.extend(unbounded);
+            return Self::build(&mut bounded);
+        }
+
+        let mut rest = HittableList::new();
+        for o in unbounded {
+            rest.add(o);
+        }
+        return Self {
+            left: Arc::new(Self::build(&mut bounded)),
+            right: Arc::new(rest),
+            bbox: Aabb::UNIVERSE,
+        };
+    }
+
+    // Splits the objects in half along the longest axis of their combined box
+    fn build(objects: &mut [Arc<dyn Hittable>]) -> Self {
+        let bbox = objects
+            .iter()
+            .fold(Aabb::EMPTY, |b, o| Aabb::surrounding(&b, &o.bounding_box()));
+        let empty: Arc<dyn Hittable> = Arc::new(HittableList::new());
+        if objects.len() <= 1 {
+            let left = objects
+                .first()
+                .cloned()
+                .unwrap_or_else(|| Arc::clone(&empty));
+            return Self {
+                left,
+                right: empty,
+                bbox,
+            };
+        }
+
+        let axis = bbox.longest_axis();
+        objects.sort_by(|a, b| Self::box_compare(a, b, axis));
+        let (lo, hi) = objects.split_at_mut(objects.len() / 2);
+        return Self {
+            left: Self::subtree(lo),
+            right: Self::subtree(hi),
+            bbox,
+        };
+    }
+
+    // A lone object is a leaf in itself and needs no node of its own
+    fn subtree(objects: &mut [Arc<dyn Hittable>]) -> Arc<dyn Hittable> {
+        if let [object] = objects {
+            return Arc::clone(object);
+        }
+        return Arc::new(Self::build(objects));
+    }
+
+    fn box_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>, axis: usize) -> Ordering {
+        let a_min = a.bounding_box().axis_interval(axis).min;
+        let b_min = b.bounding_box().axis_interval(axis).min;
+        return a_min.total_cmp(&b_min);
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
+        if !self.bbox.hit(ray, interval) {
+            return HitResult::Miss;
+        }
+
+        let hit_left = self.left.hit(ray, interval);
+        let t_max = match &hit_left {
+            HitResult::Hit(rec) => rec.t,
+            HitResult::Miss => interval.max,
+        };
+        let hit_right = self.right.hit(ray, Interval::new(interval.min, t_max));
+
+        return match hit_right {
+            HitResult::Hit(_) => hit_right,
+            HitResult::Miss => hit_left,
+        };
+    }
+
+    fn hit_any(&self, ray: &Ray, interval: Interval) -> bool {
+        return self.bbox.hit(ray, interval)
+            && (self.left.hit_any(ray, interval) || self.right.hit_any(ray, interval));
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        return self.bbox;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, Material};
+    use crate::vec3::Vec3;
+    use crate::world::{Plane, Sphere, Triangle};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn gray() -> Arc<dyn Material> {
+        return Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    }
+
+    // Spheres and triangles scattered through a box, over a floor plane
+    fn scattered(rng: &mut StdRng) -> HittableList {
+        let material = gray();
+        let mut list = HittableList::new();
+        list.add(Arc::new(Plane::new(
+            Vec3::new(0.0, -10.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            &material,
+        )));
+        for _ in 0..50 {
+            let center = Vec3::random_range(rng, -8.0, 8.0);
+            list.add(Arc::new(Sphere::new(
+                center,
+                rng.random_range(0.1..1.0),
+                &material,
+            )));
+            list.add(Arc::new(Triangle::new(
+                center + Vec3::random_range(rng, -1.0, 1.0),
+                center + Vec3::random_range(rng, -1.0, 1.0),
+                center + Vec3::random_range(rng, -1.0, 1.0),
+                &material,
+            )));
+        }
+        return list;
+    }
+
+    #[test]
+    fn bvh_and_list_return_the_same_hits() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let list = scattered(&mut rng);
+        let bvh = BvhNode::new(list.clone());
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = Vec3::random_range(&mut rng, -12.0, 12.0);
+            let ray = Ray::new(origin, Vec3::random_unit_vector(&mut rng));
+            let interval = Interval::ALMOST_FORWARD;
+            match (list.hit(&ray, interval), bvh.hit(&ray, interval)) {
+                (HitResult::Hit(a), HitResult::Hit(b)) => {
+                    assert_eq!(a.t, b.t);
+                    assert_eq!(a.point, b.point);
+                    hits += 1;
+                }
+                (HitResult::Miss, HitResult::Miss) => {}
+                _ => panic!("BVH and list disagree on {:?}", ray.dir),
+            }
+            assert_eq!(list.hit_any(&ray, interval), bvh.hit_any(&ray, interval));
+        }
+        assert!(hits > 100, "only {} rays hit", hits);
+    }
+
+    #[test]
+    fn list_with_a_bvh_matches_the_plain_list_and_drops_it_on_edit() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let plain = scattered(&mut rng);
+        let mut fast = plain.clone();
+        fast.build_bvh();
+        for _ in 0..500 {
+            let origin = Vec3::random_range(&mut rng, -12.0, 12.0);
+            let ray = Ray::new(origin, Vec3::random_unit_vector(&mut rng));
+            let (a, b) = (
+                plain.hit(&ray, Interval::ALMOST_FORWARD),
+                fast.hit(&ray, Interval::ALMOST_FORWARD),
+            );
+            assert_eq!(
+                matches!(a, HitResult::Hit(_)),
+                matches!(b, HitResult::Hit(_))
+            );
+        }
+
+        // An object added after the build must still be hit
+        let ray = Ray::new(Vec3::new(100.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        fast.add(Arc::new(Sphere::new(
+            Vec3::new(100.0, 0.0, -5.0),
+            1.0,
+            &gray(),
+        )));
+        assert!(matches!(
+            fast.hit(&ray, Interval::ALMOST_FORWARD),
+            HitResult::Hit(_)
+        ));
+    }
+
+    #[test]
+    fn empty_bvh_misses_everything() {
+        let bvh = BvhNode::new(HittableList::new());
+        let ray = Ray::new(Vec3::EMPTY, Vec3::new(0.0, 0.0, -1.0));
+        assert!(!matches!(
+            bvh.hit(&ray, Interval::ALMOST_FORWARD),
+            HitResult::Hit(_)
+        ));
+        assert!(!bvh.hit_any(&ray, Interval::ALMOST_FORWARD));
+    }
+
+    // Counts the hit tests made against the wrapped object
+    struct Counted(Sphere, AtomicUsize);
+
+    impl Hittable for Counted {
+        fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
+            self.1.fetch_add(1, Ordering::Relaxed);
+            return self.0.hit(ray, interval);
+        }
+
+        fn bounding_box(&self) -> Aabb {
+            return self.0.bounding_box();
+        }
+    }
+
+    #[test]
+    fn every_object_is_tested_once_per_ray() {
+        for n in 1..=5 {
+            let objects: Vec<Arc<Counted>> = (0..n)
+                .map(|i| {
+                    let center = Vec3::new(0.0, 0.0, -3.0 * (i + 1) as f64);
+                    Arc::new(Counted(
+                        Sphere::new(center, 1.0, &gray()),
+                        AtomicUsize::new(0),
+                    ))
+                })
+                .collect();
+            let list: HittableList = objects
+                .iter()
+                .map(|o| Arc::clone(o) as Arc<dyn Hittable>)
+                .collect();
+            let bvh = BvhNode::new(list);
+            // Straight down the row, inside every box; far objects may be culled
+            let ray = Ray::new(Vec3::EMPTY, Vec3::new(0.0, 0.0, -1.0));
+            assert!(matches!(
+                bvh.hit(&ray, Interval::ALMOST_FORWARD),
+                HitResult::Hit(_)
+            ));
+            for o in &objects {
+                assert!(
+                    o.1.load(Ordering::Relaxed) <= 1,
+                    "tested twice with {} objects",
+                    n
+                );
+            }
+            assert_eq!(objects[0].1.load(Ordering::Relaxed), 1);
+        }
+    }
+}
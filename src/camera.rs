@@ -1,177 +1,1093 @@
+use crate::accumulator::Accumulator;
+use crate::background::Background;
+use crate::buffer::PixelBuffer;
 use crate::interval::Interval;
 use crate::material::ScatterResult;
-use crate::util::{write_color, write_new_line};
-use crate::vec3::Vec3;
-use crate::world::{HitResult, HittableList, Ray};
-use rand::prelude::ThreadRng;
-use rand::{thread_rng, Rng};
+use crate::pdf::{CosinePdf, HittablePdf, MixturePdf, Pdf};
+use crate::stats::{self, Counter, RenderStats};
+use crate::util::ToneMap;
+use crate::vec3::{Onb, Vec3};
+use crate::world::{HitRecord, HitResult, HittableList, Ray};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Instant;
 
+// Most pixels a camera renders unless given its own limit with `with_max_pixels`. A render
+// holds roughly 30 bytes per pixel between the float image, its tiles and the encoded
+// output, so this keeps one near half a gigabyte, which a browser tab can still hold.
+pub const DEFAULT_MAX_PIXELS: i64 = 4096 * 4096;
+
+// Bounce limit for `Camera::preview`
+pub const PREVIEW_MAX_DEPTH: i64 = 3;
+
+// Overlay color marking in-focus pixels when focus peaking is enabled
+pub const FOCUS_PEAKING_COLOR: Vec3 = Vec3::new(1.0, 0.0, 0.0);
+
+// How sample positions are spread over a pixel
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SamplingStrategy {
+    // Each sample lands anywhere in the pixel, so samples can clump
+    Uniform,
+    // The pixel is split into a sqrt(n) x sqrt(n) grid with one jittered sample per cell,
+    // for the n samples a pixel is sure to take: `samples_per_pixel`, or `min_samples`
+    // with adaptive sampling. Samples beyond the largest square count fall back to
+    // uniform, as do `accumulate_range` and so progressive renders, whose total is open.
+    Stratified,
+}
+
+// Weight given to each sample by its offset from the pixel center, in pixels. Samples
+// stay inside their pixel; the filter only changes how much each one counts.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PixelFilter {
+    // Every sample counts the same
+    Box,
+    // Falls off linearly, reaching zero one pixel from the center
+    Tent,
+    // Falls off with standard deviation `sigma` pixels, which must be positive
+    Gaussian { sigma: f64 },
+}
+
+impl PixelFilter {
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        return match self {
+            PixelFilter::Box => 1.0,
+            PixelFilter::Tent => (1.0 - dx.abs()).max(0.0) * (1.0 - dy.abs()).max(0.0),
+            PixelFilter::Gaussian { sigma } => (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp(),
+        };
+    }
+}
+
+// How camera rays leave the image plane
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    // Rays fan out from the camera center through the viewport, following `vfov`
+    Perspective,
+    // Parallel rays along the view direction, starting across a viewport `scale` world
+    // units tall; `vfov` and defocus have no effect
+    Orthographic { scale: f64 },
+}
+
+// Per-pixel sample bounds for adaptive sampling. A pixel stops once it has `min_samples`
+// and the standard error of its mean color is at most `tolerance` in every channel, or
+// once it reaches `max_samples`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AdaptiveSampling {
+    pub min_samples: i64,
+    pub max_samples: i64,
+    pub tolerance: f64,
+}
+
+// Buffers from one `render_aovs` pass, all at the camera resolution
+pub struct Aovs {
+    // The full path-traced image
+    pub beauty: PixelBuffer,
+    // Unlit base color of the first hit, or the background
+    pub albedo: PixelBuffer,
+    // First-hit normals facing the camera, components in [-1, 1]; zero where nothing is hit
+    pub normal: PixelBuffer,
+    // Distance to the nearest first hit in world units, in every channel; infinite where
+    // nothing is hit
+    pub depth: PixelBuffer,
+}
+
+// Samples and pixels rendered so far, shared by every clone of a camera
+#[derive(Default)]
+struct SampleCounter {
+    samples: AtomicU64,
+    pixels: AtomicU64,
+}
+
+#[derive(Clone)]
 pub struct Camera {
     pub image_height: i64,
     pub image_width: i64,
     pub aspect_ratio: f64,
     pub center: Vec3,
+    pub lookat: Vec3,
+    pub vup: Vec3,
+    // Vertical field of view in degrees
+    pub vfov: f64,
+    pub projection: Projection,
+    // Camera frame: u points right, v up, and w opposite the view direction
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
     pub pixel00_loc: Vec3,
     pub pixel_delta_u: Vec3,
     pub pixel_delta_v: Vec3,
     pub samples_per_pixel: i64,
+    pub sampling: SamplingStrategy,
+    pub filter: PixelFilter,
+    // When set, paths this many bounces long or longer may be cut short at random
+    pub russian_roulette: Option<i64>,
+    // When set, replaces the fixed `samples_per_pixel`
+    pub adaptive: Option<AdaptiveSampling>,
+    sample_counter: Arc<SampleCounter>,
     pub max_depth: i64,
+    // Base seed for every random choice the camera makes. None draws a fresh seed for
+    // each render.
+    pub seed: Option<u64>,
+    // Indices into the world list; when set, camera rays only see these objects
+    pub isolate: Option<Vec<usize>>,
+    // Whether the hidden objects still occlude and reflect bounced rays
+    pub isolate_shadows: bool,
+    // Occlusion rays per camera sample and their maximum length in `render_ao`
+    pub ao_samples: i64,
+    pub ao_radius: f64,
+    // Distance from the camera to the plane of perfect focus
+    pub focus_dist: f64,
+    // Variation angle in degrees of rays through each pixel; 0 gives a pinhole camera
+    pub defocus_angle: f64,
+    pub defocus_disk_u: Vec3,
+    pub defocus_disk_v: Vec3,
+    // Times at which the shutter opens and closes; each camera ray samples a moment in
+    // between, blurring objects that move over that span
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+    // Debug overlay: when set, pixels within this distance of the focus plane are painted
+    // with FOCUS_PEAKING_COLOR instead of being rendered
+    pub focus_peaking: Option<f64>,
+    // Seen by rays that miss everything
+    pub background: Background,
+    // Width and height in pixels of the tiles parallel renders hand out to threads. Values
+    // below 1 are rendered as 1.
+    pub tile_size: i64,
+    // Curve applied when the `render` methods returning PPM text encode the image
+    pub tonemap: ToneMap,
+    // Whether camera rays carry differentials to their first hit, so mipmapped textures
+    // can filter over one pixel's footprint. Off by default as it adds work to every
+    // camera ray; without it textures sample their full-size image.
+    pub ray_differentials: bool,
+    // Largest width times height `check_size` accepts
+    pub max_pixels: i64,
 }
 
 impl Camera {
-    // Define and return a generic camera
+    // Define and return a generic camera at the origin looking down -Z
     pub fn new() -> Self {
+        return Self::look_at(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            1.0,
+        );
+    }
+
+    // Camera at `lookfrom` facing `lookat`, with `vup` giving the up direction and a
+    // vertical field of view of `vfov_degrees`. The image is 512 pixels wide.
+    pub fn look_at(
+        lookfrom: Vec3,
+        lookat: Vec3,
+        vup: Vec3,
+        vfov_degrees: f64,
+        aspect_ratio: f64,
+    ) -> Self {
+        let image_width = 512;
+        let image_height = ((image_width as f64 / aspect_ratio) as i64).max(1);
         let mut cam = Camera {
-            image_height: 512,
-            image_width: 512,
-            aspect_ratio: 1.0,
-            center: Vec3::new(0.0, 0.0, 0.0),
+            image_height,
+            image_width,
+            aspect_ratio,
+            center: lookfrom,
+            lookat,
+            vup,
+            vfov: vfov_degrees,
+            projection: Projection::Perspective,
+            u: Vec3::EMPTY,
+            v: Vec3::EMPTY,
+            w: Vec3::EMPTY,
             pixel00_loc: Vec3::new(0.0, 0.0, 0.0),
             pixel_delta_u: Vec3::new(0.0, 0.0, 0.0),
             pixel_delta_v: Vec3::new(0.0, 0.0, 0.0),
             samples_per_pixel: 10,
+            sampling: SamplingStrategy::Uniform,
+            filter: PixelFilter::Box,
+            russian_roulette: None,
+            adaptive: None,
+            sample_counter: Arc::default(),
             max_depth: 10,
+            seed: None,
+            isolate: None,
+            isolate_shadows: false,
+            ao_samples: 16,
+            ao_radius: 1.0,
+            focus_dist: (lookfrom - lookat).length(),
+            defocus_angle: 0.0,
+            defocus_disk_u: Vec3::EMPTY,
+            defocus_disk_v: Vec3::EMPTY,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+            focus_peaking: None,
+            background: Background::default(),
+            tile_size: 32,
+            tonemap: ToneMap::Gamma(2.0),
+            ray_differentials: false,
+            max_pixels: DEFAULT_MAX_PIXELS,
+        };
+        cam.initialize();
+
+        return cam;
+    }
+
+    // Renders at `width` x `height` pixels, keeping the vertical field of view
+    pub fn with_resolution(mut self, width: i64, height: i64) -> Self {
+        self.image_width = width;
+        self.image_height = height;
+        self.aspect_ratio = width as f64 / height as f64;
+        self.initialize();
+        return self;
+    }
+
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self.initialize();
+        return self;
+    }
+
+    // Thin-lens depth of field: objects `focus_dist` away stay sharp, and the blur grows
+    // with `defocus_angle`, the cone angle in degrees of rays through each pixel
+    pub fn with_defocus(mut self, defocus_angle: f64, focus_dist: f64) -> Self {
+        self.defocus_angle = defocus_angle;
+        self.focus_dist = focus_dist;
+        self.initialize();
+        return self;
+    }
+
+    // Rays averaged per pixel. Values below 1 would divide by zero when averaging, so
+    // they are raised to 1.
+    pub fn with_samples(mut self, samples_per_pixel: i64) -> Self {
+        self.samples_per_pixel = samples_per_pixel.max(1);
+        return self;
+    }
+
+    // Adaptive sampling between `min_samples` and `max_samples` per pixel. The minimum
+    // is raised to 2, since variance needs two samples, and the maximum to the minimum.
+    pub fn with_adaptive(mut self, min_samples: i64, max_samples: i64, tolerance: f64) -> Self {
+        let min_samples = min_samples.max(2);
+        self.adaptive = Some(AdaptiveSampling {
+            min_samples,
+            max_samples: max_samples.max(min_samples),
+            tolerance,
+        });
+        return self;
+    }
+
+    // Mean samples per pixel over everything this camera and its clones have rendered,
+    // or None before any pixel is done
+    pub fn average_samples(&self) -> Option<f64> {
+        let samples = self.sample_counter.samples.load(Ordering::Relaxed);
+        let pixels = self.sample_counter.pixels.load(Ordering::Relaxed);
+        return if pixels == 0 {
+            None
+        } else {
+            Some(samples as f64 / pixels as f64)
+        };
+    }
+
+    // Makes renders reproducible: every pixel draws from a generator seeded by `seed` and
+    // its position, so the same seed gives the same samples however the work is split
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        return self;
+    }
+
+    // Seed shared by all pixels of one render
+    fn render_seed(&self) -> u64 {
+        return self.seed.unwrap_or_else(rand::random);
+    }
+
+    // Generator for pixel (x, y) of a render seeded with `base`
+    fn pixel_rng(base: u64, x: i64, y: i64) -> StdRng {
+        let pixel = ((y as u64) << 32) ^ x as u64;
+        return StdRng::seed_from_u64(base ^ pixel.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    }
+
+    pub fn with_sampling(mut self, sampling: SamplingStrategy) -> Self {
+        self.sampling = sampling;
+        return self;
+    }
+
+    // Russian roulette: from bounce `min_bounces` on, a path survives each scatter with
+    // probability equal to the luminance of its throughput (at least 5%, at most 1), and
+    // survivors are scaled up by 1 / probability. The average stays the same while dim
+    // paths stop early; `max_depth` still caps every path.
+    pub fn with_russian_roulette(mut self, min_bounces: i64) -> Self {
+        self.russian_roulette = Some(min_bounces.max(0));
+        return self;
+    }
+
+    // Weighting of samples within a pixel for `render_pixel`, the renders built on it and
+    // `render_aovs`; `accumulate` and `render_ao` always average evenly
+    pub fn with_filter(mut self, filter: PixelFilter) -> Self {
+        self.filter = filter;
+        return self;
+    }
+
+    // Bounces followed after the first hit; 0 keeps only emission and direct light.
+    // Negative values would render black, so they are raised to 0.
+    pub fn with_max_depth(mut self, max_depth: i64) -> Self {
+        self.max_depth = max_depth.max(0);
+        return self;
+    }
+
+    pub fn with_background(mut self, background: Background) -> Self {
+        self.background = background;
+        return self;
+    }
+
+    // Smaller tiles balance uneven scenes better at the cost of more handoffs. Raised to
+    // at least 1.
+    pub fn with_tile_size(mut self, tile_size: i64) -> Self {
+        self.tile_size = tile_size.max(1);
+        return self;
+    }
+
+    pub fn with_tonemap(mut self, tonemap: ToneMap) -> Self {
+        self.tonemap = tonemap;
+        return self;
+    }
+
+    // Display gamma for the encoded image, which must be positive; 1.0 writes linear
+    // values unchanged
+    pub fn with_gamma(self, gamma: f64) -> Self {
+        return self.with_tonemap(ToneMap::Gamma(gamma));
+    }
+
+    // Pixel budget enforced by `check_size` and every render
+    pub fn with_max_pixels(mut self, max_pixels: i64) -> Self {
+        self.max_pixels = max_pixels;
+        return self;
+    }
+
+    pub fn with_ray_differentials(mut self, enabled: bool) -> Self {
+        self.ray_differentials = enabled;
+        return self;
+    }
+
+    // Shutter interval, in the same time units as moving objects; the bounds may be
+    // given in either order
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Self {
+        self.shutter_open = open.min(close);
+        self.shutter_close = open.max(close);
+        return self;
+    }
+
+    // Recomputes the camera frame and viewport from the view parameters and resolution
+    fn initialize(&mut self) {
+        // The viewport sits on the focus plane, which leaves pinhole rays unchanged
+        let vh = match self.projection {
+            Projection::Perspective => 2.0 * (self.vfov.to_radians() / 2.0).tan() * self.focus_dist,
+            Projection::Orthographic { scale } => scale,
         };
+        let vw = vh * (self.image_width as f64) / (self.image_height as f64);
 
-        let focal_length = 1.0;
-        let vh = 2.0;
-        let vw = vh * (cam.image_width as f64) / (cam.image_height as f64);
+        self.w = (self.center - self.lookat).unit();
+        self.u = Vec3::cross(self.vup, self.w).unit();
+        self.v = Vec3::cross(self.w, self.u);
 
-        let viewport_u = Vec3::new(vw, 0.0, 0.0);
-        let viewport_v = Vec3::new(0.0, -vh, 0.0);
+        let viewport_u = self.u * vw;
+        let viewport_v = -self.v * vh;
 
-        cam.pixel_delta_u = viewport_u / (cam.image_width as f64);
-        cam.pixel_delta_v = viewport_v / (cam.image_height as f64);
+        self.pixel_delta_u = viewport_u / (self.image_width as f64);
+        self.pixel_delta_v = viewport_v / (self.image_height as f64);
 
         let viewport_upper_left =
-            cam.center - Vec3::new(0.0, 0.0, focal_length) - viewport_u / 2.0 - viewport_v / 2.0;
-        cam.pixel00_loc = viewport_upper_left + (cam.pixel_delta_u + cam.pixel_delta_v) * 0.5;
+            self.center - self.w * self.focus_dist - viewport_u / 2.0 - viewport_v / 2.0;
+        self.pixel00_loc = viewport_upper_left + (self.pixel_delta_u + self.pixel_delta_v) * 0.5;
 
-        return cam;
+        let defocus_radius = self.focus_dist * (self.defocus_angle / 2.0).to_radians().tan();
+        self.defocus_disk_u = self.u * defocus_radius;
+        self.defocus_disk_v = self.v * defocus_radius;
+    }
+
+    // Rejects empty or negative resolutions, and ones with more than `max_pixels` pixels,
+    // which would need an unreasonable amount of memory to render
+    pub fn check_size(&self) -> Result<(), String> {
+        let (width, height) = (self.image_width, self.image_height);
+        if width <= 0 || height <= 0 {
+            return Err(format!("image size {}x{} must be positive", width, height));
+        }
+        let pixels = width as i128 * height as i128;
+        if pixels > self.max_pixels as i128 {
+            return Err(format!(
+                "image size {}x{} is {} pixels, over the limit of {}",
+                width, height, pixels, self.max_pixels
+            ));
+        }
+        return Ok(());
+    }
+
+    // Image buffer at the camera resolution, for the render methods. Fails with the
+    // `check_size` message rather than attempting an allocation that size.
+    fn image_buffer(&self) -> Result<PixelBuffer, String> {
+        self.check_size()?;
+        return Ok(PixelBuffer::new(self.image_width, self.image_height));
+    }
+
+    // One-line description of a render, handy for catching expensive settings early
+    pub fn summary(&self, world: &HittableList, threads: i64) -> String {
+        // In i128 like `check_size`, since this runs before the size is checked
+        let pixels = self.image_width as i128 * self.image_height as i128;
+        // Float buffer plus per-thread copies, and roughly 12 bytes per pixel of PPM text
+        let bytes = pixels * 3 * 8 * 2 + pixels * 12;
+        let spp = match self.adaptive {
+            Some(a) => format!("{}-{}", a.min_samples, a.max_samples),
+            None => self.samples_per_pixel.to_string(),
+        };
+        return format!(
+            "{}x{}, {} spp, depth {}, {} primitives, {} lights, {} threads, ~{:.1} MiB",
+            self.image_width,
+            self.image_height,
+            spp,
+            self.max_depth,
+            world.vec.len(),
+            world.lights.len() + world.area_lights.len(),
+            threads,
+            bytes as f64 / (1024.0 * 1024.0)
+        );
     }
 
-    fn ray_color(&self, ray: &Ray, world: &HittableList, depth: i64) -> Vec3 {
+    fn ray_color(
+        &self,
+        ray: &Ray,
+        world: &HittableList,
+        depth: i64,
+        rng: &mut dyn RngCore,
+    ) -> Vec3 {
         if depth < 0 {
             return Vec3::EMPTY;
         }
-        if let HitResult::Hit(hit_record) = world.hit(ray, Interval::ALMOST_FORWARD) {
-            if let ScatterResult::Scatter(scattered, attenuation) =
-                hit_record.material.scatter(&ray, &hit_record)
-            {
-                return attenuation * self.ray_color(&scattered, world, depth - 1);
+        let hit = self.trace(ray, world, depth);
+        return self.shade(ray, hit, world, depth, rng);
+    }
+
+    // Nearest hit for a ray `depth` bounces from the end of its path, honoring `isolate`
+    fn trace(&self, ray: &Ray, world: &HittableList, depth: i64) -> HitResult {
+        return self.nearest_hit(ray, world, depth == self.max_depth);
+    }
+
+    fn nearest_hit(&self, ray: &Ray, world: &HittableList, camera_ray: bool) -> HitResult {
+        stats::record(Counter::RaysCast, 1);
+        return match self.visible_objects(camera_ray) {
+            Some(ids) => world.hit_subset(ray, Interval::ALMOST_FORWARD, ids),
+            None => world.hit(ray, Interval::ALMOST_FORWARD),
+        };
+    }
+
+    // Whether anything blocks a shadow or occlusion ray within `interval`, honoring `isolate`
+    fn occluded(&self, ray: &Ray, world: &HittableList, interval: Interval) -> bool {
+        stats::record(Counter::RaysCast, 1);
+        return match self.visible_objects(false) {
+            Some(ids) => world.hit_any_subset(ray, interval, ids),
+            None => world.hit_any(ray, interval),
+        };
+    }
+
+    // The objects a ray may hit while isolating, or None for the whole world. Camera rays
+    // see only the isolated objects, and so do all other rays unless `isolate_shadows` is set.
+    fn visible_objects(&self, camera_ray: bool) -> Option<&[usize]> {
+        return match &self.isolate {
+            Some(ids) if camera_ray || !self.isolate_shadows => Some(ids),
+            _ => None,
+        };
+    }
+
+    // Light carried back along `ray`, given what it hit. Follows the path one bounce at a
+    // time, weighting what each hit adds by the throughput: the product of the
+    // attenuations between the camera and that hit.
+    fn shade(
+        &self,
+        ray: &Ray,
+        hit: HitResult,
+        world: &HittableList,
+        depth: i64,
+        rng: &mut dyn RngCore,
+    ) -> Vec3 {
+        let mut color = Vec3::EMPTY;
+        let mut throughput = Vec3::ONE;
+        let mut ray = *ray;
+        let mut hit = hit;
+        let mut depth = depth;
+
+        loop {
+            let mut hit_record = match hit {
+                HitResult::Hit(hit_record) => hit_record,
+                HitResult::Miss => return color + throughput * self.background.color(ray.dir),
+            };
+            if depth == self.max_depth && self.ray_differentials {
+                hit_record.footprint = self.footprint(&ray, &hit_record);
             }
+            let emitted =
+                hit_record.material.emitted() + self.direct_light(&ray, &hit_record, world);
+            color += throughput * emitted;
 
-            return Vec3::EMPTY;
+            let scattered = match hit_record.material.scatter(&ray, &hit_record, rng) {
+                ScatterResult::Scatter(scattered, attenuation) => {
+                    throughput = throughput * attenuation;
+                    scattered
+                }
+                ScatterResult::NoScatter => return color,
+            };
+            let scattered = match self.sample_lights(&ray, &hit_record, world, scattered, rng) {
+                Some((scattered, weight)) => {
+                    throughput *= weight;
+                    scattered
+                }
+                None => return color,
+            };
+            if let Some(min_bounces) = self.russian_roulette {
+                if self.max_depth - depth >= min_bounces {
+                    let survival = throughput.luminance().clamp(0.05, 1.0);
+                    if rng.random::<f64>() >= survival {
+                        return color;
+                    }
+                    throughput /= survival;
+                }
+            }
+
+            depth -= 1;
+            if depth < 0 {
+                return color;
+            }
+            ray = scattered;
+            hit = self.trace(&ray, world, depth);
         }
-        let unit_dir = ray.dir
-            / ray
-                .dir
-                .x()
-                .abs()
-                .max(ray.dir.y().abs())
-                .max(ray.dir.z().abs());
-        let t = 0.5 * (unit_dir.y() + 1.0);
-        return Vec3(1.0, 1.0, 1.0) * (1.0 - t) + Vec3(0.5, 0.7, 1.0) * t;
     }
 
-    // Non-parallel
-    pub fn render(&self, world: HittableList) -> String {
-        let mut buf = String::new();
+    // Width of one pixel's worth of surface around a camera ray's hit, from its ray
+    // differentials: the rays one pixel to the right and one pixel down are met with the
+    // tangent plane at the hit, and the farther of the two points from the hit wins. Rays
+    // running along the plane give an infinite footprint. Bounced rays carry no
+    // differentials and keep a footprint of 0.
+    fn footprint(&self, ray: &Ray, hit_record: &HitRecord) -> f64 {
+        let normal = hit_record.normal;
+        let spread = |delta: Vec3| {
+            // Perspective neighbors share the origin and step the direction; orthographic
+            // ones share the direction and step the origin
+            let (origin, dir) = match self.projection {
+                Projection::Perspective => (ray.origin, ray.dir + delta),
+                Projection::Orthographic { .. } => (ray.origin + delta, ray.dir),
+            };
+            let facing = Vec3::dot(dir, normal);
+            if facing.abs() < 1e-12 {
+                return f64::INFINITY;
+            }
+            let t = Vec3::dot(hit_record.point - origin, normal) / facing;
+            return (origin + dir * t - hit_record.point).length();
+        };
+        return spread(self.pixel_delta_u).max(spread(self.pixel_delta_v));
+    }
 
-        buf.push_str(format!("P3\n{} {}\n255\n", self.image_width, self.image_height).as_str());
+    // With area lights registered and a material that reports `scatter_pdf`, replaces the
+    // material's own bounce with one drawn from an even mix of its cosine lobe and the
+    // directions toward the lights, returning it with the factor that reweights the
+    // material's estimate to the mixture. Weighting by the mixed density is the balance
+    // heuristic of multiple importance sampling. Otherwise keeps `scattered` as it is.
+    // None means the new direction contributes nothing and the path can end.
+    fn sample_lights(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        world: &HittableList,
+        scattered: Ray,
+        rng: &mut dyn RngCore,
+    ) -> Option<(Ray, f64)> {
+        let material = &hit_record.material;
+        if world.area_lights.is_empty() || material.scatter_pdf(ray, hit_record, &scattered) <= 0.0
+        {
+            return Some((scattered, 1.0));
+        }
+
+        let lights = HittablePdf::new(&world.area_lights, hit_record.point);
+        let surface = CosinePdf::new(hit_record.normal);
+        let mixture = MixturePdf::new(&lights, &surface);
+        let towards = Ray::with_time(hit_record.point, mixture.generate(rng), ray.time);
+        let density = mixture.value(towards.dir);
+        let scatter_pdf = material.scatter_pdf(ray, hit_record, &towards);
+        // Light samples behind the surface carry nothing
+        if density <= 0.0 || scatter_pdf <= 0.0 {
+            return None;
+        }
+        return Some((towards, scatter_pdf / density));
+    }
 
-        let mut rng = thread_rng();
+    // Light arriving straight from the world's point lights at a diffuse surface,
+    // skipping lights that are behind the surface or occluded
+    fn direct_light(&self, ray: &Ray, hit_record: &HitRecord, world: &HittableList) -> Vec3 {
+        let albedo = match hit_record.material.diffuse_albedo(hit_record) {
+            Some(albedo) => albedo,
+            None => return Vec3::EMPTY,
+        };
+
+        let mut total = Vec3::EMPTY;
+        for light in world.lights.iter() {
+            let to_light = light.position - hit_record.point;
+            let cos_theta = Vec3::dot(hit_record.normal, to_light.unit());
+            if cos_theta <= 0.0 {
+                continue;
+            }
+            // The shadow ray reaches the light at t = 1
+            let shadow_ray = Ray::with_time(hit_record.point, to_light, ray.time);
+            let reach = Interval::new(Interval::ALMOST_FORWARD.min, 1.0);
+            if self.occluded(&shadow_ray, world, reach) {
+                continue;
+            }
+            total += light.color * (light.intensity * cos_theta / to_light.length_squared());
+        }
+
+        // Lambertian BRDF
+        return albedo * total / std::f64::consts::PI;
+    }
+
+    // Non-parallel
+    pub fn render(&self, world: HittableList) -> Result<String, String> {
+        let mut buf = self.image_buffer()?;
+
+        let seed = self.render_seed();
 
         for j in 0..self.image_height {
             for i in 0..self.image_width {
-                let pixel_center = self.pixel00_loc
-                    + (self.pixel_delta_u * (i as f64))
-                    + (self.pixel_delta_v * (j as f64));
+                let mut rng = Self::pixel_rng(seed, i, j);
+                let color = self.render_pixel(&world, &mut rng, i, j);
+                buf.set(i, j, color);
+            }
+        }
+
+        return Ok(buf.to_ppm_with(self.tonemap));
+    }
 
+    // Camera ray for the `sample`th of `count` samples of pixel (i, j), through a jittered
+    // point placed according to the sampling strategy
+    fn get_ray<R: Rng>(&self, rng: &mut R, i: i64, j: i64, sample: i64, count: i64) -> Ray {
+        let (x_noise, y_noise) = self.sample_offset(rng, sample, count);
+        return self.ray_at_offset(rng, i, j, x_noise, y_noise);
+    }
+
+    // Camera ray through pixel (i, j) offset from its center by (x_noise, y_noise) pixels
+    fn ray_at_offset<R: Rng>(
+        &self,
+        rng: &mut R,
+        i: i64,
+        j: i64,
+        x_noise: f64,
+        y_noise: f64,
+    ) -> Ray {
+        stats::record(Counter::PrimaryRays, 1);
+        let pixel_center = self.pixel00_loc
+            + (self.pixel_delta_u * (i as f64))
+            + (self.pixel_delta_v * (j as f64));
+        let new_pixel_center =
+            pixel_center + self.pixel_delta_u * x_noise + self.pixel_delta_v * y_noise;
+        let time = if self.shutter_close > self.shutter_open {
+            rng.random_range(self.shutter_open..self.shutter_close)
+        } else {
+            self.shutter_open
+        };
+        if let Projection::Orthographic { .. } = self.projection {
+            return self.orthographic_ray(new_pixel_center, time);
+        }
+        let origin = if self.defocus_angle <= 0.0 {
+            self.center
+        } else {
+            self.defocus_disk_sample(rng)
+        };
+        let ray_dir = new_pixel_center - origin;
+        return Ray::with_time(origin, ray_dir, time);
+    }
+
+    // Ray along -w that starts on the camera plane and passes through `viewport_point`
+    fn orthographic_ray(&self, viewport_point: Vec3, time: f64) -> Ray {
+        let origin = viewport_point + self.w * self.focus_dist;
+        return Ray::with_time(origin, -self.w, time);
+    }
+
+    // Offset of the `sample`th of `count` samples from the pixel center, each coordinate
+    // in [-0.5, 0.5). The stratification grid is sized for `count`; 0 draws uniformly.
+    fn sample_offset<R: Rng>(&self, rng: &mut R, sample: i64, count: i64) -> (f64, f64) {
+        let grid = (count.max(0) as f64).sqrt() as i64;
+        if self.sampling == SamplingStrategy::Stratified && sample < grid * grid {
+            let (cell_x, cell_y) = (sample % grid, sample / grid);
+            let x = (cell_x as f64 + rng.random::<f64>()) / grid as f64;
+            let y = (cell_y as f64 + rng.random::<f64>()) / grid as f64;
+            return (x - 0.5, y - 0.5);
+        }
+        return (rng.random_range(-0.5..0.5), rng.random_range(-0.5..0.5));
+    }
+
+    // Random point on the camera's defocus disk
+    fn defocus_disk_sample<R: Rng>(&self, rng: &mut R) -> Vec3 {
+        let p = Vec3::random_in_unit_disk(rng);
+        return self.center + self.defocus_disk_u * p.x() + self.defocus_disk_v * p.y();
+    }
+
+    // Color of the `sample`th of `count` camera samples of pixel (i, j) and its filter
+    // weight
+    fn filtered_sample<R: Rng>(
+        &self,
+        world: &HittableList,
+        rng: &mut R,
+        i: i64,
+        j: i64,
+        sample: i64,
+        count: i64,
+    ) -> (Vec3, f64) {
+        let (x_noise, y_noise) = self.sample_offset(rng, sample, count);
+        let ray = self.ray_at_offset(rng, i, j, x_noise, y_noise);
+        let color = self.ray_color(&ray, world, self.max_depth, rng);
+        return (color, self.filter.weight(x_noise, y_noise));
+    }
+
+    pub fn render_pixel<R: Rng>(&self, world: &HittableList, rng: &mut R, i: i64, j: i64) -> Vec3 {
+        if self.in_focus(world, i, j) {
+            return FOCUS_PEAKING_COLOR;
+        }
+
+        let (color, samples) = match self.adaptive {
+            Some(adaptive) => self.adaptive_pixel(world, rng, i, j, adaptive),
+            None => {
                 let mut color = Vec3::new(0.0, 0.0, 0.0);
+                let mut total_weight = 0.0;
                 for s in 0..self.samples_per_pixel {
-                    let x_noise = rng.gen_range(-0.5..0.5);
-                    let y_noise = rng.gen_range(-0.5..0.5);
-                    let new_pixel_center =
-                        pixel_center + self.pixel_delta_u * x_noise + self.pixel_delta_v * y_noise;
-                    let ray_dir = new_pixel_center - self.center;
-                    let ray = Ray {
-                        origin: self.center,
-                        dir: ray_dir,
-                    };
-                    color = color + self.ray_color(&ray, &world, self.max_depth);
+                    let (sample, weight) =
+                        self.filtered_sample(world, rng, i, j, s, self.samples_per_pixel);
+                    color += sample * weight;
+                    total_weight += weight;
                 }
+                let mean = if total_weight > 0.0 {
+                    color / total_weight
+                } else {
+                    Vec3::EMPTY
+                };
+                (mean, self.samples_per_pixel)
+            }
+        };
+
+        self.sample_counter
+            .samples
+            .fetch_add(samples as u64, Ordering::Relaxed);
+        self.sample_counter.pixels.fetch_add(1, Ordering::Relaxed);
+        return color;
+    }
 
-                write_color(&mut buf, color / (self.samples_per_pixel as f64));
+    // Mean color of pixel (i, j) and the number of samples it took. Keeps a running
+    // filter-weighted per-channel mean and variance (West's weighted form of Welford's
+    // method) and stops as described on `AdaptiveSampling`.
+    fn adaptive_pixel<R: Rng>(
+        &self,
+        world: &HittableList,
+        rng: &mut R,
+        i: i64,
+        j: i64,
+        adaptive: AdaptiveSampling,
+    ) -> (Vec3, i64) {
+        let mut mean = Vec3::EMPTY;
+        let mut m2 = Vec3::EMPTY;
+        let mut n = 0;
+        let mut total_weight = 0.0;
+        let mut total_weight_sq = 0.0;
+
+        while n < adaptive.max_samples {
+            let (color, weight) = self.filtered_sample(world, rng, i, j, n, adaptive.min_samples);
+            n += 1;
+            if weight <= 0.0 {
+                continue;
+            }
+            total_weight += weight;
+            total_weight_sq += weight * weight;
+            let delta = color - mean;
+            mean += delta * weight / total_weight;
+            m2 += delta * (color - mean) * weight;
+
+            if n >= adaptive.min_samples && n > 1 {
+                // Variance of the mean is the sample variance over the effective sample
+                // count W^2 / sum(w^2); with equal weights this is variance / n
+                let variance = m2.max_component() / total_weight * n as f64 / (n - 1) as f64;
+                let worst = variance * total_weight_sq / (total_weight * total_weight);
+                if worst.sqrt() <= adaptive.tolerance {
+                    break;
+                }
             }
-            write_new_line(&mut buf);
         }
 
-        return buf;
+        return (mean, n);
     }
 
-    pub fn render_pixel(&self, world: &HittableList, rng: &mut ThreadRng, i: i64, j: i64) -> Vec3 {
+    // With focus peaking on, whether the surface seen through the center of pixel (i, j)
+    // lies within the tolerance of `focus_dist`, measured along the view direction
+    fn in_focus(&self, world: &HittableList, i: i64, j: i64) -> bool {
+        let tolerance = match self.focus_peaking {
+            Some(tolerance) => tolerance,
+            None => return false,
+        };
+        let ray = self.center_ray(i, j);
+        if let HitResult::Hit(hit_record) = self.nearest_hit(&ray, world, true) {
+            let depth = Vec3::dot(hit_record.point - self.center, -self.w);
+            return (depth - self.focus_dist).abs() <= tolerance;
+        }
+        return false;
+    }
+
+    // Pinhole ray through the exact center of pixel (i, j) at the moment the shutter opens
+    fn center_ray(&self, i: i64, j: i64) -> Ray {
         let pixel_center = self.pixel00_loc
             + (self.pixel_delta_u * (i as f64))
             + (self.pixel_delta_v * (j as f64));
+        return match self.projection {
+            Projection::Perspective => {
+                Ray::with_time(self.center, pixel_center - self.center, self.shutter_open)
+            }
+            Projection::Orthographic { .. } => {
+                self.orthographic_ray(pixel_center, self.shutter_open)
+            }
+        };
+    }
 
-        let mut color = Vec3::new(0.0, 0.0, 0.0);
-        for s in 0..self.samples_per_pixel {
-            let x_noise = rng.gen_range(-0.5..0.5);
-            let y_noise = rng.gen_range(-0.5..0.5);
-            let new_pixel_center =
-                pixel_center + self.pixel_delta_u * x_noise + self.pixel_delta_v * y_noise;
-            let ray_dir = new_pixel_center - self.center;
-            let ray = Ray {
-                origin: self.center,
-                dir: ray_dir,
-            };
-            color = color + self.ray_color(&ray, &world, self.max_depth);
+    // Debug image colored by `shade` from what the center ray of each pixel hits first,
+    // with no sampling noise and no bounces
+    fn render_first_hit(
+        &self,
+        world: &HittableList,
+        shade: impl Fn(&Ray, Option<&HitRecord>) -> Vec3,
+    ) -> Result<PixelBuffer, String> {
+        let mut buf = self.image_buffer()?;
+        for j in 0..self.image_height {
+            for i in 0..self.image_width {
+                let ray = self.center_ray(i, j);
+                let color = match self.nearest_hit(&ray, world, true) {
+                    HitResult::Hit(hit_record) => shade(&ray, Some(&hit_record)),
+                    HitResult::Miss => shade(&ray, None),
+                };
+                buf.set(i, j, color);
+            }
         }
+        return Ok(buf);
+    }
+
+    // Surface normals as colors, each component mapped from [-1, 1] to [0, 1]; black where
+    // nothing is hit. Normals face the camera. The values are linear, so save with
+    // `ToneMap::Linear` to read them back exactly.
+    pub fn render_normals(&self, world: &HittableList) -> Result<PixelBuffer, String> {
+        return self.render_first_hit(world, |_, hit| match hit {
+            Some(hit_record) => normal_color(hit_record.normal),
+            None => Vec3::EMPTY,
+        });
+    }
 
-        return color / (self.samples_per_pixel as f64);
+    // Unlit base color of the first hit, or the background where nothing is hit, for
+    // checking textures and UVs without path-tracing noise
+    pub fn render_albedo(&self, world: &HittableList) -> Result<PixelBuffer, String> {
+        return self.render_first_hit(world, |ray, hit| match hit {
+            Some(hit_record) => {
+                hit_record
+                    .material
+                    .albedo(hit_record.u, hit_record.v, hit_record.point)
+            }
+            None => self.background.color(ray.dir),
+        });
     }
 
-    pub fn parallel_render(&self, y_blocks: i64, world: &Arc<HittableList>) -> String {
-        let n = self.image_height * self.image_width * 3;
-        let block_height = self.image_height / y_blocks;
-        let block_size = self.image_width * 3;
+    // Beauty image plus albedo, normal and depth buffers, all from the same primary rays.
+    // Albedo and normals are averaged over a pixel's samples. Uses the fixed sample count
+    // and no focus peaking, so with a seed the beauty buffer matches `render`.
+    pub fn render_aovs(&self, world: &HittableList) -> Result<Aovs, String> {
+        let (width, height) = (self.image_width, self.image_height);
+        let mut aovs = Aovs {
+            beauty: self.image_buffer()?,
+            albedo: self.image_buffer()?,
+            normal: self.image_buffer()?,
+            depth: self.image_buffer()?,
+        };
 
-        let buf = Arc::new(Mutex::new(vec![0.0; n as usize]));
+        let seed = self.render_seed();
+        for j in 0..height {
+            for i in 0..width {
+                let mut rng = Self::pixel_rng(seed, i, j);
+                let (mut beauty, mut albedo, mut normal) = (Vec3::EMPTY, Vec3::EMPTY, Vec3::EMPTY);
+                let mut depth = f64::INFINITY;
+                let mut total_weight = 0.0;
+                for s in 0..self.samples_per_pixel {
+                    let (x_noise, y_noise) =
+                        self.sample_offset(&mut rng, s, self.samples_per_pixel);
+                    let ray = self.ray_at_offset(&mut rng, i, j, x_noise, y_noise);
+                    let weight = self.filter.weight(x_noise, y_noise);
+                    let hit = self.trace(&ray, world, self.max_depth);
+                    match &hit {
+                        HitResult::Hit(hit_record) => {
+                            let base = hit_record.material.albedo(
+                                hit_record.u,
+                                hit_record.v,
+                                hit_record.point,
+                            );
+                            albedo += base * weight;
+                            normal += hit_record.normal * weight;
+                            depth = depth.min(hit_record.t * ray.dir.length());
+                        }
+                        HitResult::Miss => albedo += self.background.color(ray.dir) * weight,
+                    }
+                    beauty += self.shade(&ray, hit, world, self.max_depth, &mut rng) * weight;
+                    total_weight += weight;
+                }
+                let total_weight = if total_weight > 0.0 {
+                    total_weight
+                } else {
+                    1.0
+                };
+                aovs.beauty.set(i, j, beauty / total_weight);
+                aovs.albedo.set(i, j, albedo / total_weight);
+                aovs.normal.set(i, j, normal / total_weight);
+                aovs.depth.set(i, j, Vec3::new(depth, depth, depth));
+            }
+        }
+        return Ok(aovs);
+    }
+
+    // Grayscale distance to the first hit, from black at `near` to white at `far` and
+    // clamped outside that range. Misses count as infinitely far away.
+    pub fn render_depth(
+        &self,
+        world: &HittableList,
+        near: f64,
+        far: f64,
+    ) -> Result<PixelBuffer, String> {
+        return self.render_first_hit(world, |ray, hit| {
+            let d = depth_value(ray, hit, near, far);
+            return Vec3::new(d, d, d);
+        });
+    }
+
+    // Adds `samples_per_pixel` more samples to every pixel of `acc`, which must match the
+    // camera resolution. Passes rendered separately can be combined with `Accumulator::merge`.
+    // With a fixed seed every pass draws the same samples, so give each pass its own.
+    pub fn accumulate(&self, world: &HittableList, acc: &mut Accumulator) {
+        let seed = self.render_seed();
+        for j in 0..self.image_height {
+            for i in 0..self.image_width {
+                let mut rng = Self::pixel_rng(seed, i, j);
+                for s in 0..self.samples_per_pixel {
+                    let ray = self.get_ray(&mut rng, i, j, s, self.samples_per_pixel);
+                    let color = self.ray_color(&ray, world, self.max_depth, &mut rng);
+                    acc.add_sample(i, j, color);
+                }
+            }
+        }
+    }
+
+    // Adds samples `first` through `first + count - 1` of every pixel to `acc`, which must
+    // match the camera resolution. Each sample draws from its own generator, keyed by
+    // `seed`, the pixel and the sample index, so a run split over several calls gives
+    // the same image as one call. That leaves no per-pixel total to stratify over, so
+    // samples are placed uniformly. They are unweighted, as in `accumulate`.
+    pub fn accumulate_range(
+        &self,
+        world: &HittableList,
+        acc: &mut Accumulator,
+        seed: u64,
+        first: i64,
+        count: i64,
+    ) {
+        for j in 0..self.image_height {
+            for i in 0..self.image_width {
+                for s in first..first + count {
+                    let sample_seed = seed ^ (s as u64).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+                    let mut rng = Self::pixel_rng(sample_seed, i, j);
+                    let ray = self.get_ray(&mut rng, i, j, s, 0);
+                    let color = self.ray_color(&ray, world, self.max_depth, &mut rng);
+                    acc.add_sample(i, j, color);
+                }
+            }
+        }
+    }
+
+    // Fraction of cosine-weighted occlusion rays from the first hit that escape `ao_radius`.
+    // The cosine density cancels the cosine term, so the estimate is a plain average.
+    fn ambient_occlusion<R: Rng>(&self, ray: &Ray, world: &HittableList, rng: &mut R) -> f64 {
+        if let HitResult::Hit(hit_record) = self.nearest_hit(ray, world, true) {
+            let basis = Onb::new(hit_record.normal);
+            let mut unoccluded = 0;
+            for _ in 0..self.ao_samples {
+                let dir = basis.transform(Vec3::random_cosine_direction(rng));
+                let ao_ray = Ray::with_time(hit_record.point, dir, ray.time);
+                let reach = Interval::new(Interval::ALMOST_FORWARD.min, self.ao_radius);
+                if !self.occluded(&ao_ray, world, reach) {
+                    unoccluded += 1;
+                }
+            }
+            return unoccluded as f64 / self.ao_samples.max(1) as f64;
+        }
+        return 1.0;
+    }
+
+    // Non-parallel ambient occlusion pass, white where nothing blocks the ambient term
+    pub fn render_ao(&self, world: &HittableList) -> Result<String, String> {
+        let mut buf = self.image_buffer()?;
+
+        let seed = self.render_seed();
+
+        for j in 0..self.image_height {
+            for i in 0..self.image_width {
+                let mut rng = Self::pixel_rng(seed, i, j);
+                let mut ao = 0.0;
+                for s in 0..self.samples_per_pixel {
+                    let ray = self.get_ray(&mut rng, i, j, s, self.samples_per_pixel);
+                    ao += self.ambient_occlusion(&ray, world, &mut rng);
+                }
+                let ao = ao / (self.samples_per_pixel as f64);
+                buf.set(i, j, Vec3::new(ao, ao, ao));
+            }
+        }
+
+        return Ok(buf.to_ppm_with(self.tonemap));
+    }
+
+    // Renders on one thread per horizontal block of rows. Fails for fewer than one block;
+    // more blocks than rows are cut to one per row, so the thread count stays bounded.
+    pub fn parallel_render(
+        &self,
+        y_blocks: i64,
+        world: &Arc<HittableList>,
+    ) -> Result<String, String> {
+        if y_blocks < 1 {
+            return Err(format!("y_blocks must be at least 1, got {}", y_blocks));
+        }
+        let buf = Arc::new(Mutex::new(self.image_buffer()?));
+        let y_blocks = y_blocks.min(self.image_height);
         let world = Arc::clone(world);
+        let seed = self.render_seed();
 
         let mut handles = vec![];
         // iterate over blocks
         for j in 0..y_blocks {
             let buf = Arc::clone(&buf);
             let world = Arc::clone(&world);
-            let block = j;
+            // Spread rows evenly so every scanline belongs to exactly one block, even when
+            // the height is not a multiple of the block count
+            let row_start = j * self.image_height / y_blocks;
+            let row_end = (j + 1) * self.image_height / y_blocks;
+            let block_height = row_end - row_start;
             let width = self.image_width;
+            let camera = self.clone();
             let handle = thread::spawn(move || {
-                let camera = Camera::new();
-
-                let mut rng = thread_rng();
-
-                let q = block_height * block_size;
-                let mut local_buf = vec![0.0; q as usize];
+                if block_height == 0 {
+                    return;
+                }
+                let mut local_buf = PixelBuffer::new(width, block_height);
 
                 // iterate internally on block
                 for y in 0..block_height {
                     for x in 0..width {
-                        let c = camera.render_pixel(&world, &mut rng, x, block * block_height + y);
-                        local_buf[(y * block_size + x * 3) as usize] = c.x();
-                        local_buf[(y * block_size + x * 3 + 1) as usize] = c.y();
-                        local_buf[(y * block_size + x * 3 + 2) as usize] = c.z();
+                        let mut rng = Self::pixel_rng(seed, x, row_start + y);
+                        let c = camera.render_pixel(&world, &mut rng, x, row_start + y);
+                        local_buf.set(x, y, c);
                     }
                 }
 
                 let mut buf = buf.lock().unwrap();
-                // not convinced this will work
-                buf[((block * block_height * block_size) as usize)
-                    ..((((block + 1) * block_height) * block_size) as usize)]
-                    .copy_from_slice(&local_buf);
+                buf.copy_rows(row_start, &local_buf);
             });
             handles.push(handle);
         }
@@ -181,21 +1097,1118 @@ impl Camera {
         }
 
         let buf = buf.lock().unwrap();
+        return Ok(buf.to_ppm_with(self.tonemap));
+    }
 
-        // Unwrapping buffer to a string
-        let mut str_buf: String = String::new();
-        str_buf.push_str(format!("P3\n{} {}\n255\n", self.image_width, self.image_width).as_str());
+    // Renders on every available core. Threads steal tiles from each other, so a thread
+    // that finishes cheap tiles moves on to more instead of idling. Each pixel seeds its
+    // own RNG for camera samples, so the image does not depend on the tiling.
+    pub fn render_parallel(&self, world: &HittableList) -> Result<PixelBuffer, String> {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        return self.render_threads(world, threads);
+    }
 
-        for j in 0..self.image_height {
-            for i in 0..self.image_width {
-                let x = buf[(j * block_size + i * 3) as usize];
-                let y = buf[(j * block_size + i * 3 + 1) as usize];
-                let z = buf[(j * block_size + i * 3 + 2) as usize];
-                write_color(&mut str_buf, Vec3::new(x, y, z));
+    // Quick look for interactive tuning: renders at `scale` times the configured width and
+    // height (rounded, at least one pixel) with one sample per pixel, at most
+    // PREVIEW_MAX_DEPTH bounces and no adaptive sampling. The result is at the reduced
+    // size; `PixelBuffer::resized` blows it back up for display. Does not count towards
+    // `average_samples`.
+    pub fn preview(&self, world: &HittableList, scale: f64) -> Result<PixelBuffer, String> {
+        let size = |n: i64| ((n as f64 * scale).round() as i64).max(1);
+        let mut preview = self
+            .clone()
+            .with_resolution(size(self.image_width), size(self.image_height))
+            .with_samples(1)
+            .with_max_depth(self.max_depth.min(PREVIEW_MAX_DEPTH));
+        preview.adaptive = None;
+        preview.sample_counter = Arc::default();
+        return preview.render_parallel(world);
+    }
+
+    // Like `render_parallel` on exactly `num_threads` worker threads (at least one)
+    pub fn render_threads(
+        &self,
+        world: &HittableList,
+        num_threads: usize,
+    ) -> Result<PixelBuffer, String> {
+        return self.render_tiles(world, num_threads, None, || {});
+    }
+
+    // Like `render_threads`, also returning how long the render took and, with the `stats`
+    // feature, how many rays and intersection tests it needed. The counters are global, so
+    // other renders running at the same time are counted too.
+    pub fn render_with_stats(
+        &self,
+        world: &HittableList,
+        num_threads: usize,
+    ) -> Result<(PixelBuffer, RenderStats), String> {
+        let before = RenderStats::totals();
+        let start = Instant::now();
+        let image = self.render_threads(world, num_threads)?;
+        let stats = RenderStats::totals().since(&before, start.elapsed());
+        return Ok((image, stats));
+    }
+
+    // Like `render_parallel` on `threads` threads, calling `progress` with the fraction of
+    // tiles finished each time one completes. Calls may come from any render thread.
+    pub fn render_with_progress(
+        &self,
+        world: &HittableList,
+        threads: usize,
+        progress: impl Fn(f64) + Send + Sync,
+    ) -> Result<PixelBuffer, String> {
+        let done = AtomicUsize::new(0);
+        let (tiles_x, tiles_y) = self.tile_grid();
+        let total = (tiles_x * tiles_y) as f64;
+        return self.render_tiles(world, threads, None, || {
+            let finished = done.fetch_add(1, Ordering::Relaxed) + 1;
+            progress(finished as f64 / total);
+        });
+    }
+
+    // Like `render_parallel` on `threads` threads, but stops soon after `cancel` is set.
+    // Workers check the flag before every pixel, so a cancelled render returns within
+    // about one pixel's work per thread. The returned image is then partial: pixels not
+    // yet rendered are left black.
+    pub fn render_cancellable(
+        &self,
+        world: &HittableList,
+        threads: usize,
+        cancel: Arc<AtomicBool>,
+    ) -> Result<PixelBuffer, String> {
+        return self.render_tiles(world, threads, Some(&cancel), || {});
+    }
+
+    // `tile_size`, which can be set directly, raised to at least 1 as `with_tile_size` does
+    fn tile_extent(&self) -> i64 {
+        return self.tile_size.max(1);
+    }
+
+    // Number of tile columns and rows covering the image; tiles on the right and bottom
+    // edges may be smaller than `tile_size`
+    fn tile_grid(&self) -> (i64, i64) {
+        let tile = self.tile_extent();
+        return (
+            (self.image_width + tile - 1) / tile,
+            (self.image_height + tile - 1) / tile,
+        );
+    }
+
+    // Like `render_parallel`, but on the calling thread, so `progress` need not be
+    // shareable between threads. Called with the fraction of tiles finished after each one.
+    pub fn render_serial_with_progress(
+        &self,
+        world: &HittableList,
+        mut progress: impl FnMut(f64),
+    ) -> Result<PixelBuffer, String> {
+        let seed = self.render_seed();
+        let (tiles_x, tiles_y) = self.tile_grid();
+        let tile_count = tiles_x * tiles_y;
+        let mut buf = self.image_buffer()?;
+        for index in 0..tile_count {
+            let (x0, y0, tile, _) = self.render_tile(world, seed, index, &|| false);
+            buf.paste(x0, y0, &tile);
+            progress((index + 1) as f64 / tile_count as f64);
+        }
+        return Ok(buf);
+    }
+
+    // Renders tile `index`, counted row-major, and returns its top-left corner, its pixels,
+    // and whether it was finished before `cancelled` returned true
+    fn render_tile(
+        &self,
+        world: &HittableList,
+        seed: u64,
+        index: i64,
+        cancelled: &impl Fn() -> bool,
+    ) -> (i64, i64, PixelBuffer, bool) {
+        let (tiles_x, _) = self.tile_grid();
+        let size = self.tile_extent();
+        let x0 = (index % tiles_x) * size;
+        let y0 = (index / tiles_x) * size;
+        let mut tile = PixelBuffer::new(
+            size.min(self.image_width - x0),
+            size.min(self.image_height - y0),
+        );
+        for y in 0..tile.height {
+            for x in 0..tile.width {
+                if cancelled() {
+                    return (x0, y0, tile, false);
+                }
+                let (i, j) = (x0 + x, y0 + y);
+                let mut rng = Self::pixel_rng(seed, i, j);
+                tile.set(x, y, self.render_pixel(world, &mut rng, i, j));
+            }
+        }
+        return (x0, y0, tile, true);
+    }
+
+    // Splits the image into bands one tile high with rayon's `par_chunks_mut`, so each
+    // band's rows are written without a lock, and renders the tiles within a band in
+    // parallel too. Work stealing keeps every thread busy while tiles differ in cost.
+    // Calls `on_tile` after each finished tile, and stops early once `cancel` is set.
+    fn render_tiles(
+        &self,
+        world: &HittableList,
+        threads: usize,
+        cancel: Option<&AtomicBool>,
+        on_tile: impl Fn() + Sync,
+    ) -> Result<PixelBuffer, String> {
+        let cancelled = || cancel.is_some_and(|c| c.load(Ordering::Relaxed));
+        let seed = self.render_seed();
+        let (tiles_x, tiles_y) = self.tile_grid();
+        let tile_count = tiles_x * tiles_y;
+        let mut buf = self.image_buffer()?;
+
+        // No more threads than tiles, as the rest would have nothing to do. A single thread
+        // renders on the calling thread without a pool, which also suits targets without
+        // threads such as wasm.
+        let threads = threads.min(tile_count as usize);
+        if threads <= 1 {
+            for index in 0..tile_count {
+                let (x0, y0, tile, finished) = self.render_tile(world, seed, index, &cancelled);
+                buf.paste(x0, y0, &tile);
+                if !finished {
+                    break;
+                }
+                on_tile();
+            }
+            return Ok(buf);
+        }
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|e| format!("could not start render threads: {}", e))?;
+        let width = self.image_width;
+        let band_len = (width * self.tile_extent() * 3) as usize;
+        pool.install(|| {
+            buf.samples_mut()
+                .par_chunks_mut(band_len)
+                .enumerate()
+                .for_each(|(band, rows)| {
+                    let tiles: Vec<_> = (0..tiles_x)
+                        .into_par_iter()
+                        .map(|column| {
+                            let index = band as i64 * tiles_x + column;
+                            let (x0, _, tile, finished) =
+                                self.render_tile(world, seed, index, &cancelled);
+                            if finished {
+                                on_tile();
+                            }
+                            return (x0, tile);
+                        })
+                        .collect();
+                    for (x0, tile) in tiles {
+                        tile.paste_into(rows, width, x0);
+                    }
+                });
+        });
+
+        return Ok(buf);
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+// Maps a unit normal onto an RGB color in [0, 1]
+fn normal_color(normal: Vec3) -> Vec3 {
+    return (normal + Vec3::new(1.0, 1.0, 1.0)) * 0.5;
+}
+
+// Distance along `ray` to `hit`, normalized to [near, far] and clamped to [0, 1]
+fn depth_value(ray: &Ray, hit: Option<&HitRecord>, near: f64, far: f64) -> f64 {
+    return match hit {
+        Some(hit_record) => {
+            let distance = hit_record.t * ray.dir.length();
+            ((distance - near) / (far - near)).clamp(0.0, 1.0)
+        }
+        None => 1.0,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::{SKY_BOTTOM, SKY_TOP};
+    use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+    use crate::texture::Texture;
+    use crate::world::{Hittable, Plane, PointLight, Quad, Sphere};
+    use std::time::Instant;
+
+    fn gray() -> Arc<dyn Material> {
+        return Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    }
+
+    #[test]
+    fn empty_scene_shows_the_sky_gradient_endpoints() {
+        let world = HittableList::new();
+        // Narrow views straight up and straight down see only the two ends of the sky
+        for (lookat, vup, expected) in [
+            (Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, -1.0), SKY_TOP),
+            (
+                Vec3::new(0.0, -1.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                SKY_BOTTOM,
+            ),
+        ] {
+            let camera = Camera::look_at(Vec3::EMPTY, lookat, vup, 1.0, 1.0)
+                .with_resolution(2, 2)
+                .with_samples(4)
+                .with_seed(1);
+            let buf = camera.render_threads(&world, 1).unwrap();
+            for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                assert!((buf.get(x, y) - expected).length() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn block_render_fills_the_last_rows_when_blocks_do_not_divide_the_height() {
+        let world = Arc::new(HittableList::new());
+        let mut camera = Camera::new().with_resolution(3, 10);
+        camera.samples_per_pixel = 1;
+        let ppm = camera.parallel_render(4, &world).unwrap();
+        // One line per scanline, three values per pixel
+        let last_row: Vec<u32> = ppm
+            .lines()
+            .last()
+            .unwrap()
+            .split_whitespace()
+            .map(|v| v.parse().unwrap())
+            .collect();
+        assert_eq!(last_row.len(), 9);
+        assert!(last_row.chunks(3).all(|pixel| pixel != [0, 0, 0]));
+    }
+
+    #[test]
+    fn block_render_rejects_fewer_than_one_block() {
+        let world = Arc::new(HittableList::new());
+        let camera = Camera::new().with_resolution(3, 4).with_samples(1);
+        for y_blocks in [0, -2] {
+            assert_eq!(
+                camera.parallel_render(y_blocks, &world),
+                Err(format!("y_blocks must be at least 1, got {}", y_blocks))
+            );
+        }
+    }
+
+    #[test]
+    fn block_render_with_more_blocks_than_rows_matches_one_block() {
+        let world = Arc::new(HittableList::new());
+        let camera = Camera::new()
+            .with_resolution(3, 4)
+            .with_samples(1)
+            .with_seed(1);
+        assert_eq!(
+            camera.parallel_render(1_000_000, &world),
+            camera.parallel_render(1, &world)
+        );
+    }
+
+    #[test]
+    fn cancelled_renders_return_promptly() {
+        let mut camera = Camera::new().with_resolution(1000, 1000);
+        camera.samples_per_pixel = 1000;
+        let cancel = Arc::new(AtomicBool::new(true));
+        let start = Instant::now();
+        let buf = camera
+            .render_cancellable(&HittableList::new(), 2, cancel)
+            .unwrap();
+        assert!(
+            start.elapsed().as_secs_f64() < 5.0,
+            "took {:?}",
+            start.elapsed()
+        );
+        // Nothing was rendered, so the partial image is still at its default
+        assert_eq!((buf.width, buf.height), (1000, 1000));
+        assert_eq!(buf.get(999, 999), Vec3::EMPTY);
+    }
+
+    #[test]
+    fn builders_coerce_invalid_sample_counts_and_depths() {
+        assert_eq!(Camera::new().with_samples(0).samples_per_pixel, 1);
+        assert_eq!(Camera::new().with_samples(-5).samples_per_pixel, 1);
+        assert_eq!(Camera::new().with_max_depth(-3).max_depth, 0);
+
+        let buf = Camera::new()
+            .with_resolution(2, 2)
+            .with_samples(0)
+            .render_parallel(&HittableList::new())
+            .unwrap();
+        let pixel = buf.get(0, 0);
+        assert!(!pixel.x().is_nan(), "zero samples gave {:?}", pixel);
+    }
+
+    // Distinct cells of a `grid` x `grid` split of the pixel hit by samples 0..count
+    fn stratified_cells(camera: &Camera, count: i64, grid: i64) -> usize {
+        let mut rng = Camera::pixel_rng(1, 0, 0);
+        let mut cells: Vec<(i64, i64)> = (0..count)
+            .map(|s| {
+                let (x, y) = camera.sample_offset(&mut rng, s, count);
+                assert!((-0.5..0.5).contains(&x) && (-0.5..0.5).contains(&y));
+                let cell = |v: f64| ((v + 0.5) * grid as f64) as i64;
+                return (cell(x), cell(y));
+            })
+            .collect();
+        cells.sort();
+        cells.dedup();
+        return cells.len();
+    }
+
+    #[test]
+    fn stratified_offsets_fall_in_distinct_subcells() {
+        let camera = Camera::new()
+            .with_samples(16)
+            .with_sampling(SamplingStrategy::Stratified);
+        assert_eq!(stratified_cells(&camera, 16, 4), 16);
+    }
+
+    #[test]
+    fn stratification_grid_follows_the_samples_drawn() {
+        // Adaptive pixels are sure of `min_samples`, whatever `samples_per_pixel` says
+        let camera = Camera::new()
+            .with_samples(100)
+            .with_sampling(SamplingStrategy::Stratified);
+        assert_eq!(stratified_cells(&camera, 4, 2), 4);
+        assert_eq!(stratified_cells(&camera.with_samples(1), 9, 3), 9);
+    }
+
+    #[test]
+    fn adaptive_sampling_stops_early_on_flat_pixels() {
+        let material = gray();
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, -2.0),
+            1.0,
+            &material,
+        )));
+        world.add(Arc::new(Plane::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            &material,
+        )));
+        let camera = Camera::new()
+            .with_resolution(9, 9)
+            .with_background(Background::SolidColor(Vec3::new(0.5, 0.5, 0.5)))
+            .with_adaptive(4, 256, 0.001)
+            .with_max_depth(4);
+        let adaptive = camera.adaptive.unwrap();
+
+        // A top corner pixel sees only the flat background. The center ones see the
+        // sphere, whose diffuse bounces escape or meet the floor. A single pixel can
+        // still settle early by chance, so count over a block of them.
+        let mut rng = rand::rng();
+        let (_, flat) = camera.adaptive_pixel(&world, &mut rng, 0, 0, adaptive);
+        assert_eq!(flat, 4);
+        let mut noisy = 0;
+        for j in 3..6 {
+            for i in 3..6 {
+                noisy += camera.adaptive_pixel(&world, &mut rng, i, j, adaptive).1;
+            }
+        }
+        assert!(noisy > 9 * flat, "noisy pixels took {} samples", noisy);
+
+        camera.render_parallel(&world).unwrap();
+        let average = camera.average_samples().unwrap();
+        assert!(average < 256.0, "averaged {} samples", average);
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel_but_start_apart() {
+        let camera = Camera::new()
+            .with_resolution(4, 4)
+            .with_projection(Projection::Orthographic { scale: 2.0 });
+        let (a, b) = (camera.center_ray(0, 0), camera.center_ray(3, 3));
+        assert_eq!(a.dir, b.dir);
+        assert_eq!(a.dir, Vec3::new(0.0, 0.0, -1.0));
+        // Pixels are half a unit apart on a 2-unit viewport 4 pixels tall
+        assert!((b.origin - a.origin - Vec3::new(1.5, -1.5, 0.0)).length() < 1e-12);
+
+        let mut rng = Camera::pixel_rng(1, 0, 0);
+        let (c, d) = (
+            camera.get_ray(&mut rng, 1, 2, 0, 1),
+            camera.get_ray(&mut rng, 2, 1, 1, 1),
+        );
+        assert_eq!(c.dir, d.dir);
+        assert_ne!(c.origin, d.origin);
+    }
+
+    #[test]
+    fn normals_facing_the_camera_map_to_light_blue() {
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, -3.0),
+            1.0,
+            &gray(),
+        )));
+        let normals = Camera::new()
+            .with_resolution(5, 5)
+            .render_normals(&world)
+            .unwrap();
+        // The center of the sphere faces +z, straight back at the camera
+        assert!((normals.get(2, 2) - Vec3::new(0.5, 0.5, 1.0)).length() < 1e-9);
+        // Nothing is hit in the corners
+        assert_eq!(normals.get(0, 0), Vec3::EMPTY);
+    }
+
+    #[test]
+    fn closer_surfaces_render_darker_in_depth() {
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(-2.0, 0.0, -4.0),
+            1.0,
+            &gray(),
+        )));
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(4.0, 0.0, -8.0),
+            1.0,
+            &gray(),
+        )));
+        let depth = Camera::new()
+            .with_resolution(9, 9)
+            .render_depth(&world, 0.0, 20.0)
+            .unwrap();
+        // Both spheres sit on the left and right thirds of the middle row
+        let (near, far) = (depth.get(2, 4).x(), depth.get(6, 4).x());
+        assert!(
+            0.0 < near && near < far && far < 1.0,
+            "near {} far {}",
+            near,
+            far
+        );
+        // Misses count as infinitely far
+        assert_eq!(depth.get(4, 0).x(), 1.0);
+    }
+
+    #[test]
+    fn albedo_shows_each_material_unlit() {
+        let red = Vec3::new(0.8, 0.1, 0.1);
+        let blue = Vec3::new(0.1, 0.2, 0.9);
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(-2.0, 0.0, -4.0),
+            1.0,
+            &(Arc::new(Lambertian::new(red)) as Arc<dyn Material>),
+        )));
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(2.0, 0.0, -4.0),
+            1.0,
+            &(Arc::new(Metal::new(blue, 0.0)) as Arc<dyn Material>),
+        )));
+        let camera = Camera::new().with_resolution(9, 9);
+        let albedo = camera.render_albedo(&world).unwrap();
+        assert_eq!(albedo.get(2, 4), red);
+        assert_eq!(albedo.get(6, 4), blue);
+        // Misses show the background, as the beauty render would
+        let corner = camera.center_ray(0, 0);
+        assert_eq!(albedo.get(0, 0), camera.background.color(corner.dir));
+    }
+
+    #[test]
+    fn aovs_share_dimensions_and_match_a_serial_render() {
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, -3.0),
+            1.0,
+            &gray(),
+        )));
+        world.add(Arc::new(Plane::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            &gray(),
+        )));
+        let camera = Camera::new()
+            .with_resolution(7, 5)
+            .with_samples(4)
+            .with_seed(3);
+        let aovs = camera.render_aovs(&world).unwrap();
+        for buf in [&aovs.beauty, &aovs.albedo, &aovs.normal, &aovs.depth] {
+            assert_eq!((buf.width, buf.height), (7, 5));
+        }
+
+        let serial = camera.render_parallel(&world).unwrap();
+        for j in 0..5 {
+            for i in 0..7 {
+                assert_eq!(aovs.beauty.get(i, j), serial.get(i, j), "pixel {} {}", i, j);
+            }
+        }
+    }
+
+    #[test]
+    fn one_thread_matches_the_non_parallel_render() {
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, -3.0),
+            1.0,
+            &gray(),
+        )));
+        let camera = Camera::new()
+            .with_resolution(12, 9)
+            .with_samples(3)
+            .with_tile_size(5)
+            .with_seed(2);
+        let one = camera.render_threads(&world, 1).unwrap();
+        assert_eq!(one.to_ppm(), camera.render(world.clone()).unwrap());
+
+        // More threads only change which worker renders each tile
+        let many = camera.render_threads(&world, 4).unwrap();
+        for j in 0..9 {
+            for i in 0..12 {
+                assert_eq!(one.get(i, j), many.get(i, j), "pixel {} {}", i, j);
+            }
+        }
+    }
+
+    #[test]
+    fn workers_are_capped_at_the_tile_count() {
+        let camera = Camera::new()
+            .with_resolution(8, 8)
+            .with_samples(1)
+            .with_seed(1);
+        let world = HittableList::new();
+        // One 32-pixel tile, so this runs as a single worker rather than 100k threads
+        let buf = camera.render_threads(&world, 100_000).unwrap();
+        assert_eq!(buf.to_ppm(), camera.render(world).unwrap());
+    }
+
+    #[test]
+    fn threaded_render_fills_the_last_row_of_an_odd_height() {
+        let camera = Camera::new()
+            .with_resolution(5, 37)
+            .with_samples(1)
+            .with_seed(1);
+        let buf = camera.render_threads(&HittableList::new(), 3).unwrap();
+        for x in 0..5 {
+            assert_ne!(buf.get(x, 36), Vec3::EMPTY);
+        }
+    }
+
+    #[test]
+    fn tent_filter_weights_the_center_most() {
+        let tent = PixelFilter::Tent;
+        assert_eq!(tent.weight(0.0, 0.0), 1.0);
+        // Edges and corners of the pixel, half a pixel out
+        assert_eq!(tent.weight(0.5, 0.0), 0.5);
+        assert_eq!(tent.weight(0.0, -0.5), 0.5);
+        assert_eq!(tent.weight(0.5, 0.5), 0.25);
+        assert!(tent.weight(0.1, 0.1) > tent.weight(0.3, 0.1));
+
+        let gaussian = PixelFilter::Gaussian { sigma: 0.5 };
+        assert_eq!(gaussian.weight(0.0, 0.0), 1.0);
+        assert!(gaussian.weight(0.5, 0.0) < gaussian.weight(0.2, 0.0));
+        assert_eq!(PixelFilter::Box.weight(0.5, 0.5), 1.0);
+    }
+
+    fn mean_color(image: &PixelBuffer) -> Vec3 {
+        let mut total = Vec3::EMPTY;
+        for j in 0..image.height {
+            for i in 0..image.width {
+                total += image.get(i, j);
+            }
+        }
+        return total / (image.width * image.height) as f64;
+    }
+
+    #[test]
+    fn russian_roulette_keeps_the_average_energy() {
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, -2.0),
+            1.0,
+            &(Arc::new(Metal::new(Vec3::new(0.9, 0.9, 0.9), 0.3)) as Arc<dyn Material>),
+        )));
+        world.add(Arc::new(Plane::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            &gray(),
+        )));
+        let camera = Camera::new()
+            .with_resolution(8, 8)
+            .with_samples(200)
+            .with_max_depth(8)
+            .with_seed(4);
+        let plain = mean_color(&camera.render_threads(&world, 1).unwrap());
+        let roulette = camera.clone().with_russian_roulette(0);
+        let culled = mean_color(&roulette.render_threads(&world, 1).unwrap());
+        for c in 0..3 {
+            let relative = (culled[c] - plain[c]).abs() / plain[c];
+            assert!(relative < 0.02, "{:?} vs {:?}", culled, plain);
+        }
+    }
+
+    // The shading recursion as it was before `shade` became a loop, kept as a reference
+    fn recursive_color(
+        camera: &Camera,
+        ray: &Ray,
+        world: &HittableList,
+        depth: i64,
+        rng: &mut dyn RngCore,
+    ) -> Vec3 {
+        if depth < 0 {
+            return Vec3::EMPTY;
+        }
+        let hit_record = match camera.trace(ray, world, depth) {
+            HitResult::Hit(hit_record) => hit_record,
+            HitResult::Miss => return camera.background.color(ray.dir),
+        };
+        let emitted = hit_record.material.emitted() + camera.direct_light(ray, &hit_record, world);
+        let (scattered, attenuation) = match hit_record.material.scatter(ray, &hit_record, rng) {
+            ScatterResult::Scatter(scattered, attenuation) => (scattered, attenuation),
+            ScatterResult::NoScatter => return emitted,
+        };
+        return match camera.sample_lights(ray, &hit_record, world, scattered, rng) {
+            Some((scattered, weight)) => {
+                emitted
+                    + attenuation
+                        * weight
+                        * recursive_color(camera, &scattered, world, depth - 1, rng)
             }
-            write_new_line(&mut str_buf);
+            None => emitted,
+        };
+    }
+
+    #[test]
+    fn iterative_shading_matches_the_recursive_version() {
+        let light: Arc<dyn Material> = Arc::new(DiffuseLight::new(Vec3::new(4.0, 4.0, 4.0)));
+        let panel: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Vec3::new(-0.5, 2.0, -3.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            &light,
+        ));
+        let mut world = HittableList::new();
+        world.add(Arc::clone(&panel));
+        world.add_area_light(panel);
+        world.add_light(PointLight::new(
+            Vec3::new(2.0, 3.0, 0.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            5.0,
+        ));
+        let materials: [Arc<dyn Material>; 3] = [
+            gray(),
+            Arc::new(Metal::new(Vec3::new(0.8, 0.6, 0.2), 0.2)),
+            Arc::new(Dielectric::new(1.5)),
+        ];
+        for (k, material) in materials.iter().enumerate() {
+            world.add(Arc::new(Sphere::new(
+                Vec3::new(k as f64 - 1.0, 0.0, -3.0),
+                0.45,
+                material,
+            )));
         }
+        world.add(Arc::new(Plane::new(
+            Vec3::new(0.0, -0.5, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            &gray(),
+        )));
 
-        return str_buf;
+        let camera = Camera::new().with_resolution(12, 12).with_max_depth(6);
+        for j in 0..12 {
+            for i in 0..12 {
+                let mut rng = Camera::pixel_rng(7, i, j);
+                let ray = camera.get_ray(&mut rng, i, j, 0, 1);
+                let (mut a, mut b) = (Camera::pixel_rng(8, i, j), Camera::pixel_rng(8, i, j));
+                let iterative = camera.ray_color(&ray, &world, camera.max_depth, &mut a);
+                let recursive = recursive_color(&camera, &ray, &world, camera.max_depth, &mut b);
+                assert!(
+                    (iterative - recursive).length() < 1e-9,
+                    "pixel {} {}: {:?} vs {:?}",
+                    i,
+                    j,
+                    iterative,
+                    recursive
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn light_sampling_converges_to_the_brute_force_mean() {
+        let light: Arc<dyn Material> = Arc::new(DiffuseLight::new(Vec3::new(6.0, 6.0, 6.0)));
+        let panel: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Vec3::new(-1.0, 1.0, -3.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            &light,
+        ));
+        let mut brute_force = HittableList::new();
+        brute_force.add(Arc::clone(&panel));
+        brute_force.add(Arc::new(Plane::new(
+            Vec3::new(0.0, -0.5, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            &gray(),
+        )));
+        let mut sampled = brute_force.clone();
+        sampled.add_area_light(panel);
+
+        // Looking straight down at the floor under the panel, from just below it
+        let camera = Camera::look_at(
+            Vec3::new(0.0, 0.5, -2.0),
+            Vec3::new(0.0, -0.5, -2.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            60.0,
+            1.0,
+        )
+        .with_resolution(4, 4)
+        .with_max_depth(3)
+        .with_background(Background::SolidColor(Vec3::EMPTY))
+        .with_seed(2);
+        // Sampling the panel gets as close with a twentieth of the samples
+        let brute = mean_color(
+            &camera
+                .clone()
+                .with_samples(20000)
+                .render_threads(&brute_force, 1)
+                .unwrap(),
+        );
+        let mis = mean_color(
+            &camera
+                .clone()
+                .with_samples(1000)
+                .render_threads(&sampled, 1)
+                .unwrap(),
+        );
+        assert!(
+            (mis.x() - brute.x()).abs() < 0.02 * brute.x(),
+            "{:?} vs {:?}",
+            mis,
+            brute
+        );
+    }
+
+    fn first_hit(world: &HittableList, ray: &Ray) -> HitRecord {
+        return match world.hit(ray, Interval::ALMOST_FORWARD) {
+            HitResult::Hit(hit_record) => hit_record,
+            HitResult::Miss => panic!("ray missed the scene"),
+        };
+    }
+
+    #[test]
+    fn footprint_is_one_pixel_wide_on_a_facing_plane() {
+        let camera = Camera::new()
+            .with_resolution(64, 64)
+            .with_ray_differentials(true);
+        let mut world = HittableList::new();
+        world.add(Arc::new(Plane::new(
+            Vec3::new(0.0, 0.0, -2.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            &gray(),
+        )));
+
+        let ray = camera.center_ray(20, 40);
+        let hit_record = first_hit(&world, &ray);
+        // The viewport is 2 units wide at the focus distance of 1, and the plane is twice
+        // as far away
+        let expected = 2.0 / 64.0 * 2.0;
+        assert!((camera.footprint(&ray, &hit_record) - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn footprint_grows_toward_the_horizon() {
+        let camera = Camera::new()
+            .with_resolution(64, 64)
+            .with_ray_differentials(true);
+        let mut world = HittableList::new();
+        world.add(Arc::new(Plane::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            &gray(),
+        )));
+
+        let footprint = |row: i64| {
+            let ray = camera.center_ray(32, row);
+            return camera.footprint(&ray, &first_hit(&world, &ray));
+        };
+        assert!(footprint(34) > footprint(40));
+        assert!(footprint(40) > footprint(63));
+    }
+
+    #[test]
+    fn check_size_rejects_images_over_the_pixel_limit() {
+        let camera = Camera::new().with_resolution(5000, 4000);
+        assert_eq!(
+            camera.check_size(),
+            Err("image size 5000x4000 is 20000000 pixels, over the limit of 16777216".to_string())
+        );
+        assert_eq!(
+            Camera::new().with_resolution(4096, 4096).check_size(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn check_size_rejects_empty_and_negative_sizes() {
+        for (width, height) in [(0, 10), (10, 0), (-640, 480)] {
+            let camera = Camera::new().with_resolution(width, height);
+            assert_eq!(
+                camera.check_size(),
+                Err(format!("image size {}x{} must be positive", width, height))
+            );
+        }
+    }
+
+    #[test]
+    fn summary_handles_resolutions_over_the_pixel_limit() {
+        let camera = Camera::new().with_resolution(4_000_000_000, 4_000_000_000);
+        let summary = camera.summary(&HittableList::new(), 1);
+        assert!(summary.starts_with("4000000000x4000000000,"));
+        assert!(summary.ends_with("~915527343750000.0 MiB"));
+    }
+
+    #[test]
+    fn summary_counts_area_lights() {
+        let light: Arc<dyn Material> = Arc::new(DiffuseLight::new(Vec3::new(4.0, 4.0, 4.0)));
+        let panel: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Vec3::new(-0.5, 2.0, -3.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            &light,
+        ));
+        let mut world = HittableList::new();
+        world.add(Arc::clone(&panel));
+        world.add_area_light(panel);
+        let summary = Camera::new().summary(&world, 1);
+        assert!(summary.contains("1 primitives, 1 lights"), "{}", summary);
+    }
+
+    #[test]
+    fn pixel_limit_is_configurable() {
+        let camera = Camera::new().with_max_pixels(100);
+        assert!(camera.clone().with_resolution(10, 10).check_size().is_ok());
+        assert!(camera.with_resolution(11, 10).check_size().is_err());
+    }
+
+    #[test]
+    fn renders_refuse_images_over_the_pixel_limit() {
+        let camera = Camera::new().with_resolution(20, 20).with_max_pixels(100);
+        let world = HittableList::new();
+        let message = Some(camera.check_size().unwrap_err());
+        assert!(message.as_ref().unwrap().contains("over the limit of 100"));
+        assert_eq!(camera.render(world.clone()).err(), message);
+        assert_eq!(camera.render_threads(&world, 1).err(), message);
+        assert_eq!(
+            camera.render_serial_with_progress(&world, |_| {}).err(),
+            message
+        );
+        assert_eq!(camera.render_normals(&world).err(), message);
+        assert_eq!(camera.render_aovs(&world).err(), message);
+        assert_eq!(camera.preview(&world, 1.0).err(), message);
+    }
+
+    // Records the largest footprint it is sampled with
+    struct FootprintProbe(AtomicU64);
+
+    impl Texture for FootprintProbe {
+        fn value(&self, _u: f64, _v: f64, _point: Vec3) -> Vec3 {
+            return Vec3::new(0.5, 0.5, 0.5);
+        }
+
+        fn value_lod(&self, u: f64, v: f64, point: Vec3, footprint: f64) -> Vec3 {
+            // Non-negative floats order the same as their bit patterns
+            self.0.fetch_max(footprint.to_bits(), Ordering::Relaxed);
+            return self.value(u, v, point);
+        }
+    }
+
+    fn largest_footprint(ray_differentials: bool) -> f64 {
+        let probe = Arc::new(FootprintProbe(AtomicU64::new(0)));
+        let material: Arc<dyn Material> = Arc::new(Lambertian::textured(probe.clone()));
+        let mut world = HittableList::new();
+        world.add(Arc::new(Plane::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            &material,
+        )));
+        let camera = Camera::new()
+            .with_resolution(8, 8)
+            .with_samples(1)
+            .with_max_depth(1)
+            .with_seed(1)
+            .with_ray_differentials(ray_differentials);
+        camera.render_threads(&world, 1).unwrap();
+        return f64::from_bits(probe.0.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn footprints_reach_textures_only_with_ray_differentials() {
+        assert_eq!(largest_footprint(false), 0.0);
+        assert!(largest_footprint(true) > 0.0);
+    }
+
+    #[test]
+    fn preview_is_scaled_and_rounded() {
+        let world = HittableList::new();
+        let camera = Camera::new().with_resolution(101, 50).with_samples(50);
+        let preview = camera.preview(&world, 0.25).unwrap();
+        // 25.25 and 12.5 round to 25 and 13
+        assert_eq!((preview.width, preview.height), (25, 13));
+        let tiny = camera.preview(&world, 0.001).unwrap();
+        assert_eq!((tiny.width, tiny.height), (1, 1));
+        // Previews do not count towards the camera's own sample statistics
+        assert_eq!(camera.average_samples(), None);
+    }
+
+    // A floor, isolated, under a hidden object that would otherwise block its light
+    fn isolated_floor(blocker: Arc<dyn Hittable>, isolate_shadows: bool) -> (Camera, HittableList) {
+        let mut world = HittableList::new();
+        world.add(Arc::new(Plane::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            &gray(),
+        )));
+        world.add(blocker);
+        let mut camera = Camera::new();
+        camera.isolate = Some(vec![0]);
+        camera.isolate_shadows = isolate_shadows;
+        return (camera, world);
+    }
+
+    #[test]
+    fn hidden_objects_cast_point_light_shadows_only_with_isolate_shadows() {
+        let blocker = Arc::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 0.5, &gray()));
+        let down = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        for isolate_shadows in [false, true] {
+            let (camera, mut world) = isolated_floor(blocker.clone(), isolate_shadows);
+            world.add_light(PointLight::new(
+                Vec3::new(0.0, 3.0, 0.0),
+                Vec3::new(1.0, 1.0, 1.0),
+                10.0,
+            ));
+            let hit_record = first_hit(&world, &down);
+            let light = camera.direct_light(&down, &hit_record, &world);
+            assert_eq!(light == Vec3::EMPTY, isolate_shadows);
+        }
+    }
+
+    #[test]
+    fn hidden_objects_occlude_ambient_light_only_with_isolate_shadows() {
+        // A ceiling just above the floor that the downward camera ray would hit first
+        let ceiling = Arc::new(Plane::new(
+            Vec3::new(0.0, -0.5, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            &gray(),
+        ));
+        let down = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        for (isolate_shadows, expected) in [(false, 1.0), (true, 0.0)] {
+            let (mut camera, world) = isolated_floor(ceiling.clone(), isolate_shadows);
+            // Long enough that even grazing occlusion rays reach the ceiling
+            camera.ao_radius = 1000.0;
+            let mut rng = Camera::pixel_rng(1, 0, 0);
+            assert_eq!(camera.ambient_occlusion(&down, &world, &mut rng), expected);
+        }
+    }
+
+    #[test]
+    fn debug_passes_skip_hidden_objects() {
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, -3.0),
+            1.0,
+            &gray(),
+        )));
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, -6.0),
+            1.0,
+            &gray(),
+        )));
+        let mut camera = Camera::new().with_resolution(5, 5);
+        camera.isolate = Some(vec![1]);
+        // The center ray passes the hidden sphere and meets the far one 5 units away
+        let depth = camera.render_depth(&world, 0.0, 10.0).unwrap().get(2, 2);
+        assert!((depth.x() - 0.5).abs() < 1e-9, "depth {}", depth.x());
+        camera.focus_peaking = Some(0.1);
+        camera.focus_dist = 5.0;
+        assert!(camera.in_focus(&world, 2, 2));
+    }
+
+    #[test]
+    fn every_tile_is_rendered_exactly_once() {
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, -3.0),
+            1.0,
+            &gray(),
+        )));
+        // 5 by 3 tiles, with partial ones on the right and bottom edges
+        let camera = Camera::new()
+            .with_resolution(37, 21)
+            .with_tile_size(8)
+            .with_seed(1);
+        assert_eq!(camera.tile_grid(), (5, 3));
+
+        let tiles = AtomicUsize::new(0);
+        let image = camera
+            .render_tiles(&world, 3, None, || {
+                tiles.fetch_add(1, Ordering::Relaxed);
+            })
+            .unwrap();
+        assert_eq!(tiles.load(Ordering::Relaxed), 15);
+        // Each pixel is rendered once, so none belongs to two tiles
+        assert_eq!(
+            camera.sample_counter.pixels.load(Ordering::Relaxed),
+            37 * 21
+        );
+
+        let serial = camera.render_serial_with_progress(&world, |_| {}).unwrap();
+        for j in 0..21 {
+            for i in 0..37 {
+                assert_eq!(image.get(i, j), serial.get(i, j), "pixel {} {}", i, j);
+            }
+        }
+    }
+
+    #[test]
+    fn tile_sizes_set_below_one_render_one_pixel_tiles() {
+        for tile_size in [0, -5] {
+            let mut camera = Camera::new()
+                .with_resolution(4, 3)
+                .with_samples(1)
+                .with_seed(1);
+            camera.tile_size = tile_size;
+            assert_eq!(camera.tile_grid(), (4, 3));
+            let threaded = camera.render_threads(&HittableList::new(), 2).unwrap();
+            let serial = camera
+                .render_serial_with_progress(&HittableList::new(), |_| {})
+                .unwrap();
+            for j in 0..3 {
+                for i in 0..4 {
+                    assert_ne!(threaded.get(i, j), Vec3::EMPTY);
+                    assert_eq!(threaded.get(i, j), serial.get(i, j));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tiled_renders_match_the_serial_render_at_any_tile_size() {
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, -3.0),
+            1.0,
+            &gray(),
+        )));
+        let camera = Camera::new()
+            .with_resolution(12, 9)
+            .with_samples(2)
+            .with_seed(5);
+        let serial = camera.render(world.clone()).unwrap();
+        for (tile_size, tiles) in [(1, 108), (5, 6), (32, 1)] {
+            let finished = AtomicUsize::new(0);
+            let tiled = camera
+                .clone()
+                .with_tile_size(tile_size)
+                .render_with_progress(&world, 3, |_| {
+                    finished.fetch_add(1, Ordering::Relaxed);
+                })
+                .unwrap();
+            assert_eq!(tiled.to_ppm(), serial, "tile size {}", tile_size);
+            // Progress fires once per finished tile
+            assert_eq!(finished.load(Ordering::Relaxed), tiles);
+        }
     }
 }
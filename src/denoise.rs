@@ -0,0 +1,179 @@
+use crate::buffer::PixelBuffer;
+use crate::camera::Aovs;
+use crate::vec3::Vec3;
+
+// What a denoiser gets to work with: the noisy image plus noise-free feature buffers
+// that tell real edges apart from sampling noise, as from `Camera::render_aovs`
+pub struct DenoiseInput<'a> {
+    pub beauty: &'a PixelBuffer,
+    pub albedo: &'a PixelBuffer,
+    pub normal: &'a PixelBuffer,
+}
+
+impl<'a> DenoiseInput<'a> {
+    pub fn new(beauty: &'a PixelBuffer, albedo: &'a PixelBuffer, normal: &'a PixelBuffer) -> Self {
+        for feature in [albedo, normal] {
+            assert!(
+                feature.width == beauty.width && feature.height == beauty.height,
+                "feature buffer is {}x{} but the image is {}x{}",
+                feature.width,
+                feature.height,
+                beauty.width,
+                beauty.height
+            );
+        }
+        return Self {
+            beauty,
+            albedo,
+            normal,
+        };
+    }
+}
+
+impl<'a> From<&'a Aovs> for DenoiseInput<'a> {
+    fn from(aovs: &'a Aovs) -> Self {
+        return Self::new(&aovs.beauty, &aovs.albedo, &aovs.normal);
+    }
+}
+
+// Extension point for denoisers, such as bindings to an external library. Returns a
+// cleaned copy of `input.beauty` at the same resolution.
+pub trait Denoiser {
+    fn denoise(&self, input: &DenoiseInput) -> PixelBuffer;
+}
+
+// Built-in fallback: a joint bilateral filter. Each pixel becomes a weighted average of
+// its neighbors, where neighbors count less the farther away they are and the more their
+// color, albedo or normal differs. Far from a real denoiser, but cheap and edge-aware.
+#[derive(Copy, Clone, Debug)]
+pub struct BilateralDenoiser {
+    // Gaussian falloff with distance, in pixels
+    pub sigma_space: f64,
+    // Falloff with the difference in each buffer
+    pub sigma_color: f64,
+    pub sigma_albedo: f64,
+    pub sigma_normal: f64,
+}
+
+impl BilateralDenoiser {
+    pub fn new() -> Self {
+        return Self {
+            sigma_space: 2.0,
+            sigma_color: 1.0,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.2,
+        };
+    }
+}
+
+impl Default for BilateralDenoiser {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl Denoiser for BilateralDenoiser {
+    fn denoise(&self, input: &DenoiseInput) -> PixelBuffer {
+        let guides = [
+            (input.beauty, self.sigma_color),
+            (input.albedo, self.sigma_albedo),
+            (input.normal, self.sigma_normal),
+        ];
+        return filter(input.beauty, self.sigma_space, &guides);
+    }
+}
+
+// Gaussian blur of `img`, with each neighbor's weight further scaled by how closely it
+// matches the center pixel in every guide buffer. No guides gives a plain Gaussian.
+pub(crate) fn filter(
+    img: &PixelBuffer,
+    sigma_space: f64,
+    guides: &[(&PixelBuffer, f64)],
+) -> PixelBuffer {
+    let mut out = PixelBuffer::new(img.width, img.height);
+    if sigma_space <= 0.0 {
+        out.copy_rows(0, img);
+        return out;
+    }
+
+    // Weights past three sigmas are negligible
+    let radius = (3.0 * sigma_space).ceil() as i64;
+    let falloff = |d2: f64, sigma: f64| (-d2 / (2.0 * sigma * sigma)).exp();
+
+    for y in 0..img.height {
+        for x in 0..img.width {
+            let centers: Vec<Vec3> = guides.iter().map(|(g, _)| g.get(x, y)).collect();
+            let mut total = Vec3::EMPTY;
+            let mut weight_sum = 0.0;
+            for ny in (y - radius).max(0)..=(y + radius).min(img.height - 1) {
+                for nx in (x - radius).max(0)..=(x + radius).min(img.width - 1) {
+                    let (dx, dy) = ((nx - x) as f64, (ny - y) as f64);
+                    let mut weight = falloff(dx * dx + dy * dy, sigma_space);
+                    for ((guide, sigma), center) in guides.iter().zip(&centers) {
+                        let diff = (guide.get(nx, ny) - *center).length_squared();
+                        weight *= falloff(diff, *sigma);
+                    }
+                    total += img.get(nx, ny) * weight;
+                    weight_sum += weight;
+                }
+            }
+            // The center pixel always has weight 1, so the sum is never zero
+            out.set(x, y, total / weight_sum);
+        }
+    }
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn variance(img: &PixelBuffer) -> f64 {
+        let values: Vec<f64> = (0..img.height)
+            .flat_map(|y| (0..img.width).map(move |x| (x, y)))
+            .map(|(x, y)| img.get(x, y).x())
+            .collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        return values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / values.len() as f64;
+    }
+
+    fn constant(width: i64, height: i64, color: Vec3) -> PixelBuffer {
+        let mut buf = PixelBuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                buf.set(x, y, color);
+            }
+        }
+        return buf;
+    }
+
+    #[test]
+    fn bilateral_fallback_reduces_noise_on_a_flat_region() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut beauty = PixelBuffer::new(16, 16);
+        for y in 0..16 {
+            for x in 0..16 {
+                let v = 0.5 + rng.random_range(-0.2..0.2);
+                beauty.set(x, y, Vec3::new(v, v, v));
+            }
+        }
+        let albedo = constant(16, 16, Vec3::new(0.5, 0.5, 0.5));
+        let normal = constant(16, 16, Vec3::new(0.0, 0.0, 1.0));
+
+        let input = DenoiseInput::new(&beauty, &albedo, &normal);
+        let cleaned = BilateralDenoiser::new().denoise(&input);
+        assert_eq!((cleaned.width, cleaned.height), (16, 16));
+        let (before, after) = (variance(&beauty), variance(&cleaned));
+        assert!(after < before / 4.0, "variance {} -> {}", before, after);
+    }
+
+    #[test]
+    #[should_panic(expected = "feature buffer")]
+    fn input_rejects_mismatched_feature_buffers() {
+        let beauty = PixelBuffer::new(4, 4);
+        let albedo = PixelBuffer::new(4, 3);
+        DenoiseInput::new(&beauty, &albedo, &beauty);
+    }
+}
@@ -0,0 +1,274 @@
+use crate::aabb::Aabb;
+use crate::interval::Interval;
+use crate::vec3::Vec3;
+use crate::world::{HitResult, Hittable, Ray};
+use rand::RngCore;
+use std::sync::Arc;
+
+// Places `object` at an offset. Rays are moved into object space instead of moving
+// the geometry, so the same object can be shared between instances.
+pub struct Translate {
+    pub object: Arc<dyn Hittable>,
+    pub offset: Vec3,
+    bbox: Aabb,
+}
+
+impl Translate {
+    pub fn new(object: Arc<dyn Hittable>, offset: Vec3) -> Self {
+        let bbox = object.bounding_box().translated(offset);
+        return Self {
+            object,
+            offset,
+            bbox,
+        };
+    }
+}
+
+impl Hittable for Translate {
+    fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
+        let moved = Ray::with_time(ray.origin - self.offset, ray.dir, ray.time);
+        return match self.object.hit(&moved, interval) {
+            HitResult::Hit(mut rec) => {
+                rec.point += self.offset;
+                HitResult::Hit(rec)
+            }
+            HitResult::Miss => HitResult::Miss,
+        };
+    }
+
+    fn hit_any(&self, ray: &Ray, interval: Interval) -> bool {
+        let moved = Ray::with_time(ray.origin - self.offset, ray.dir, ray.time);
+        return self.object.hit_any(&moved, interval);
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        return self.object.pdf_value(origin - self.offset, direction);
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        return self.object.random(origin - self.offset, rng);
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        return self.bbox;
+    }
+}
+
+// Rotates `object` counterclockwise about the y axis, looking down from +y
+pub struct RotateY {
+    pub object: Arc<dyn Hittable>,
+    pub angle_degrees: f64,
+    sin_theta: f64,
+    cos_theta: f64,
+    bbox: Aabb,
+}
+
+impl RotateY {
+    pub fn new(object: Arc<dyn Hittable>, angle_degrees: f64) -> Self {
+        let theta = angle_degrees.to_radians();
+        let (sin_theta, cos_theta) = theta.sin_cos();
+
+        // Rotating an infinite box would mix infinities into NaNs
+        let inner = object.bounding_box();
+        let bbox = if inner.is_infinite() {
+            Aabb::UNIVERSE
+        } else {
+            // Box around all eight rotated corners
+            let mut bbox = Aabb::EMPTY;
+            for x in [inner.x.min, inner.x.max] {
+                for y in [inner.y.min, inner.y.max] {
+                    for z in [inner.z.min, inner.z.max] {
+                        let corner = Vec3::new(
+                            cos_theta * x + sin_theta * z,
+                            y,
+                            -sin_theta * x + cos_theta * z,
+                        );
+                        bbox = Aabb::surrounding(&bbox, &Aabb::from_points(corner, corner));
+                    }
+                }
+            }
+            bbox
+        };
+
+        return Self {
+            object,
+            angle_degrees,
+            sin_theta,
+            cos_theta,
+            bbox,
+        };
+    }
+
+    // World space to object space
+    fn to_object(&self, p: Vec3) -> Vec3 {
+        return Vec3::new(
+            self.cos_theta * p.x() - self.sin_theta * p.z(),
+            p.y(),
+            self.sin_theta * p.x() + self.cos_theta * p.z(),
+        );
+    }
+
+    // Object space to world space
+    fn to_world(&self, p: Vec3) -> Vec3 {
+        return Vec3::new(
+            self.cos_theta * p.x() + self.sin_theta * p.z(),
+            p.y(),
+            -self.sin_theta * p.x() + self.cos_theta * p.z(),
+        );
+    }
+}
+
+impl Hittable for RotateY {
+    fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
+        let rotated = Ray::with_time(
+            self.to_object(ray.origin),
+            self.to_object(ray.dir),
+            ray.time,
+        );
+        return match self.object.hit(&rotated, interval) {
+            HitResult::Hit(mut rec) => {
+                // A rotation preserves lengths and angles, so the normal needs no
+                // renormalizing and front_face is unchanged
+                rec.point = self.to_world(rec.point);
+                rec.normal = self.to_world(rec.normal);
+                HitResult::Hit(rec)
+            }
+            HitResult::Miss => HitResult::Miss,
+        };
+    }
+
+    fn hit_any(&self, ray: &Ray, interval: Interval) -> bool {
+        let rotated = Ray::with_time(
+            self.to_object(ray.origin),
+            self.to_object(ray.dir),
+            ray.time,
+        );
+        return self.object.hit_any(&rotated, interval);
+    }
+
+    // Rotations preserve solid angles, so densities carry over unchanged
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        let origin = self.to_object(origin);
+        return self.object.pdf_value(origin, self.to_object(direction));
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        let direction = self.object.random(self.to_object(origin), rng);
+        return self.to_world(direction);
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        return self.bbox;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, Material};
+    use crate::world::{BoxPrim, HitRecord, Sphere};
+
+    fn gray() -> Arc<dyn Material> {
+        return Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    }
+
+    fn hit(object: &dyn Hittable, origin: Vec3, dir: Vec3) -> HitRecord {
+        return match object.hit(&Ray::new(origin, dir), Interval::ALMOST_FORWARD) {
+            HitResult::Hit(rec) => rec,
+            HitResult::Miss => panic!("ray from {:?} along {:?} missed", origin, dir),
+        };
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn translated_sphere_is_hit_at_its_offset() {
+        let sphere = Arc::new(Sphere::new(Vec3::EMPTY, 1.0, &gray()));
+        let moved = Translate::new(sphere, Vec3::new(5.0, 0.0, 0.0));
+        let down = Vec3::new(0.0, 0.0, -1.0);
+
+        let rec = hit(&moved, Vec3::new(5.0, 0.0, 5.0), down);
+        assert!((rec.t - 4.0).abs() < 1e-9);
+        assert_close(rec.point, Vec3::new(5.0, 0.0, 1.0));
+        assert_close(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+
+        // The original position is empty now
+        let at_origin = Ray::new(Vec3::new(0.0, 0.0, 5.0), down);
+        assert!(matches!(
+            moved.hit(&at_origin, Interval::ALMOST_FORWARD),
+            HitResult::Miss
+        ));
+        assert!(matches!(
+            moved.hit(&at_origin, Interval::ALMOST_FORWARD),
+            HitResult::Miss
+        ));
+
+        let bbox = moved.bounding_box();
+        assert!(bbox.x.min <= 4.0 && bbox.x.max >= 6.0);
+        assert!(bbox.x.min > 3.9 && bbox.x.max < 6.1);
+    }
+
+    #[test]
+    fn rotated_box_returns_rotated_points_and_normals() {
+        // x in [0, 1] and z in [0, 2]; a quarter turn maps (x, y, z) to (z, y, -x)
+        let cuboid = Arc::new(BoxPrim::new(Vec3::EMPTY, Vec3::new(1.0, 1.0, 2.0), &gray()));
+        let rotated = RotateY::new(cuboid, 90.0);
+
+        // The object's far z face now faces +x
+        let rec = hit(
+            &rotated,
+            Vec3::new(5.0, 0.5, -0.5),
+            Vec3::new(-1.0, 0.0, 0.0),
+        );
+        assert!((rec.t - 3.0).abs() < 1e-9);
+        assert_close(rec.point, Vec3::new(2.0, 0.5, -0.5));
+        assert_close(rec.normal, Vec3::new(1.0, 0.0, 0.0));
+        assert!(rec.front_face);
+
+        // And its x = 1 face now faces -z
+        let rec = hit(
+            &rotated,
+            Vec3::new(1.0, 0.5, -5.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        assert_close(rec.point, Vec3::new(1.0, 0.5, -1.0));
+        assert_close(rec.normal, Vec3::new(0.0, 0.0, -1.0));
+
+        // A vertical ray through the unrotated box now passes beside it
+        let old_spot = Ray::new(Vec3::new(0.5, 5.0, 1.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(matches!(
+            rotated.hit(&old_spot, Interval::ALMOST_FORWARD),
+            HitResult::Miss
+        ));
+    }
+
+    #[test]
+    fn rotated_bounding_boxes_contain_every_rotated_corner() {
+        let (min, max) = (Vec3::new(-1.0, 0.0, 0.5), Vec3::new(2.0, 3.0, 1.5));
+        for angle in [0.0, 30.0, 45.0, 90.0, 135.0, 200.0, -75.0] {
+            let rotated = RotateY::new(Arc::new(BoxPrim::new(min, max, &gray())), angle);
+            let bbox = rotated.bounding_box();
+            for x in [min.x(), max.x()] {
+                for y in [min.y(), max.y()] {
+                    for z in [min.z(), max.z()] {
+                        let corner = rotated.to_world(Vec3::new(x, y, z));
+                        for (extent, c) in [
+                            (bbox.x, corner.x()),
+                            (bbox.y, corner.y()),
+                            (bbox.z, corner.z()),
+                        ] {
+                            assert!(
+                                extent.min <= c + 1e-9 && c <= extent.max + 1e-9,
+                                "corner {:?} outside the box at {} degrees",
+                                corner,
+                                angle
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,3 +1,4 @@
+#[derive(Copy, Clone)]
 pub struct Interval {
     pub min: f64,
     pub max: f64,
@@ -8,6 +9,22 @@ impl Interval {
         return Self { min, max };
     }
 
+    // Interval between `a` and `b`, in either order
+    pub fn from_bounds(a: f64, b: f64) -> Self {
+        return Self::new(a.min(b), a.max(b));
+    }
+
+    // Smallest interval enclosing both; the union with EMPTY is the other interval
+    pub fn union(a: &Interval, b: &Interval) -> Self {
+        return Self::new(a.min.min(b.min), a.max.max(b.max));
+    }
+
+    // Overlap of the two intervals. Disjoint intervals give an empty result with
+    // min > max; intervals that only touch give a single point.
+    pub fn intersect(a: &Interval, b: &Interval) -> Self {
+        return Self::new(a.min.max(b.min), a.max.min(b.max));
+    }
+
     pub fn size(&self) -> f64 {
         return self.max - self.min;
     }
@@ -20,8 +37,91 @@ impl Interval {
         return self.min < x && x < self.max;
     }
 
+    // Interval widened by `delta`, half at each end
+    pub fn expand(&self, delta: f64) -> Self {
+        let padding = delta / 2.0;
+        return Self::new(self.min - padding, self.max + padding);
+    }
+
+    // `x` bounded to [min, max]
+    pub fn clamp(&self, x: f64) -> f64 {
+        if x < self.min {
+            return self.min;
+        }
+        if x > self.max {
+            return self.max;
+        }
+        return x;
+    }
+
     pub const EMPTY: Interval = Interval::new(f64::INFINITY, -f64::INFINITY);
     pub const MAX: Interval = Interval::new(-f64::INFINITY, f64::INFINITY);
     pub const FORWARD: Interval = Interval::new(0.0, f64::INFINITY);
     pub const ALMOST_FORWARD: Interval = Interval::new(0.001, f64::INFINITY);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp_bounds_values_to_the_interval() {
+        let unit = Interval::new(0.0, 1.0);
+        assert_eq!(unit.clamp(-0.5), 0.0);
+        assert_eq!(unit.clamp(0.25), 0.25);
+        assert_eq!(unit.clamp(3.0), 1.0);
+        assert_eq!(unit.clamp(f64::INFINITY), 1.0);
+    }
+
+    fn bounds(i: Interval) -> (f64, f64) {
+        return (i.min, i.max);
+    }
+
+    #[test]
+    fn from_bounds_accepts_either_order() {
+        assert_eq!(bounds(Interval::from_bounds(3.0, -1.0)), (-1.0, 3.0));
+        assert_eq!(bounds(Interval::from_bounds(-1.0, 3.0)), (-1.0, 3.0));
+    }
+
+    #[test]
+    fn expand_pads_half_at_each_end() {
+        let padded = Interval::new(1.0, 2.0).expand(0.5);
+        assert_eq!(bounds(padded), (0.75, 2.25));
+        assert_eq!(padded.size(), 1.5);
+    }
+
+    #[test]
+    fn union_encloses_both_intervals() {
+        let a = Interval::new(0.0, 2.0);
+        assert_eq!(
+            bounds(Interval::union(&a, &Interval::new(1.0, 3.0))),
+            (0.0, 3.0)
+        );
+        assert_eq!(
+            bounds(Interval::union(&a, &Interval::new(2.0, 3.0))),
+            (0.0, 3.0)
+        );
+        assert_eq!(
+            bounds(Interval::union(&a, &Interval::new(5.0, 6.0))),
+            (0.0, 6.0)
+        );
+        assert_eq!(bounds(Interval::union(&a, &Interval::EMPTY)), (0.0, 2.0));
+    }
+
+    #[test]
+    fn intersect_keeps_the_overlap() {
+        let a = Interval::new(0.0, 2.0);
+        assert_eq!(
+            bounds(Interval::intersect(&a, &Interval::new(1.0, 3.0))),
+            (1.0, 2.0)
+        );
+        // Touching intervals share a single point
+        let touching = Interval::intersect(&a, &Interval::new(2.0, 3.0));
+        assert_eq!(bounds(touching), (2.0, 2.0));
+        assert!(touching.contains(2.0));
+        // Disjoint ones come out empty, with min past max
+        let disjoint = Interval::intersect(&a, &Interval::new(5.0, 6.0));
+        assert!(disjoint.min > disjoint.max);
+        assert!(!disjoint.contains(1.0) && !disjoint.contains(5.5));
+    }
+}
@@ -0,0 +1,90 @@
+// The explicit `return` style is used throughout the crate
+#![allow(clippy::needless_return)]
+
+pub mod aabb;
+pub mod accumulator;
+pub mod background;
+pub mod batch;
+pub mod buffer;
+pub mod bvh;
+pub mod camera;
+pub mod denoise;
+pub mod instance;
+pub mod interval;
+pub mod material;
+pub mod medium;
+pub mod pdf;
+pub mod perlin;
+pub mod progressive;
+pub mod scene;
+pub mod stats;
+pub mod texture;
+pub mod util;
+pub mod vec3;
+pub mod web;
+pub mod world;
+
+// `#[wasm_bindgen]` exports for the browser build, forwarding to the `web` entry points.
+// Errors are thrown to JS as exceptions carrying the error message.
+#[cfg(feature = "wasm")]
+mod wasm {
+    use crate::scene::SceneError;
+    use crate::web;
+    use wasm_bindgen::prelude::*;
+
+    fn js_error(e: SceneError) -> JsValue {
+        return JsValue::from_str(&e.to_string());
+    }
+
+    #[wasm_bindgen]
+    pub fn render() -> Result<Vec<u8>, JsValue> {
+        return web::render().map_err(js_error);
+    }
+
+    #[wasm_bindgen]
+    pub fn render_rgba() -> Result<Vec<u8>, JsValue> {
+        return web::render_rgba().map_err(js_error);
+    }
+
+    #[wasm_bindgen]
+    pub fn render_scene(
+        json: &str,
+        width: u32,
+        height: u32,
+        samples: u32,
+        gamma: f64,
+    ) -> Result<Vec<u8>, JsValue> {
+        return web::render_scene(json, width, height, samples, gamma).map_err(js_error);
+    }
+
+    #[wasm_bindgen]
+    pub fn render_scene_rgba(
+        json: &str,
+        width: u32,
+        height: u32,
+        samples: u32,
+        gamma: f64,
+    ) -> Result<Vec<u8>, JsValue> {
+        return web::render_scene_rgba(json, width, height, samples, gamma).map_err(js_error);
+    }
+
+    // Calls `progress` with the finished fraction of the image as tiles complete. The
+    // render blocks the calling thread, so run it in a Web Worker and post each fraction
+    // back to the page; on the main thread the page cannot repaint until it returns.
+    #[wasm_bindgen]
+    pub fn render_scene_progress(
+        json: &str,
+        width: u32,
+        height: u32,
+        samples: u32,
+        gamma: f64,
+        progress: &js_sys::Function,
+    ) -> Result<Vec<u8>, JsValue> {
+        let report = |fraction: f64| {
+            // A throwing callback should not abort the render
+            let _ = progress.call1(&JsValue::NULL, &JsValue::from_f64(fraction));
+        };
+        return web::render_scene_with_progress(json, width, height, samples, gamma, report)
+            .map_err(js_error);
+    }
+}
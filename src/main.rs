@@ -1,81 +1,75 @@
-mod camera;
-mod interval;
-mod material;
-mod util;
-mod vec3;
-mod world;
+#![allow(clippy::needless_return)]
 
-use camera::Camera;
-use material::{Lambertian, Material, Metal};
-use world::{HittableList, Sphere, Triangle};
+use ray_trace_rs::camera::Camera;
+use ray_trace_rs::scene::Scene;
+use ray_trace_rs::util::{save_ppm_with, write_exr};
 
-use vec3::Vec3;
+use std::thread;
 
-use std::fs::File;
-use std::io::Write;
-
-use std::sync::Arc;
-
-use rand::Rng;
 use std::time;
 
 fn main() {
     let start = time::Instant::now();
-    let mut file = File::create("test.ppm").unwrap();
-
-    let camera = Camera::new();
-
-    let mut world = HittableList::new();
-
-    // Materials
-    let material_ground: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.8, 0.8, 0.0)));
-    let material_center: Arc<dyn Material> = Arc::new(Metal::new(Vec3::new(0.1, 0.2, 0.5), 0.1));
-    let material_left: Arc<dyn Material> = Arc::new(Metal::new(Vec3::new(0.8, 0.8, 0.8), 0.3));
-    let material_right: Arc<dyn Material> = Arc::new(Metal::new(Vec3::new(0.8, 0.6, 0.2), 1.0));
 
-    // Scene objects
-    world.add(Arc::new(Sphere::new(
-        Vec3(0.0, -100.5, -1.0),
-        100.0,
-        &material_ground,
-    )));
-    world.add(Arc::new(Sphere::new(
-        Vec3(0.0, 0.0, -1.2),
-        0.5,
-        &material_center,
-    )));
-    world.add(Arc::new(Sphere::new(
-        Vec3(-1.0, 0.0, -1.0),
-        0.5,
-        &material_left,
-    )));
-    world.add(Arc::new(Sphere::new(
-        Vec3(1.0, 0.0, -1.0),
-        0.5,
-        &material_right,
-    )));
-    world.add(Arc::new(Sphere::new(
-        Vec3(0.5, 0.0, -1.2),
-        0.05,
-        &material_left,
-    )));
-    world.add(Arc::new(Sphere::new(
-        Vec3(-0.5, 0.0, -1.2),
-        0.05,
-        &material_right,
-    )));
-    world.add(Arc::new(Triangle::new(
-        Vec3(0.7, -0.2, -1.2),
-        Vec3(-0.7, -0.2, -0.8),
-        Vec3(0.0, 0.7, -1.5),
-        &material_right,
-    )));
+    // Either `<scene.json>`, or optional `<width> <height>` for the built-in scene. With
+    // `--exr` anywhere, the linear render is also saved as test.exr.
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let exr = args.iter().any(|a| a == "--exr");
+    args.retain(|a| a != "--exr");
+    let scene = match &args[..] {
+        [path] => match Scene::load(path) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        [width, height] => {
+            let parse = |a: &str| match a.parse::<i64>() {
+                Ok(n) => n,
+                Err(e) => {
+                    eprintln!("resolution must be integers, got {:?}: {}", a, e);
+                    std::process::exit(2);
+                }
+            };
+            let camera = Camera::new().with_resolution(parse(width), parse(height));
+            Scene::demo(camera)
+        }
+        _ => Scene::demo(Camera::new()),
+    };
+    for warning in &scene.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    let camera = scene.render_camera();
 
-    let world_ptr = Arc::new(world);
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    println!("Rendering {}", scene.summary());
 
-    let str_buf = camera.parallel_render(16, &world_ptr);
+    let (image, stats) = match camera.render_with_stats(&scene.world, threads) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
-    file.write_all(str_buf.as_ref()).unwrap();
+    if let Err(e) = save_ppm_with(&image, camera.tonemap, "test.ppm") {
+        eprintln!("could not write test.ppm: {}", e);
+        std::process::exit(1);
+    }
+    if exr {
+        if let Err(e) = write_exr(&image, "test.exr") {
+            eprintln!("could not write test.exr: {}", e);
+            std::process::exit(1);
+        }
+    }
 
+    if let (Some(_), Some(average)) = (camera.adaptive, camera.average_samples()) {
+        println!(
+            "Adaptive sampling used {:.1} samples per pixel on average",
+            average
+        );
+    }
+    println!("Rendered in {}", stats);
     println!("Done! {} s", start.elapsed().as_secs());
 }
@@ -1,5 +1,9 @@
+use crate::texture::{SolidColor, Texture};
+use crate::util::blackbody_to_rgb;
 use crate::vec3::Vec3;
 use crate::world::{HitRecord, Ray};
+use rand::{Rng, RngCore};
+use std::sync::Arc;
 
 pub enum ScatterResult {
     Scatter(Ray, Vec3),
@@ -7,58 +11,392 @@ pub enum ScatterResult {
 }
 
 pub trait Material: Send + Sync {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> ScatterResult;
+    // All randomness comes from `rng`, so a seeded generator reproduces the same bounces
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut dyn RngCore) -> ScatterResult;
+
+    // Light given off by the surface, none unless the material is a light
+    fn emitted(&self) -> Vec3 {
+        return Vec3::EMPTY;
+    }
+
+    // Base color at surface coordinates (u, v) and `point`, ignoring lighting and view
+    // angle. Black for materials that only emit.
+    fn albedo(&self, _u: f64, _v: f64, _point: Vec3) -> Vec3 {
+        return Vec3::EMPTY;
+    }
+
+    // Reflectance of a diffuse surface, used to shade it directly from point lights.
+    // None for materials that do not scatter diffusely.
+    fn diffuse_albedo(&self, _hit_record: &HitRecord) -> Option<Vec3> {
+        return None;
+    }
+
+    // Density, per steradian, of scattering from `ray` into `scattered` for a material
+    // whose `scatter` follows a smooth distribution. Materials reporting one can have
+    // bounces aimed at area lights instead, weighted back by this density. The default
+    // of 0 opts out, as mirrors and glass must.
+    fn scatter_pdf(&self, _ray: &Ray, _hit_record: &HitRecord, _scattered: &Ray) -> f64 {
+        return 0.0;
+    }
+
+    // Short lowercase label for logs and debug output, matching the material's "type"
+    // in scene files where it has one
+    fn name(&self) -> &str {
+        return "material";
+    }
 }
 
 pub struct Lambertian {
-    albedo: Vec3,
+    albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Vec3) -> Self {
+        return Self::textured(Arc::new(SolidColor::new(albedo)));
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
         return Self { albedo };
     }
+
+    // Texture color at a hit, filtered over the hit's footprint
+    fn albedo_at(&self, hit_record: &HitRecord) -> Vec3 {
+        return self.albedo.value_lod(
+            hit_record.u,
+            hit_record.v,
+            hit_record.point,
+            hit_record.footprint,
+        );
+    }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> ScatterResult {
-        let mut dir = hit_record.normal + Vec3::random_on_sphere();
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut dyn RngCore) -> ScatterResult {
+        let mut dir = hit_record.normal + Vec3::random_unit_vector(rng);
 
         // Catch degenerate scatter direction
         if dir.near_zero() {
             dir = hit_record.normal;
         }
 
-        let scattered_ray = Ray::new(hit_record.point, dir);
-        let attenuation = self.albedo;
+        let scattered_ray = Ray::with_time(hit_record.point, dir, ray.time);
+        let attenuation = self.albedo_at(hit_record);
 
         return ScatterResult::Scatter(scattered_ray, attenuation);
     }
+
+    fn albedo(&self, u: f64, v: f64, point: Vec3) -> Vec3 {
+        return self.albedo.value(u, v, point);
+    }
+
+    fn diffuse_albedo(&self, hit_record: &HitRecord) -> Option<Vec3> {
+        return Some(self.albedo_at(hit_record));
+    }
+
+    // Cosine-weighted, matching the normal-plus-unit-vector direction `scatter` draws
+    fn scatter_pdf(&self, _ray: &Ray, hit_record: &HitRecord, scattered: &Ray) -> f64 {
+        let cosine = Vec3::dot(hit_record.normal, scattered.dir.unit());
+        return cosine.max(0.0) / std::f64::consts::PI;
+    }
+
+    fn name(&self) -> &str {
+        return "lambertian";
+    }
 }
 
 pub struct Metal {
     albedo: Vec3,
     fuzz: f64,
+    // Share of `albedo` reflected at normal incidence, rising toward all of it at grazing
+    // angles. 1 reflects the full albedo at every angle.
+    f0: f64,
 }
 
 impl Metal {
+    // `fuzz` is the radius of the sphere the reflected direction is perturbed by, relative
+    // to its unit length. It is clamped to [0, 1]; beyond that reflections turn diffuse.
     pub fn new(albedo: Vec3, fuzz: f64) -> Self {
-        return Self { albedo, fuzz };
+        return Self {
+            albedo,
+            fuzz: fuzz.clamp(0.0, 1.0),
+            f0: 1.0,
+        };
+    }
+
+    // Enables Fresnel falloff with base reflectivity `f0`, clamped to [0, 1]
+    pub fn with_f0(mut self, f0: f64) -> Self {
+        self.f0 = f0.clamp(0.0, 1.0);
+        return self;
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> ScatterResult {
-        let reflected = Vec3::reflect(ray.dir, hit_record.normal).unit() + Vec3::random_on_sphere
-            () *
-            self
-            .fuzz;
-        let scattered_ray = Ray::new(hit_record.point, reflected);
-        let attenuation = self.albedo;
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut dyn RngCore) -> ScatterResult {
+        let reflected = Vec3::reflect(ray.dir, hit_record.normal).unit()
+            + Vec3::random_unit_vector(rng) * self.fuzz;
+        let scattered_ray = Ray::with_time(hit_record.point, reflected, ray.time);
+        let cos_theta = Vec3::dot(-ray.dir.unit(), hit_record.normal).min(1.0);
+        let attenuation = self.albedo * schlick(cos_theta, self.f0);
         return if Vec3::dot(reflected, hit_record.normal) > 0.0 {
             ScatterResult::Scatter(scattered_ray, attenuation)
         } else {
             ScatterResult::NoScatter
         };
     }
+
+    fn albedo(&self, _u: f64, _v: f64, _point: Vec3) -> Vec3 {
+        return self.albedo;
+    }
+
+    fn name(&self) -> &str {
+        return "metal";
+    }
+}
+
+pub struct Dielectric {
+    albedo: Vec3,
+    // Refractive index in vacuum or air, or the ratio of the material's index over the
+    // index of the enclosing media
+    refraction_index: f64,
+}
+
+impl Dielectric {
+    pub fn new(refraction_index: f64) -> Self {
+        return Self::colored(Vec3::new(1.0, 1.0, 1.0), refraction_index);
+    }
+
+    // Tinted glass, attenuating every pass through the surface by `albedo`
+    pub fn colored(albedo: Vec3, refraction_index: f64) -> Self {
+        return Self {
+            albedo,
+            refraction_index,
+        };
+    }
+}
+
+// Schlick's approximation for the reflectance of a dielectric boundary with relative
+// index `refraction_index`, at an angle whose cosine is `cosine`
+pub fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
+    let r0 = (1.0 - refraction_index) / (1.0 + refraction_index);
+    return schlick(cosine, r0 * r0);
+}
+
+// Schlick's Fresnel curve from reflectance `r0` at normal incidence up to 1 at grazing
+pub fn schlick(cosine: f64, r0: f64) -> f64 {
+    return r0 + (1.0 - r0) * (1.0 - cosine).powi(5);
+}
+
+impl Material for Dielectric {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut dyn RngCore) -> ScatterResult {
+        let ri = if hit_record.front_face {
+            1.0 / self.refraction_index
+        } else {
+            self.refraction_index
+        };
+
+        let unit_dir = ray.dir.unit();
+        let cos_theta = Vec3::dot(-unit_dir, hit_record.normal).min(1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+        // Total internal reflection leaves no refracted ray
+        let cannot_refract = ri * sin_theta > 1.0;
+        let dir = if cannot_refract || reflectance(cos_theta, ri) > rng.random::<f64>() {
+            Vec3::reflect(unit_dir, hit_record.normal)
+        } else {
+            Vec3::refract(unit_dir, hit_record.normal, ri)
+        };
+
+        let scattered_ray = Ray::with_time(hit_record.point, dir, ray.time);
+        return ScatterResult::Scatter(scattered_ray, self.albedo);
+    }
+
+    fn albedo(&self, _u: f64, _v: f64, _point: Vec3) -> Vec3 {
+        return self.albedo;
+    }
+
+    fn name(&self) -> &str {
+        return "dielectric";
+    }
+}
+
+// Emits a constant color and absorbs every incoming ray
+pub struct DiffuseLight {
+    emit: Vec3,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Vec3) -> Self {
+        return Self { emit };
+    }
+
+    // Light colored like a blackbody at `kelvin`, scaled by `intensity`
+    pub fn from_temperature(kelvin: f64, intensity: f64) -> Self {
+        return Self::new(blackbody_to_rgb(kelvin) * intensity);
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        _hit_record: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> ScatterResult {
+        return ScatterResult::NoScatter;
+    }
+
+    fn emitted(&self) -> Vec3 {
+        return self.emit;
+    }
+
+    fn name(&self) -> &str {
+        return "diffuse_light";
+    }
+}
+
+// Phase function of a participating medium: scatters equally in every direction
+pub struct Isotropic {
+    albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Vec3) -> Self {
+        return Self::textured(Arc::new(SolidColor::new(albedo)));
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
+        return Self { albedo };
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut dyn RngCore) -> ScatterResult {
+        let dir = Vec3::random_unit_vector(rng);
+        let scattered_ray = Ray::with_time(hit_record.point, dir, ray.time);
+        let attenuation = self
+            .albedo
+            .value(hit_record.u, hit_record.v, hit_record.point);
+
+        return ScatterResult::Scatter(scattered_ray, attenuation);
+    }
+
+    fn albedo(&self, u: f64, v: f64, point: Vec3) -> Vec3 {
+        return self.albedo.value(u, v, point);
+    }
+
+    fn name(&self) -> &str {
+        return "isotropic";
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn light_temperatures_map_to_expected_hues() {
+        let warm = DiffuseLight::from_temperature(3200.0, 1.0).emitted();
+        assert!(
+            warm.x() > warm.y() && warm.y() > warm.z(),
+            "3200K {:?}",
+            warm
+        );
+
+        let neutral = DiffuseLight::from_temperature(6500.0, 1.0).emitted();
+        let spread = neutral.x().max(neutral.y()).max(neutral.z())
+            - neutral.x().min(neutral.y()).min(neutral.z());
+        assert!(spread < 0.05, "6500K {:?}", neutral);
+
+        let cool = DiffuseLight::from_temperature(10000.0, 1.0).emitted();
+        assert!(
+            cool.z() > cool.y() && cool.y() > cool.x(),
+            "10000K {:?}",
+            cool
+        );
+    }
+
+    #[test]
+    fn light_temperature_intensity_scales_the_color() {
+        let dim = DiffuseLight::from_temperature(3200.0, 1.0).emitted();
+        let bright = DiffuseLight::from_temperature(3200.0, 4.0).emitted();
+        assert_eq!(bright, dim * 4.0);
+    }
+
+    // Hit on a mirror facing +y at the origin
+    fn mirror_hit() -> HitRecord {
+        let mut rec = HitRecord::new();
+        rec.normal = Vec3::new(0.0, 1.0, 0.0);
+        rec.front_face = true;
+        return rec;
+    }
+
+    fn attenuation_from(metal: Metal, dir: Vec3) -> Vec3 {
+        let rec = mirror_hit();
+        let mut rng = StdRng::seed_from_u64(1);
+        return match metal.scatter(&Ray::new(-dir * 3.0, dir), &rec, &mut rng) {
+            ScatterResult::Scatter(_, attenuation) => attenuation,
+            ScatterResult::NoScatter => panic!("metal absorbed the ray"),
+        };
+    }
+
+    #[test]
+    fn metal_fresnel_brightens_toward_grazing_angles() {
+        let gold = || Metal::new(Vec3::new(1.0, 0.8, 0.4), 0.0).with_f0(0.5);
+        let normal = attenuation_from(gold(), Vec3::new(0.0, -1.0, 0.0));
+        let grazing = attenuation_from(gold(), Vec3::new(1.0, -0.05, 0.0).unit());
+        assert!((normal - Vec3::new(0.5, 0.4, 0.2)).length() < 1e-12);
+        // 0.5 + 0.5 * (1 - 0.05)^5 at a cosine of about 0.05
+        assert!((grazing.x() - 0.887).abs() < 0.01, "{:?}", grazing);
+
+        // The default reflects the full albedo at every angle
+        let plain = Metal::new(Vec3::new(1.0, 0.8, 0.4), 0.0);
+        assert_eq!(
+            attenuation_from(plain, Vec3::new(0.0, -1.0, 0.0)),
+            Vec3::new(1.0, 0.8, 0.4)
+        );
+    }
+
+    #[test]
+    fn unfuzzed_metal_is_a_perfect_mirror() {
+        let metal = Metal::new(Vec3::new(0.9, 0.9, 0.9), 0.0);
+        let dir = Vec3::new(1.0, -1.0, 0.0);
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..10 {
+            match metal.scatter(&Ray::new(-dir, dir), &mirror_hit(), &mut rng) {
+                ScatterResult::Scatter(ray, _) => {
+                    assert_eq!(ray.dir, Vec3::new(1.0, 1.0, 0.0).unit())
+                }
+                ScatterResult::NoScatter => panic!("mirror absorbed the ray"),
+            }
+        }
+    }
+
+    #[test]
+    fn metal_fuzz_is_clamped_to_the_unit_range() {
+        assert_eq!(Metal::new(Vec3::EMPTY, -0.5).fuzz, 0.0);
+        assert_eq!(Metal::new(Vec3::EMPTY, 3.0).fuzz, 1.0);
+    }
+
+    #[test]
+    fn reflectance_runs_from_r0_head_on_to_one_at_grazing() {
+        // Glass against air reflects 4% head on
+        assert!((reflectance(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert_eq!(reflectance(0.0, 1.5), 1.0);
+        // The same boundary seen from inside
+        assert!((reflectance(1.0, 1.0 / 1.5) - 0.04).abs() < 1e-12);
+        assert_eq!(schlick(1.0, 0.3), 0.3);
+        assert_eq!(schlick(0.0, 0.3), 1.0);
+    }
+
+    #[test]
+    fn materials_are_named_as_in_scene_files() {
+        let gray = Vec3::new(0.5, 0.5, 0.5);
+        assert_eq!(Metal::new(gray, 0.2).name(), "metal");
+        assert_eq!(Lambertian::new(gray).name(), "lambertian");
+        assert_eq!(Dielectric::new(1.5).name(), "dielectric");
+        assert_eq!(DiffuseLight::new(gray).name(), "diffuse_light");
+        assert_eq!(Isotropic::new(gray).name(), "isotropic");
+    }
 }
@@ -0,0 +1,162 @@
+use crate::aabb::Aabb;
+use crate::interval::Interval;
+use crate::material::{Isotropic, Material};
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+use crate::world::{HitRecord, HitResult, Hittable, Ray};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+
+// Fog or smoke of uniform density filling `boundary`, which must be closed and convex.
+// A ray travelling through it scatters after an exponentially distributed distance, so
+// thin media let most light straight through.
+pub struct ConstantMedium {
+    pub boundary: Arc<dyn Hittable>,
+    neg_inv_density: f64,
+    pub phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Hittable>, density: f64, albedo: Vec3) -> Self {
+        return Self::with_phase(boundary, density, Arc::new(Isotropic::new(albedo)));
+    }
+
+    pub fn textured(boundary: Arc<dyn Hittable>, density: f64, albedo: Arc<dyn Texture>) -> Self {
+        return Self::with_phase(boundary, density, Arc::new(Isotropic::textured(albedo)));
+    }
+
+    // A density that is not positive, which would scatter rays before they enter the
+    // volume, is taken as 0: the medium is empty and every ray passes through
+    pub fn with_phase(
+        boundary: Arc<dyn Hittable>,
+        density: f64,
+        phase_function: Arc<dyn Material>,
+    ) -> Self {
+        let neg_inv_density = if density > 0.0 {
+            -1.0 / density
+        } else {
+            f64::NEG_INFINITY
+        };
+        return Self {
+            boundary,
+            neg_inv_density,
+            phase_function,
+        };
+    }
+
+    // `hit` gets no generator, so the scatter distance is drawn from one seeded by the
+    // ray itself. Renders stay reproducible, and distinct rays still get independent draws.
+    fn random_for(ray: &Ray) -> f64 {
+        let mut hasher = DefaultHasher::new();
+        for c in 0..3 {
+            ray.origin[c].to_bits().hash(&mut hasher);
+            ray.dir[c].to_bits().hash(&mut hasher);
+        }
+        ray.time.to_bits().hash(&mut hasher);
+        let mut rng = StdRng::seed_from_u64(hasher.finish());
+        // In (0, 1], so the logarithm below stays finite
+        return 1.0 - rng.random::<f64>();
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
+        if self.neg_inv_density == f64::NEG_INFINITY {
+            return HitResult::Miss;
+        }
+        // Where the ray enters and leaves the boundary, ignoring `interval` for now so
+        // rays starting inside the medium are handled too
+        let mut enter = match self.boundary.hit(ray, Interval::MAX) {
+            HitResult::Hit(rec) => rec.t,
+            HitResult::Miss => return HitResult::Miss,
+        };
+        let mut exit = match self
+            .boundary
+            .hit(ray, Interval::new(enter + 0.0001, f64::INFINITY))
+        {
+            HitResult::Hit(rec) => rec.t,
+            HitResult::Miss => return HitResult::Miss,
+        };
+
+        enter = enter.max(interval.min).max(0.0);
+        exit = exit.min(interval.max);
+        if enter >= exit {
+            return HitResult::Miss;
+        }
+
+        let ray_length = ray.dir.length();
+        let distance_inside = (exit - enter) * ray_length;
+        let hit_distance = self.neg_inv_density * Self::random_for(ray).ln();
+        if hit_distance > distance_inside {
+            return HitResult::Miss;
+        }
+
+        let mut rec = HitRecord::new();
+        rec.t = enter + hit_distance / ray_length;
+        rec.point = ray.at(rec.t);
+        // A volume has no surface; any normal will do for the isotropic phase function
+        rec.normal = Vec3::new(1.0, 0.0, 0.0);
+        rec.front_face = true;
+        rec.material = Arc::clone(&self.phase_function);
+        return HitResult::Hit(rec);
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        return self.boundary.bounding_box();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::world::Sphere;
+
+    // How many of a bundle of rays through the middle of a unit sphere of fog scatter
+    fn scatter_count(density: f64) -> usize {
+        let shell: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+        let boundary = Arc::new(Sphere::new(Vec3::EMPTY, 1.0, &shell));
+        let fog = ConstantMedium::new(boundary, density, Vec3::new(1.0, 1.0, 1.0));
+        return (0..1000)
+            .filter(|i| {
+                let offset = (*i as f64 / 1000.0 - 0.5) * 0.2;
+                let ray = Ray::new(Vec3::new(offset, -offset, 5.0), Vec3::new(0.0, 0.0, -1.0));
+                return matches!(fog.hit(&ray, Interval::ALMOST_FORWARD), HitResult::Hit(_));
+            })
+            .count();
+    }
+
+    #[test]
+    fn denser_media_scatter_more_often() {
+        let (thin, dense) = (scatter_count(0.2), scatter_count(5.0));
+        assert!(0 < thin && thin < dense, "thin {} dense {}", thin, dense);
+        // About 1 - e^(-0.4) and 1 - e^(-10) of rays crossing two units of fog
+        assert!((250..420).contains(&thin), "thin {}", thin);
+        assert!(dense > 990, "dense {}", dense);
+    }
+
+    #[test]
+    fn non_positive_densities_leave_the_medium_empty() {
+        for density in [0.0, -0.5, -100.0, f64::NAN] {
+            assert_eq!(scatter_count(density), 0, "density {}", density);
+        }
+    }
+
+    #[test]
+    fn scatter_points_lie_inside_the_boundary() {
+        let shell: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+        let boundary = Arc::new(Sphere::new(Vec3::EMPTY, 1.0, &shell));
+        let fog = ConstantMedium::new(boundary, 1.0, Vec3::new(1.0, 1.0, 1.0));
+        for i in 0..200 {
+            let ray = Ray::new(
+                Vec3::new(0.0, 0.0, 5.0 + i as f64),
+                Vec3::new(0.0, 0.0, -1.0),
+            );
+            if let HitResult::Hit(rec) = fog.hit(&ray, Interval::ALMOST_FORWARD) {
+                assert!(rec.point.length() <= 1.0 + 1e-9, "{:?}", rec.point);
+            }
+        }
+    }
+}
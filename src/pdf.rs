@@ -0,0 +1,91 @@
+use crate::vec3::{Onb, Vec3};
+use crate::world::Hittable;
+use rand::{Rng, RngCore};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Probability density over directions, used to aim bounce rays where the light is
+pub trait Pdf {
+    // Density of `generate` returning `direction`, per steradian
+    fn value(&self, direction: Vec3) -> f64;
+    fn generate(&self, rng: &mut dyn RngCore) -> Vec3;
+}
+
+// Cosine-weighted hemisphere about a normal, the distribution Lambertian scatter follows
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(normal: Vec3) -> Self {
+        return Self {
+            uvw: Onb::new(normal),
+        };
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let cosine = Vec3::dot(direction.unit(), self.uvw.w);
+        return cosine.max(0.0) / PI;
+    }
+
+    fn generate(&self, rng: &mut dyn RngCore) -> Vec3 {
+        return self.uvw.transform(Vec3::random_cosine_direction(rng));
+    }
+}
+
+// Directions from `origin` toward one of `objects`, each picked with equal chance
+pub struct HittablePdf<'a> {
+    objects: &'a [Arc<dyn Hittable>],
+    origin: Vec3,
+}
+
+impl<'a> HittablePdf<'a> {
+    // `objects` must not be empty
+    pub fn new(objects: &'a [Arc<dyn Hittable>], origin: Vec3) -> Self {
+        return Self { objects, origin };
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        let total: f64 = self
+            .objects
+            .iter()
+            .map(|o| o.pdf_value(self.origin, direction))
+            .sum();
+        return total / self.objects.len() as f64;
+    }
+
+    fn generate(&self, rng: &mut dyn RngCore) -> Vec3 {
+        let index = rng.random_range(0..self.objects.len());
+        return self.objects[index].random(self.origin, rng);
+    }
+}
+
+// Even blend of two densities, sampling either with probability one half
+pub struct MixturePdf<'a> {
+    a: &'a dyn Pdf,
+    b: &'a dyn Pdf,
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(a: &'a dyn Pdf, b: &'a dyn Pdf) -> Self {
+        return Self { a, b };
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        return 0.5 * self.a.value(direction) + 0.5 * self.b.value(direction);
+    }
+
+    fn generate(&self, rng: &mut dyn RngCore) -> Vec3 {
+        return if rng.random::<f64>() < 0.5 {
+            self.a.generate(rng)
+        } else {
+            self.b.generate(rng)
+        };
+    }
+}
@@ -0,0 +1,167 @@
+use crate::vec3::Vec3;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+const POINT_COUNT: usize = 256;
+
+// Gradient noise on an integer lattice. Each lattice point gets a random unit vector,
+// picked through three hashed permutations, and values in between are blended smoothly.
+pub struct Perlin {
+    randvec: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new() -> Self {
+        return Self::with_seed(0);
+    }
+
+    // The same seed always gives the same noise, so renders are reproducible
+    pub fn with_seed(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let randvec = (0..POINT_COUNT)
+            .map(|_| {
+                Vec3::new(
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(-1.0..1.0),
+                )
+                .unit()
+            })
+            .collect();
+
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            p.shuffle(&mut rng);
+            return p;
+        };
+        let perm_x = permutation();
+        let perm_y = permutation();
+        let perm_z = permutation();
+
+        return Self {
+            randvec,
+            perm_x,
+            perm_y,
+            perm_z,
+        };
+    }
+
+    // Noise value at `p`, roughly in [-1, 1]
+    pub fn noise(&self, p: Vec3) -> f64 {
+        let floor = Vec3::new(p.x().floor(), p.y().floor(), p.z().floor());
+        let frac = p - floor;
+        let (i, j, k) = (floor.x() as i64, floor.y() as i64, floor.z() as i64);
+
+        let mut c = [[[Vec3::EMPTY; 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let hash = self.perm_x[Self::wrap(i + di as i64)]
+                        ^ self.perm_y[Self::wrap(j + dj as i64)]
+                        ^ self.perm_z[Self::wrap(k + dk as i64)];
+                    *corner = self.randvec[hash];
+                }
+            }
+        }
+
+        return Self::interpolate(&c, frac);
+    }
+
+    // Sum of `depth` octaves of noise, each at twice the frequency and half the weight
+    // of the last. Always non-negative.
+    pub fn turbulence(&self, p: Vec3, depth: i64) -> f64 {
+        let mut sum = 0.0;
+        let mut point = p;
+        let mut weight = 1.0;
+
+        for _ in 0..depth {
+            sum += weight * self.noise(point);
+            weight *= 0.5;
+            point *= 2.0;
+        }
+
+        return sum.abs();
+    }
+
+    fn wrap(i: i64) -> usize {
+        return i.rem_euclid(POINT_COUNT as i64) as usize;
+    }
+
+    // Trilinear blend of the corner gradients, eased with a Hermite cubic to hide the
+    // lattice
+    fn interpolate(c: &[[[Vec3; 2]; 2]; 2], frac: Vec3) -> f64 {
+        let ease = |t: f64| t * t * (3.0 - 2.0 * t);
+        let (uu, vv, ww) = (ease(frac.x()), ease(frac.y()), ease(frac.z()));
+
+        let mut sum = 0.0;
+        for (i, plane) in c.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, gradient) in row.iter().enumerate() {
+                    let (fi, fj, fk) = (i as f64, j as f64, k as f64);
+                    let weight = Vec3::new(frac.x() - fi, frac.y() - fj, frac.z() - fk);
+                    sum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * Vec3::dot(*gradient, weight);
+                }
+            }
+        }
+        return sum;
+    }
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_points() -> impl Iterator<Item = Vec3> {
+        let mut rng = StdRng::seed_from_u64(9);
+        return (0..5000).map(move |_| Vec3::random_range(&mut rng, -50.0, 50.0));
+    }
+
+    #[test]
+    fn noise_stays_within_bounds() {
+        let perlin = Perlin::new();
+        for p in sample_points() {
+            let n = perlin.noise(p);
+            assert!((-1.0..=1.0).contains(&n), "noise {} at {:?}", n, p);
+            // Octave weights sum to less than 2
+            let t = perlin.turbulence(p, 7);
+            assert!((0.0..2.0).contains(&t), "turbulence {} at {:?}", t, p);
+        }
+    }
+
+    #[test]
+    fn noise_is_zero_on_the_lattice() {
+        let perlin = Perlin::new();
+        for p in [
+            Vec3::EMPTY,
+            Vec3::new(3.0, -7.0, 12.0),
+            Vec3::new(-300.0, 5.0, 1.0),
+        ] {
+            assert_eq!(perlin.noise(p), 0.0);
+        }
+    }
+
+    #[test]
+    fn seeds_make_noise_reproducible() {
+        let (a, b, c) = (
+            Perlin::with_seed(5),
+            Perlin::with_seed(5),
+            Perlin::with_seed(6),
+        );
+        let p = Vec3::new(1.3, 2.7, -0.4);
+        assert_eq!(a.noise(p), b.noise(p));
+        assert_ne!(a.noise(p), c.noise(p));
+    }
+}
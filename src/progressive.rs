@@ -0,0 +1,133 @@
+use crate::accumulator::Accumulator;
+use crate::buffer::PixelBuffer;
+use crate::camera::Camera;
+use crate::world::HittableList;
+
+// Renders an image a few samples at a time, so a viewer can show it converging. Samples
+// pile up in linear space; tone mapping happens only when reading the image out.
+pub struct ProgressiveRenderer {
+    pub camera: Camera,
+    pub world: HittableList,
+    acc: Accumulator,
+    // Fixed for the renderer's lifetime so every call continues the same sequence
+    seed: u64,
+    samples: i64,
+}
+
+impl ProgressiveRenderer {
+    // Uses the camera's seed, or a random one if it has none. `add_samples` decides how
+    // many samples are taken, so the camera's `samples_per_pixel` is unused, and samples
+    // are not stratified since the final count is open. Fails like the camera's renders
+    // if `Camera::check_size` rejects the resolution.
+    pub fn new(camera: Camera, world: HittableList) -> Result<Self, String> {
+        camera.check_size()?;
+        let acc = Accumulator::new(camera.image_width, camera.image_height);
+        let seed = camera.seed.unwrap_or_else(rand::random);
+        return Ok(Self {
+            camera,
+            world,
+            acc,
+            seed,
+            samples: 0,
+        });
+    }
+
+    // Takes `n` more samples in every pixel
+    pub fn add_samples(&mut self, n: i64) {
+        let n = n.max(0);
+        self.camera
+            .accumulate_range(&self.world, &mut self.acc, self.seed, self.samples, n);
+        self.samples += n;
+    }
+
+    // Samples per pixel taken so far
+    pub fn samples(&self) -> i64 {
+        return self.samples;
+    }
+
+    // Linear per-pixel averages so far, black before the first samples
+    pub fn current_image(&self) -> PixelBuffer {
+        return self.acc.to_buffer();
+    }
+
+    // The current image tone-mapped with the camera's curve, as RGBA bytes for display
+    pub fn current_rgba8(&self) -> Vec<u8> {
+        return self.current_image().to_rgba8_with(self.camera.tonemap);
+    }
+
+    // Drops every sample, e.g. after moving the camera or editing the world. Fails, keeping
+    // the samples, if the camera has since been given a size `Camera::check_size` rejects.
+    pub fn reset(&mut self) -> Result<(), String> {
+        self.camera.check_size()?;
+        self.acc = Accumulator::new(self.camera.image_width, self.camera.image_height);
+        self.samples = 0;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, Material};
+    use crate::vec3::Vec3;
+    use crate::world::Sphere;
+    use std::sync::Arc;
+
+    fn renderer() -> ProgressiveRenderer {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, -2.0),
+            1.0,
+            &material,
+        )));
+        let camera = Camera::new().with_resolution(6, 4).with_seed(3);
+        return ProgressiveRenderer::new(camera, world).unwrap();
+    }
+
+    #[test]
+    fn two_passes_of_ten_match_one_of_twenty() {
+        let mut split = renderer();
+        split.add_samples(10);
+        split.add_samples(10);
+        let mut whole = renderer();
+        whole.add_samples(20);
+        assert_eq!(split.samples(), 20);
+
+        let (a, b) = (split.current_image(), whole.current_image());
+        for y in 0..4 {
+            for x in 0..6 {
+                assert!(
+                    (a.get(x, y) - b.get(x, y)).length() < 1e-12,
+                    "pixel {} {}",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reset_drops_every_sample() {
+        let mut progressive = renderer();
+        progressive.add_samples(3);
+        assert_ne!(progressive.current_image().get(3, 2), Vec3::EMPTY);
+        progressive.reset().unwrap();
+        assert_eq!(progressive.samples(), 0);
+        assert_eq!(progressive.current_image().get(3, 2), Vec3::EMPTY);
+    }
+
+    #[test]
+    fn sizes_over_the_pixel_limit_are_rejected() {
+        let camera = Camera::new().with_resolution(20, 20).with_max_pixels(100);
+        let message = camera.check_size().err();
+        let result = ProgressiveRenderer::new(camera, HittableList::new());
+        assert_eq!(result.err(), message);
+
+        let mut progressive = renderer();
+        progressive.add_samples(1);
+        progressive.camera = progressive.camera.clone().with_max_pixels(10);
+        assert!(progressive.reset().is_err());
+        assert_eq!(progressive.samples(), 1);
+    }
+}
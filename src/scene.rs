@@ -0,0 +1,722 @@
+use crate::background::Background;
+use crate::buffer::PixelBuffer;
+use crate::camera::{Camera, PixelFilter, Projection, SamplingStrategy};
+use crate::instance::{RotateY, Translate};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::texture::{
+    CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture, TextureFilter, WrapMode,
+};
+use crate::vec3::Vec3;
+use crate::world::{
+    BoxPrim, Cylinder, Disk, Hittable, HittableList, MovingSphere, ObjError, Plane, PointLight,
+    Polygon, Quad, Sphere, Triangle,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Obj(String, ObjError),
+    // Well-formed JSON that does not describe a valid scene
+    Invalid(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            SceneError::Io(e) => write!(f, "could not read scene: {}", e),
+            SceneError::Json(e) => write!(f, "invalid JSON: {}", e),
+            SceneError::Obj(path, e) => write!(f, "{}: {}", path, e),
+            SceneError::Invalid(message) => write!(f, "invalid scene: {}", message),
+        };
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(e: io::Error) -> Self {
+        return SceneError::Io(e);
+    }
+}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(e: serde_json::Error) -> Self {
+        return SceneError::Json(e);
+    }
+}
+
+// Everything needed to render an image
+pub struct Scene {
+    pub camera: Camera,
+    pub world: HittableList,
+    // Takes the place of the camera's own background when rendering
+    pub background: Background,
+    // Problems that did not stop the scene loading, such as image files that could not
+    // be read and render as magenta
+    pub warnings: Vec<String>,
+}
+
+impl Scene {
+    pub fn new(camera: Camera, world: HittableList, background: Background) -> Self {
+        return Self {
+            camera,
+            world,
+            background,
+            warnings: Vec::new(),
+        };
+    }
+
+    // The built-in demo seen through `camera`: metal spheres and a triangle resting on a
+    // large ground sphere, under the default sky
+    pub fn demo(camera: Camera) -> Self {
+        return Self::new(camera, demo_world(), Background::default());
+    }
+
+    // Loads a scene file; see `scenes/example.json` for the format. OBJ paths are resolved
+    // relative to the directory containing the scene file.
+    pub fn load(path: &str) -> Result<Self, SceneError> {
+        let text = fs::read_to_string(path)?;
+        let base = Path::new(path).parent().unwrap_or(Path::new(""));
+        return Self::from_json(&text, base);
+    }
+
+    // Builds a scene from JSON text, resolving OBJ and image paths against `base`
+    pub fn from_json(text: &str, base: &Path) -> Result<Self, SceneError> {
+        return parse_scene(text, base);
+    }
+
+    // The camera as configured for rendering this scene
+    pub fn render_camera(&self) -> Camera {
+        return self.camera.clone().with_background(self.background.clone());
+    }
+
+    // Renders on every available core, failing if `Camera::check_size` rejects the
+    // resolution
+    pub fn render(&self) -> Result<PixelBuffer, SceneError> {
+        return self
+            .render_camera()
+            .render_parallel(&self.world)
+            .map_err(SceneError::Invalid);
+    }
+
+    // One-line description of the render `render` would do: resolution, samples, depth,
+    // primitive and light counts, threads and estimated memory
+    pub fn summary(&self) -> String {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        return self.render_camera().summary(&self.world, threads as i64);
+    }
+}
+
+fn demo_world() -> HittableList {
+    let mut world = HittableList::new();
+
+    // Materials
+    let material_ground: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.8, 0.8, 0.0)));
+    let material_center: Arc<dyn Material> = Arc::new(Metal::new(Vec3::new(0.1, 0.2, 0.5), 0.1));
+    let material_left: Arc<dyn Material> = Arc::new(Metal::new(Vec3::new(0.8, 0.8, 0.8), 0.3));
+    let material_right: Arc<dyn Material> = Arc::new(Metal::new(Vec3::new(0.8, 0.6, 0.2), 1.0));
+
+    // Scene objects
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(0.0, -100.5, -1.0),
+        100.0,
+        &material_ground,
+    )));
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(0.0, 0.0, -1.2),
+        0.5,
+        &material_center,
+    )));
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(-1.0, 0.0, -1.0),
+        0.5,
+        &material_left,
+    )));
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(1.0, 0.0, -1.0),
+        0.5,
+        &material_right,
+    )));
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(0.5, 0.0, -1.2),
+        0.05,
+        &material_left,
+    )));
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(-0.5, 0.0, -1.2),
+        0.05,
+        &material_right,
+    )));
+    world.add(Arc::new(Triangle::new(
+        Vec3::new(0.7, -0.2, -1.2),
+        Vec3::new(-0.7, -0.2, -0.8),
+        Vec3::new(0.0, 0.7, -1.5),
+        &material_right,
+    )));
+
+    world.build_bvh();
+    return world;
+}
+
+// Loads a scene file as a camera and world, with the background set on the camera
+pub fn load_json(path: &str) -> Result<(Camera, HittableList), SceneError> {
+    let scene = Scene::load(path)?;
+    return Ok((scene.render_camera(), scene.world));
+}
+
+// Like `load_json`, from JSON text with paths resolved against `base`
+pub fn parse_json(text: &str, base: &Path) -> Result<(Camera, HittableList), SceneError> {
+    let scene = Scene::from_json(text, base)?;
+    return Ok((scene.render_camera(), scene.world));
+}
+
+fn parse_scene(text: &str, base: &Path) -> Result<Scene, SceneError> {
+    // serde_json stops at 128 levels of nesting, so hostile input cannot overflow the stack
+    let doc: Value = serde_json::from_str(text)?;
+    let mut warnings = Vec::new();
+
+    let camera = match doc.get("camera") {
+        Some(c) => parse_camera(c)?,
+        None => Camera::new(),
+    };
+    let background = match doc.get("background") {
+        Some(_) => parse_background(&doc, base, &mut warnings)?,
+        None => Background::default(),
+    };
+
+    let mut materials: HashMap<&str, Arc<dyn Material>> = HashMap::new();
+    if let Some(members) = doc.get("materials") {
+        let members = members
+            .as_object()
+            .ok_or_else(|| invalid("\"materials\" must be an object"))?;
+        for (name, m) in members {
+            materials.insert(name.as_str(), parse_material(name, m, base, &mut warnings)?);
+        }
+    }
+
+    let mut world = HittableList::new();
+    let objects = match doc.get("objects") {
+        Some(o) => o
+            .as_array()
+            .map(Vec::as_slice)
+            .ok_or_else(|| invalid("\"objects\" must be an array"))?,
+        None => &[],
+    };
+    for (i, o) in objects.iter().enumerate() {
+        let context = format!("objects[{}]", i);
+        let name = string(o, "material", &context)?;
+        let material = materials
+            .get(name)
+            .ok_or_else(|| invalid(&format!("{}: unknown material {:?}", context, name)))?;
+
+        let mut object: Arc<dyn Hittable> = match string(o, "type", &context)? {
+            "sphere" => Arc::new(Sphere::new(
+                vec3(o, "center", &context)?,
+                number(o, "radius", &context)?,
+                material,
+            )),
+            "moving_sphere" => Arc::new(MovingSphere::new(
+                vec3(o, "center0", &context)?,
+                vec3(o, "center1", &context)?,
+                optional_number(o, "time0", &context)?.unwrap_or(0.0),
+                optional_number(o, "time1", &context)?.unwrap_or(1.0),
+                number(o, "radius", &context)?,
+                material,
+            )),
+            "triangle" => Arc::new(
+                Triangle::new(
+                    vec3(o, "a", &context)?,
+                    vec3(o, "b", &context)?,
+                    vec3(o, "c", &context)?,
+                    material,
+                )
+                .with_backface_culling(
+                    optional_bool(o, "cull_backfaces", &context)?.unwrap_or(false),
+                ),
+            ),
+            "plane" => Arc::new(Plane::new(
+                vec3(o, "point", &context)?,
+                vec3(o, "normal", &context)?,
+                material,
+            )),
+            "box" => Arc::new(BoxPrim::new(
+                vec3(o, "min", &context)?,
+                vec3(o, "max", &context)?,
+                material,
+            )),
+            "quad" => Arc::new(Quad::new(
+                vec3(o, "q", &context)?,
+                vec3(o, "u", &context)?,
+                vec3(o, "v", &context)?,
+                material,
+            )),
+            "disk" => Arc::new(Disk::new(
+                vec3(o, "center", &context)?,
+                vec3(o, "normal", &context)?,
+                number(o, "radius", &context)?,
+                material,
+            )),
+            "cylinder" => Arc::new(
+                Cylinder::new(
+                    vec3(o, "base", &context)?,
+                    vec3(o, "axis", &context)?,
+                    number(o, "radius", &context)?,
+                    number(o, "height", &context)?,
+                    material,
+                )
+                .with_caps(optional_bool(o, "capped", &context)?.unwrap_or(true)),
+            ),
+            "obj" => {
+                let obj_path = base.join(string(o, "path", &context)?);
+                let shown = obj_path.display().to_string();
+                let file = File::open(&obj_path)
+                    .map_err(|e| SceneError::Obj(shown.clone(), ObjError::Io(e)))?;
+                let mesh = Polygon::from_reader(BufReader::new(file), material)
+                    .map_err(|e| SceneError::Obj(shown, e))?;
+                Arc::new(mesh)
+            }
+            other => {
+                return Err(invalid(&format!(
+                    "{}: unknown object type {:?}",
+                    context, other
+                )))
+            }
+        };
+
+        // Optional placement, applied as rotate then translate
+        if let Some(angle) = optional_number(o, "rotate_y", &context)? {
+            object = Arc::new(RotateY::new(object, angle));
+        }
+        if let Some(offset) = optional_vec3(o, "translate", &context)? {
+            object = Arc::new(Translate::new(object, offset));
+        }
+        if optional_bool(o, "area_light", &context)?.unwrap_or(false) {
+            world.add_area_light(Arc::clone(&object));
+        }
+        world.add(object);
+    }
+
+    if let Some(lights) = doc.get("lights") {
+        let lights = lights
+            .as_array()
+            .ok_or_else(|| invalid("\"lights\" must be an array"))?;
+        for (i, l) in lights.iter().enumerate() {
+            let context = format!("lights[{}]", i);
+            world.add_light(PointLight::new(
+                vec3(l, "position", &context)?,
+                optional_vec3(l, "color", &context)?.unwrap_or(Vec3::new(1.0, 1.0, 1.0)),
+                number(l, "intensity", &context)?,
+            ));
+        }
+    }
+
+    world.build_bvh();
+    let mut scene = Scene::new(camera, world, background);
+    scene.warnings = warnings;
+    return Ok(scene);
+}
+
+fn parse_camera(c: &Value) -> Result<Camera, SceneError> {
+    let context = "camera";
+    let default = Camera::new();
+    let mut camera = Camera::look_at(
+        optional_vec3(c, "lookfrom", context)?.unwrap_or(default.center),
+        optional_vec3(c, "lookat", context)?.unwrap_or(default.lookat),
+        optional_vec3(c, "vup", context)?.unwrap_or(default.vup),
+        optional_number(c, "vfov", context)?.unwrap_or(default.vfov),
+        1.0,
+    );
+
+    let width = optional_number(c, "width", context)?.unwrap_or(camera.image_width as f64);
+    let height = optional_number(c, "height", context)?.unwrap_or(camera.image_height as f64);
+    camera = camera.with_resolution(width as i64, height as i64);
+
+    if let Some(angle) = optional_number(c, "defocus_angle", context)? {
+        let focus_dist = optional_number(c, "focus_dist", context)?.unwrap_or(camera.focus_dist);
+        camera = camera.with_defocus(angle, focus_dist);
+    }
+    if let Some(samples) = optional_number(c, "samples_per_pixel", context)? {
+        camera = camera.with_samples(samples as i64);
+    }
+    if let Some(seed) = optional_number(c, "seed", context)? {
+        camera = camera.with_seed(seed as u64);
+    }
+    if let Some(adaptive) = c.get("adaptive") {
+        let context = "camera.adaptive";
+        camera = camera.with_adaptive(
+            number(adaptive, "min_samples", context)? as i64,
+            number(adaptive, "max_samples", context)? as i64,
+            number(adaptive, "tolerance", context)?,
+        );
+    }
+    if let Some(sampling) = c.get("sampling") {
+        camera = camera.with_sampling(match sampling.as_str() {
+            Some("uniform") => SamplingStrategy::Uniform,
+            Some("stratified") => SamplingStrategy::Stratified,
+            _ => return Err(missing(context, "sampling")),
+        });
+    }
+    if let Some(filter) = c.get("filter") {
+        let context = "camera.filter";
+        camera = camera.with_filter(match string(filter, "type", context)? {
+            "box" => PixelFilter::Box,
+            "tent" => PixelFilter::Tent,
+            "gaussian" => {
+                let sigma = optional_number(filter, "sigma", context)?.unwrap_or(0.5);
+                if sigma <= 0.0 {
+                    return Err(invalid(&format!("{}: sigma must be positive", context)));
+                }
+                PixelFilter::Gaussian { sigma }
+            }
+            other => return Err(invalid(&format!("{}: unknown type {:?}", context, other))),
+        });
+    }
+    let shutter_open = optional_number(c, "shutter_open", context)?;
+    let shutter_close = optional_number(c, "shutter_close", context)?;
+    if shutter_open.is_some() || shutter_close.is_some() {
+        let open = shutter_open.unwrap_or(0.0);
+        camera = camera.with_shutter(open, shutter_close.unwrap_or(open));
+    }
+    if let Some(projection) = c.get("projection") {
+        let context = "camera.projection";
+        camera = camera.with_projection(match string(projection, "type", context)? {
+            "perspective" => Projection::Perspective,
+            "orthographic" => Projection::Orthographic {
+                scale: number(projection, "scale", context)?,
+            },
+            other => return Err(invalid(&format!("{}: unknown type {:?}", context, other))),
+        });
+    }
+    if let Some(enabled) = optional_bool(c, "ray_differentials", context)? {
+        camera = camera.with_ray_differentials(enabled);
+    }
+    if let Some(min_bounces) = optional_number(c, "russian_roulette", context)? {
+        camera = camera.with_russian_roulette(min_bounces as i64);
+    }
+    if let Some(depth) = optional_number(c, "max_depth", context)? {
+        camera = camera.with_max_depth(depth as i64);
+    }
+    if let Some(gamma) = optional_number(c, "gamma", context)? {
+        if gamma <= 0.0 {
+            return Err(invalid(&format!("{}: gamma must be positive", context)));
+        }
+        camera = camera.with_gamma(gamma);
+    }
+
+    return Ok(camera);
+}
+
+// Either a color array, or an object with a "type" of gradient or environment
+fn parse_background(
+    doc: &Value,
+    base: &Path,
+    warnings: &mut Vec<String>,
+) -> Result<Background, SceneError> {
+    let key = "background";
+    let b = doc.get(key).ok_or_else(|| missing("scene", key))?;
+    if b.as_array().is_some() {
+        return Ok(Background::SolidColor(vec3(doc, key, "scene")?));
+    }
+
+    return Ok(match string(b, "type", key)? {
+        "gradient" => Background::Gradient(vec3(b, "bottom", key)?, vec3(b, "top", key)?),
+        "environment" => {
+            let path = base.join(string(b, "path", key)?);
+            let image = open_image(&path, warnings).with_wrap(WrapMode::RepeatU);
+            Background::Environment(Arc::new(image))
+        }
+        other => return Err(invalid(&format!("{}: unknown type {:?}", key, other))),
+    });
+}
+
+fn parse_material(
+    name: &str,
+    m: &Value,
+    base: &Path,
+    warnings: &mut Vec<String>,
+) -> Result<Arc<dyn Material>, SceneError> {
+    let context = format!("materials.{}", name);
+    let context = context.as_str();
+    return Ok(match string(m, "type", context)? {
+        "lambertian" => Arc::new(Lambertian::textured(texture(
+            m, "albedo", context, base, warnings,
+        )?)),
+        "metal" => Arc::new(
+            Metal::new(
+                vec3(m, "albedo", context)?,
+                optional_number(m, "fuzz", context)?.unwrap_or(0.0),
+            )
+            .with_f0(optional_number(m, "f0", context)?.unwrap_or(1.0)),
+        ),
+        "dielectric" => {
+            let ri = number(m, "refraction_index", context)?;
+            match optional_vec3(m, "albedo", context)? {
+                Some(albedo) => Arc::new(Dielectric::colored(albedo, ri)),
+                None => Arc::new(Dielectric::new(ri)),
+            }
+        }
+        "diffuse_light" => Arc::new(DiffuseLight::new(vec3(m, "emit", context)?)),
+        other => {
+            return Err(invalid(&format!(
+                "{}: unknown material type {:?}",
+                context, other
+            )))
+        }
+    });
+}
+
+// A texture is either a plain color array or an object with a "type". Image paths are
+// resolved against `base` like OBJ paths.
+fn texture(
+    v: &Value,
+    key: &str,
+    context: &str,
+    base: &Path,
+    warnings: &mut Vec<String>,
+) -> Result<Arc<dyn Texture>, SceneError> {
+    let t = v.get(key).ok_or_else(|| missing(context, key))?;
+    if t.as_array().is_some() {
+        return Ok(Arc::new(SolidColor::new(vec3(v, key, context)?)));
+    }
+
+    let context = format!("{}.{}", context, key);
+    let context = context.as_str();
+    return Ok(match string(t, "type", context)? {
+        "solid" => Arc::new(SolidColor::new(vec3(t, "color", context)?)),
+        "checker" => Arc::new(CheckerTexture::new(
+            number(t, "scale", context)?,
+            texture(t, "even", context, base, warnings)?,
+            texture(t, "odd", context, base, warnings)?,
+        )),
+        "noise" => {
+            let scale = optional_number(t, "scale", context)?.unwrap_or(1.0);
+            match optional_number(t, "turbulence", context)? {
+                Some(octaves) => Arc::new(NoiseTexture::marble(scale, octaves as i64)),
+                None => Arc::new(NoiseTexture::new(scale)),
+            }
+        }
+        "image" => {
+            let path = base.join(string(t, "path", context)?);
+            let mut image = open_image(&path, warnings);
+            if let Some(wrap) = t.get("wrap") {
+                image = image.with_wrap(match wrap.as_str() {
+                    Some("clamp") => WrapMode::Clamp,
+                    Some("repeat") => WrapMode::Repeat,
+                    _ => return Err(missing(context, "wrap")),
+                });
+            }
+            if let Some(filter) = t.get("filter") {
+                image = image.with_filter(match filter.as_str() {
+                    Some("nearest") => TextureFilter::Nearest,
+                    Some("bilinear") => TextureFilter::Bilinear,
+                    _ => return Err(missing(context, "filter")),
+                });
+            }
+            if let Some(texel_size) = optional_number(t, "texel_size", context)? {
+                if texel_size <= 0.0 {
+                    return Err(invalid(&format!(
+                        "{}: texel_size must be positive",
+                        context
+                    )));
+                }
+                image = image.with_mipmaps(texel_size);
+            }
+            Arc::new(image)
+        }
+        other => {
+            return Err(invalid(&format!(
+                "{}: unknown texture type {:?}",
+                context, other
+            )))
+        }
+    });
+}
+
+// An image texture, or the magenta stand-in with a warning if the file cannot be read
+fn open_image(path: &Path, warnings: &mut Vec<String>) -> ImageTexture {
+    return match ImageTexture::open(path) {
+        Ok(image) => image,
+        Err(e) => {
+            warnings.push(format!("could not load texture {}: {}", path.display(), e));
+            ImageTexture::missing()
+        }
+    };
+}
+
+fn invalid(message: &str) -> SceneError {
+    return SceneError::Invalid(message.to_string());
+}
+
+fn missing(context: &str, key: &str) -> SceneError {
+    return invalid(&format!("{}: missing or malformed {:?}", context, key));
+}
+
+fn string<'a>(v: &'a Value, key: &str, context: &str) -> Result<&'a str, SceneError> {
+    return v
+        .get(key)
+        .and_then(Value::as_str)
+        .ok_or_else(|| missing(context, key));
+}
+
+fn number(v: &Value, key: &str, context: &str) -> Result<f64, SceneError> {
+    return optional_number(v, key, context)?.ok_or_else(|| missing(context, key));
+}
+
+fn optional_number(v: &Value, key: &str, context: &str) -> Result<Option<f64>, SceneError> {
+    return match v.get(key) {
+        Some(n) => n.as_f64().map(Some).ok_or_else(|| missing(context, key)),
+        None => Ok(None),
+    };
+}
+
+fn optional_bool(v: &Value, key: &str, context: &str) -> Result<Option<bool>, SceneError> {
+    return match v.get(key) {
+        Some(b) => b.as_bool().map(Some).ok_or_else(|| missing(context, key)),
+        None => Ok(None),
+    };
+}
+
+fn vec3(v: &Value, key: &str, context: &str) -> Result<Vec3, SceneError> {
+    return optional_vec3(v, key, context)?.ok_or_else(|| missing(context, key));
+}
+
+// Vectors are written as three-element number arrays
+fn optional_vec3(v: &Value, key: &str, context: &str) -> Result<Option<Vec3>, SceneError> {
+    let items = match v.get(key) {
+        Some(items) => items.as_array().ok_or_else(|| missing(context, key))?,
+        None => return Ok(None),
+    };
+    let components: Option<Vec<f64>> = items.iter().map(Value::as_f64).collect();
+    let components: Option<[f64; 3]> = components.and_then(|c| c.try_into().ok());
+    return match components {
+        Some(xyz) => Ok(Some(Vec3::from(xyz))),
+        None => Err(missing(context, key)),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene(json: &str) -> Scene {
+        return Scene::from_json(json, Path::new("")).unwrap();
+    }
+
+    #[test]
+    fn render_rejects_a_negative_resolution() {
+        let scene = scene(r#"{"camera": {"width": -5, "height": 10}}"#);
+        match scene.render() {
+            Err(SceneError::Invalid(message)) => {
+                assert_eq!(message, "image size -5x10 must be positive")
+            }
+            _ => panic!("negative width was accepted"),
+        }
+    }
+
+    #[test]
+    fn render_rejects_a_resolution_over_the_pixel_limit() {
+        let scene = scene(r#"{"camera": {"width": 16000, "height": 16000}}"#);
+        assert!(matches!(scene.render(), Err(SceneError::Invalid(_))));
+    }
+
+    #[test]
+    fn constructed_scene_renders_a_thumbnail_with_its_own_background() {
+        let backdrop = Vec3::new(0.1, 0.2, 0.3);
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.8, 0.8, 0.8)));
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, -3.0),
+            1.0,
+            &material,
+        )));
+        let camera = Camera::new()
+            .with_resolution(8, 6)
+            .with_samples(2)
+            .with_seed(1);
+        let scene = Scene::new(camera, world, Background::SolidColor(backdrop));
+
+        let thumbnail = scene.render().unwrap();
+        assert_eq!((thumbnail.width, thumbnail.height), (8, 6));
+        // The scene's background replaces the camera's default sky
+        assert_eq!(thumbnail.get(0, 0), backdrop);
+        assert_ne!(thumbnail.get(4, 3), backdrop);
+    }
+
+    #[test]
+    fn example_scene_loads_and_renders_a_thumbnail() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/example.json");
+        let mut scene = Scene::load(path).unwrap();
+        // Plane, two spheres, a triangle and the OBJ cube
+        assert_eq!(scene.world.len(), 5);
+        assert_eq!(scene.camera.samples_per_pixel, 20);
+
+        scene.camera = scene
+            .camera
+            .clone()
+            .with_resolution(16, 16)
+            .with_samples(2)
+            .with_seed(1);
+        let thumbnail = scene.render().unwrap();
+        assert_eq!((thumbnail.width, thumbnail.height), (16, 16));
+        assert_ne!(thumbnail.get(8, 8), Vec3::EMPTY);
+    }
+
+    #[test]
+    fn unreadable_images_load_as_a_warning() {
+        let scene = scene(
+            r#"{
+                "materials": {
+                    "earth": {
+                        "type": "lambertian",
+                        "albedo": { "type": "image", "path": "no/such/earth.ppm" }
+                    }
+                },
+                "objects": [
+                    { "type": "sphere", "center": [0, 0, -1], "radius": 0.5, "material": "earth" }
+                ]
+            }"#,
+        );
+        assert_eq!(scene.world.len(), 1);
+        assert_eq!(scene.warnings.len(), 1);
+        assert!(
+            scene.warnings[0].starts_with("could not load texture no/such/earth.ppm"),
+            "{}",
+            scene.warnings[0]
+        );
+    }
+
+    #[test]
+    fn missing_obj_files_are_named_in_the_error() {
+        let json = r#"{
+            "materials": { "gray": { "type": "lambertian", "albedo": [0.5, 0.5, 0.5] } },
+            "objects": [{ "type": "obj", "path": "no/such/mesh.obj", "material": "gray" }]
+        }"#;
+        let message = match Scene::from_json(json, Path::new("")) {
+            Err(e @ SceneError::Obj(..)) => e.to_string(),
+            _ => panic!("missing OBJ file was not reported as an OBJ error"),
+        };
+        assert!(message.contains("no/such/mesh.obj"), "{}", message);
+    }
+
+    #[test]
+    fn deeply_nested_json_is_an_error_rather_than_a_stack_overflow() {
+        let json = format!(
+            "{{\"objects\": {}{}}}",
+            "[".repeat(100_000),
+            "]".repeat(100_000)
+        );
+        assert!(matches!(
+            Scene::from_json(&json, Path::new("")),
+            Err(SceneError::Json(_))
+        ));
+    }
+}
@@ -0,0 +1,119 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Work counted while rendering, for finding out what makes a scene expensive. The counts
+// are only kept when built with the `stats` feature; otherwise they stay 0 and the
+// counting compiles away.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RenderStats {
+    pub elapsed: Duration,
+    // Every ray traced into the world: camera rays, bounces, shadow and occlusion rays
+    pub rays_cast: u64,
+    // Camera rays, one per pixel sample
+    pub primary_rays: u64,
+    pub ray_triangle_tests: u64,
+    pub ray_sphere_tests: u64,
+}
+
+#[derive(Copy, Clone)]
+pub(crate) enum Counter {
+    RaysCast,
+    PrimaryRays,
+    RayTriangleTests,
+    RaySphereTests,
+}
+
+// Process-wide totals, indexed by `Counter`. Shared by every thread and render, so
+// counts for renders that overlap in time get mixed.
+static COUNTERS: [AtomicU64; 4] = [const { AtomicU64::new(0) }; 4];
+
+// Adds `n` to `counter` when counting is enabled
+#[inline(always)]
+pub(crate) fn record(counter: Counter, n: u64) {
+    #[cfg(feature = "stats")]
+    COUNTERS[counter as usize].fetch_add(n, Ordering::Relaxed);
+    #[cfg(not(feature = "stats"))]
+    let _ = (counter, n);
+}
+
+impl RenderStats {
+    // Whether this build counts anything
+    pub const ENABLED: bool = cfg!(feature = "stats");
+
+    // Counts since the program started, with no elapsed time
+    pub fn totals() -> Self {
+        let load = |c: Counter| COUNTERS[c as usize].load(Ordering::Relaxed);
+        return Self {
+            elapsed: Duration::ZERO,
+            rays_cast: load(Counter::RaysCast),
+            primary_rays: load(Counter::PrimaryRays),
+            ray_triangle_tests: load(Counter::RayTriangleTests),
+            ray_sphere_tests: load(Counter::RaySphereTests),
+        };
+    }
+
+    // Counts from `earlier` to these totals, taking `elapsed` time
+    pub fn since(&self, earlier: &Self, elapsed: Duration) -> Self {
+        return Self {
+            elapsed,
+            rays_cast: self.rays_cast - earlier.rays_cast,
+            primary_rays: self.primary_rays - earlier.primary_rays,
+            ray_triangle_tests: self.ray_triangle_tests - earlier.ray_triangle_tests,
+            ray_sphere_tests: self.ray_sphere_tests - earlier.ray_sphere_tests,
+        };
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.elapsed.as_secs_f64();
+        write!(f, "{:.2} s", seconds)?;
+        if !Self::ENABLED {
+            return Ok(());
+        }
+        let rate = if seconds > 0.0 {
+            self.rays_cast as f64 / seconds / 1e6
+        } else {
+            0.0
+        };
+        return write!(
+            f,
+            ", {} rays ({} primary, {:.2} M/s), {} triangle tests, {} sphere tests",
+            self.rays_cast, self.primary_rays, rate, self.ray_triangle_tests, self.ray_sphere_tests
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn since_subtracts_every_counter() {
+        let earlier = RenderStats {
+            rays_cast: 10,
+            primary_rays: 4,
+            ray_triangle_tests: 3,
+            ray_sphere_tests: 7,
+            ..RenderStats::default()
+        };
+        let later = RenderStats {
+            rays_cast: 25,
+            primary_rays: 9,
+            ray_triangle_tests: 3,
+            ray_sphere_tests: 20,
+            ..RenderStats::default()
+        };
+        let elapsed = Duration::from_millis(1500);
+        let expected = RenderStats {
+            elapsed,
+            rays_cast: 15,
+            primary_rays: 5,
+            ray_triangle_tests: 0,
+            ray_sphere_tests: 13,
+        };
+        assert_eq!(later.since(&earlier, elapsed), expected);
+        assert!(expected.to_string().starts_with("1.50 s"));
+    }
+}
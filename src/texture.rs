@@ -0,0 +1,503 @@
+use crate::buffer::PixelBuffer;
+use crate::perlin::Perlin;
+use crate::vec3::Vec3;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// Color that varies over a surface, looked up by surface coordinates or by the hit
+// point itself for solid textures
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, point: Vec3) -> Vec3;
+
+    // Like `value`, averaged over a patch about `footprint` world units wide. Textures
+    // without prefiltered levels ignore the footprint.
+    fn value_lod(&self, u: f64, v: f64, point: Vec3, _footprint: f64) -> Vec3 {
+        return self.value(u, v, point);
+    }
+}
+
+pub struct SolidColor {
+    albedo: Vec3,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Vec3) -> Self {
+        return Self { albedo };
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _point: Vec3) -> Vec3 {
+        return self.albedo;
+    }
+}
+
+// 3D checkerboard of cubes `scale` units wide, alternating between two textures
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        return Self {
+            inv_scale: 1.0 / scale,
+            even,
+            odd,
+        };
+    }
+
+    pub fn from_colors(scale: f64, even: Vec3, odd: Vec3) -> Self {
+        return Self::new(
+            scale,
+            Arc::new(SolidColor::new(even)),
+            Arc::new(SolidColor::new(odd)),
+        );
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, point: Vec3) -> Vec3 {
+        // A footprint of 0 reads both sides unfiltered
+        return self.value_lod(u, v, point, 0.0);
+    }
+
+    fn value_lod(&self, u: f64, v: f64, point: Vec3, footprint: f64) -> Vec3 {
+        let cell = |x: f64| (self.inv_scale * x).floor() as i64;
+        let sum = cell(point.x()) + cell(point.y()) + cell(point.z());
+
+        return if sum.rem_euclid(2) == 0 {
+            self.even.value_lod(u, v, point, footprint)
+        } else {
+            self.odd.value_lod(u, v, point, footprint)
+        };
+    }
+}
+
+// How texel lookups outside the image are resolved
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WrapMode {
+    // Coordinates outside [0, 1] take the color of the nearest edge
+    Clamp,
+    // The image tiles the plane
+    Repeat,
+    // Tiles along u and clamps along v, for equirectangular maps: they wrap around in
+    // longitude, but the top and bottom rows are the poles and must not blend
+    RepeatU,
+}
+
+impl WrapMode {
+    // Texel indices for (x, y) in an image of `width` x `height` texels
+    fn index(&self, x: i64, y: i64, width: i64, height: i64) -> (i64, i64) {
+        return match self {
+            WrapMode::Clamp => (x.clamp(0, width - 1), y.clamp(0, height - 1)),
+            WrapMode::Repeat => (x.rem_euclid(width), y.rem_euclid(height)),
+            WrapMode::RepeatU => (x.rem_euclid(width), y.clamp(0, height - 1)),
+        };
+    }
+}
+
+// How an image texture is read between texel centers
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TextureFilter {
+    // The texel under the lookup point; blocky up close but cheapest
+    Nearest,
+    // Linear blend of the four nearest texels
+    Bilinear,
+}
+
+// Image wrapped over the surface's (u, v) coordinates, with v = 0 at the bottom row
+pub struct ImageTexture {
+    // None for `missing`
+    image: Option<PixelBuffer>,
+    // Successively halved copies of `image` down to 1x1; empty unless mipmapping is on
+    mipmaps: Vec<PixelBuffer>,
+    // World-space width of one full-size texel on the surface, for picking mip levels
+    texel_size: f64,
+    pub wrap: WrapMode,
+    pub filter: TextureFilter,
+}
+
+impl ImageTexture {
+    // Loads a PNG or JPEG image by extension, decoded from sRGB to linear, a Radiance
+    // `.hdr` or OpenEXR `.exr` image, or a PPM image for any other extension. PPM values
+    // are decoded with the curve this crate writes them with; see `read_ppm`.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let image = match path.extension().and_then(|e| e.to_str()) {
+            Some(e)
+                if ["png", "jpg", "jpeg"]
+                    .iter()
+                    .any(|x| e.eq_ignore_ascii_case(x)) =>
+            {
+                PixelBuffer::read_image(path)
+            }
+            Some(e) if e.eq_ignore_ascii_case("hdr") => PixelBuffer::read_hdr(path),
+            Some(e) if e.eq_ignore_ascii_case("exr") => PixelBuffer::read_exr(path),
+            _ => PixelBuffer::read_ppm(path),
+        }?;
+        return Ok(Self::from_buffer(image));
+    }
+
+    // Stand-in for an image that could not be loaded. It renders as solid magenta so the
+    // problem is obvious in the output.
+    pub fn missing() -> Self {
+        return Self::with_image(None);
+    }
+
+    pub fn from_buffer(image: PixelBuffer) -> Self {
+        return Self::with_image(Some(image));
+    }
+
+    fn with_image(image: Option<PixelBuffer>) -> Self {
+        return Self {
+            image,
+            mipmaps: Vec::new(),
+            texel_size: 0.0,
+            wrap: WrapMode::Clamp,
+            filter: TextureFilter::Bilinear,
+        };
+    }
+
+    pub fn with_wrap(mut self, wrap: WrapMode) -> Self {
+        self.wrap = wrap;
+        return self;
+    }
+
+    // Nearest is worth it for surfaces far enough away that a texel is smaller than a pixel
+    pub fn with_filter(mut self, filter: TextureFilter) -> Self {
+        self.filter = filter;
+        return self;
+    }
+
+    // Builds the mip chain so `value_lod` filters trilinearly: bilinear lookups in the two
+    // levels whose texels bracket the footprint, blended by where it falls between them.
+    // `texel_size` is how wide one texel of the full image is in world units on the
+    // surface, e.g. the quad width over the image width. Sizes that are not positive and
+    // finite would pick a level at random, so they leave mipmapping off. Only cameras with
+    // ray differentials on supply the footprint.
+    pub fn with_mipmaps(mut self, texel_size: f64) -> Self {
+        self.mipmaps.clear();
+        if !(texel_size > 0.0 && texel_size.is_finite()) {
+            self.texel_size = 0.0;
+            return self;
+        }
+        self.texel_size = texel_size;
+        if let Some(image) = &self.image {
+            let mut level = image;
+            while level.width > 1 || level.height > 1 {
+                self.mipmaps.push(level.downsampled());
+                level = self.mipmaps.last().unwrap();
+            }
+        }
+        return self;
+    }
+
+    // Number of levels including the full image; 1 without mipmaps, 0 for `missing`
+    pub fn mip_levels(&self) -> usize {
+        return match self.image {
+            Some(_) => 1 + self.mipmaps.len(),
+            None => 0,
+        };
+    }
+
+    // Level 0 is the full image, and each level after it half the size of the one before
+    pub fn mip_level(&self, level: usize) -> Option<&PixelBuffer> {
+        return match level {
+            0 => self.image.as_ref(),
+            _ => self.mipmaps.get(level - 1),
+        };
+    }
+
+    fn texel(&self, image: &PixelBuffer, x: i64, y: i64) -> Vec3 {
+        let (x, y) = self.wrap.index(x, y, image.width, image.height);
+        return image.get(x, y);
+    }
+
+    const MISSING_COLOR: Vec3 = Vec3(1.0, 0.0, 1.0);
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _point: Vec3) -> Vec3 {
+        let image = match &self.image {
+            Some(image) => image,
+            None => return Self::MISSING_COLOR,
+        };
+        return match self.filter {
+            TextureFilter::Nearest => self.nearest(image, u, v),
+            TextureFilter::Bilinear => self.bilinear(image, u, v),
+        };
+    }
+
+    fn value_lod(&self, u: f64, v: f64, point: Vec3, footprint: f64) -> Vec3 {
+        if self.mipmaps.is_empty() || footprint <= 0.0 {
+            return self.value(u, v, point);
+        }
+
+        let top = self.mipmaps.len() as f64;
+        let lod = (footprint / self.texel_size).log2().clamp(0.0, top);
+        let lower = lod.floor() as usize;
+        let blend = lod - lower as f64;
+        let color = self.bilinear(self.mip_level(lower).unwrap(), u, v);
+        if blend == 0.0 {
+            return color;
+        }
+        let next = self.bilinear(self.mip_level(lower + 1).unwrap(), u, v);
+        return color * (1.0 - blend) + next * blend;
+    }
+}
+
+impl ImageTexture {
+    // Position of (u, v) in texels of `image`, flipping v since image rows run top to
+    // bottom
+    fn texel_position(image: &PixelBuffer, u: f64, v: f64) -> (f64, f64) {
+        return (u * image.width as f64, (1.0 - v) * image.height as f64);
+    }
+
+    fn nearest(&self, image: &PixelBuffer, u: f64, v: f64) -> Vec3 {
+        let (s, t) = Self::texel_position(image, u, v);
+        return self.texel(image, s.floor() as i64, t.floor() as i64);
+    }
+
+    fn bilinear(&self, image: &PixelBuffer, u: f64, v: f64) -> Vec3 {
+        // Texel centers sit at half-integer positions
+        let (s, t) = Self::texel_position(image, u, v);
+        let (s, t) = (s - 0.5, t - 0.5);
+        let (x, y) = (s.floor(), t.floor());
+        let (fx, fy) = (s - x, t - y);
+        let (x, y) = (x as i64, y as i64);
+
+        let top = self.texel(image, x, y) * (1.0 - fx) + self.texel(image, x + 1, y) * fx;
+        let bottom =
+            self.texel(image, x, y + 1) * (1.0 - fx) + self.texel(image, x + 1, y + 1) * fx;
+        return top * (1.0 - fy) + bottom * fy;
+    }
+}
+
+// Gray Perlin noise. With turbulence it becomes marble: veins from a sine wave along z
+// whose phase is pushed around by `turbulence` octaves of noise.
+pub struct NoiseTexture {
+    noise: Perlin,
+    // Spatial frequency; higher values give finer detail
+    scale: f64,
+    turbulence: Option<i64>,
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f64) -> Self {
+        return Self {
+            noise: Perlin::new(),
+            scale,
+            turbulence: None,
+        };
+    }
+
+    pub fn marble(scale: f64, octaves: i64) -> Self {
+        return Self {
+            turbulence: Some(octaves),
+            ..Self::new(scale)
+        };
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, point: Vec3) -> Vec3 {
+        let shade = match self.turbulence {
+            Some(octaves) => {
+                let phase = self.scale * point.z() + 10.0 * self.noise.turbulence(point, octaves);
+                0.5 * (1.0 + phase.sin())
+            }
+            None => 0.5 * (1.0 + self.noise.noise(point * self.scale)),
+        };
+        return Vec3::new(shade, shade, shade);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checker_alternates_across_a_grid() {
+        let (even, odd) = (Vec3::new(1.0, 1.0, 1.0), Vec3::new(0.0, 0.0, 0.0));
+        let checker = CheckerTexture::from_colors(0.5, even, odd);
+        // Sample the middle of each cell, including negative ones
+        for x in -4..4 {
+            for z in -4..4 {
+                let point = Vec3::new((x as f64 + 0.5) * 0.5, 0.25, (z as f64 + 0.5) * 0.5);
+                let expected = if (x + z) % 2 == 0 { even } else { odd };
+                assert_eq!(
+                    checker.value(0.0, 0.0, point),
+                    expected,
+                    "cell ({}, {})",
+                    x,
+                    z
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn noise_textures_stay_between_black_and_white() {
+        let textures = [NoiseTexture::new(4.0), NoiseTexture::marble(4.0, 7)];
+        for i in 0..2000 {
+            let point = Vec3::new(i as f64 * 0.37, i as f64 * -0.11, i as f64 * 0.05);
+            for texture in &textures {
+                let shade = texture.value(0.0, 0.0, point);
+                assert!((0.0..=1.0).contains(&shade.x()), "shade {:?}", shade);
+                assert!(shade.x() == shade.y() && shade.y() == shade.z());
+            }
+        }
+    }
+
+    fn red_blue() -> ImageTexture {
+        let mut image = PixelBuffer::new(2, 1);
+        image.set(0, 0, Vec3::new(1.0, 0.0, 0.0));
+        image.set(1, 0, Vec3::new(0.0, 0.0, 1.0));
+        return ImageTexture::from_buffer(image);
+    }
+
+    #[test]
+    fn bilinear_halfway_between_texels_is_their_average() {
+        let purple = Vec3::new(0.5, 0.0, 0.5);
+        let texture = red_blue();
+        assert_eq!(texture.value(0.5, 0.5, Vec3::EMPTY), purple);
+        // At texel centers the texel comes back unchanged
+        assert_eq!(
+            texture.value(0.25, 0.5, Vec3::EMPTY),
+            Vec3::new(1.0, 0.0, 0.0)
+        );
+
+        // Across the left edge, clamping holds the edge texel and repeating wraps around
+        assert_eq!(
+            texture.value(0.0, 0.5, Vec3::EMPTY),
+            Vec3::new(1.0, 0.0, 0.0)
+        );
+        let tiled = red_blue().with_wrap(WrapMode::Repeat);
+        assert_eq!(tiled.value(0.0, 0.5, Vec3::EMPTY), purple);
+
+        let blocky = red_blue().with_filter(TextureFilter::Nearest);
+        assert_eq!(
+            blocky.value(0.49, 0.5, Vec3::EMPTY),
+            Vec3::new(1.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn open_returns_the_error_for_a_missing_file() {
+        let result = ImageTexture::open("no/such/texture.ppm");
+        assert_eq!(result.err().unwrap().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn missing_texture_renders_magenta() {
+        let texture = ImageTexture::missing().with_mipmaps(1.0);
+        assert_eq!(texture.mip_levels(), 0);
+        assert_eq!(
+            texture.value(0.5, 0.5, Vec3::EMPTY),
+            Vec3::new(1.0, 0.0, 1.0)
+        );
+        assert_eq!(
+            texture.value_lod(0.5, 0.5, Vec3::EMPTY, 4.0),
+            Vec3::new(1.0, 0.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn open_loads_a_ppm_with_v_running_up() {
+        let mut image = PixelBuffer::new(2, 2);
+        image.set(0, 0, Vec3::new(1.0, 1.0, 1.0));
+        let path = std::env::temp_dir().join(format!("texture-{}.ppm", std::process::id()));
+        crate::util::save_ppm(&image, &path).unwrap();
+        let texture = ImageTexture::open(&path)
+            .unwrap()
+            .with_filter(TextureFilter::Nearest);
+        std::fs::remove_file(&path).unwrap();
+
+        // The top-left texel sits at small u and large v
+        assert_eq!(
+            texture.value(0.25, 0.75, Vec3::EMPTY),
+            Vec3::new(1.0, 1.0, 1.0)
+        );
+        assert_eq!(texture.value(0.25, 0.25, Vec3::EMPTY), Vec3::EMPTY);
+    }
+
+    #[test]
+    fn mip_levels_halve_down_to_one_texel() {
+        let mut image = PixelBuffer::new(4, 2);
+        for x in 0..4 {
+            let v = x as f64 / 3.0;
+            image.set(x, 0, Vec3::new(v, v, v));
+            image.set(x, 1, Vec3::new(1.0, 0.0, 0.0));
+        }
+        let texture = ImageTexture::from_buffer(image.clone()).with_mipmaps(0.25);
+        assert_eq!(texture.mip_levels(), 3);
+
+        let base = texture.mip_level(0).unwrap();
+        for x in 0..4 {
+            for y in 0..2 {
+                assert_eq!(base.get(x, y), image.get(x, y));
+            }
+        }
+        let half = texture.mip_level(1).unwrap();
+        assert_eq!((half.width, half.height), (2, 1));
+        let expected =
+            (image.get(0, 0) + image.get(1, 0) + image.get(0, 1) + image.get(1, 1)) / 4.0;
+        assert!((half.get(0, 0) - expected).length() < 1e-12);
+        let last = texture.mip_level(2).unwrap();
+        assert_eq!((last.width, last.height), (1, 1));
+        assert!(texture.mip_level(3).is_none());
+
+        // A footprint of one full-size texel reads the full image
+        assert_eq!(
+            texture.value_lod(0.5, 0.5, Vec3::EMPTY, 0.25),
+            texture.value(0.5, 0.5, Vec3::EMPTY)
+        );
+    }
+
+    #[test]
+    fn invalid_texel_sizes_leave_mipmapping_off() {
+        for texel_size in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let texture = red_blue().with_mipmaps(texel_size);
+            assert_eq!(texture.mip_levels(), 1, "texel size {}", texel_size);
+            assert_eq!(
+                texture.value_lod(0.25, 0.5, Vec3::EMPTY, 10.0),
+                Vec3::new(1.0, 0.0, 0.0)
+            );
+        }
+    }
+
+    #[test]
+    fn open_decodes_png_from_srgb() {
+        // Top row: an sRGB mid gray and white; bottom row black
+        let pixels = [188, 188, 188, 255, 255, 255, 0, 0, 0, 0, 0, 0];
+        let path = std::env::temp_dir().join(format!("texture-{}.png", std::process::id()));
+        let png = crate::util::encode_png_rgb8(2, 2, &pixels).unwrap();
+        std::fs::write(&path, png).unwrap();
+        let texture = ImageTexture::open(&path)
+            .unwrap()
+            .with_filter(TextureFilter::Nearest);
+        std::fs::remove_file(&path).unwrap();
+
+        let gray = crate::util::srgb_to_linear(188.0 / 255.0);
+        assert!((gray - 0.5).abs() < 0.01);
+        let top_left = texture.value(0.25, 0.75, Vec3::EMPTY);
+        assert!((top_left.x() - gray).abs() < 1e-6, "{:?}", top_left);
+        assert_eq!(
+            texture.value(0.75, 0.75, Vec3::EMPTY),
+            Vec3::new(1.0, 1.0, 1.0)
+        );
+        assert_eq!(texture.value(0.25, 0.25, Vec3::EMPTY), Vec3::EMPTY);
+    }
+
+    #[test]
+    fn open_reports_an_undecodable_png_as_invalid_data() {
+        let path = std::env::temp_dir().join(format!("garbage-{}.png", std::process::id()));
+        std::fs::write(&path, b"not a png").unwrap();
+        let result = ImageTexture::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result.err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,17 +1,444 @@
+use crate::buffer::PixelBuffer;
+use crate::denoise;
+use crate::interval::Interval;
 use crate::vec3::Vec3;
+use image::codecs::png::PngEncoder;
+use image::{ExtendedColorType, ImageEncoder};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// How linear radiance is mapped to display values in [0, 1]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ToneMap {
+    // No curve at all; values above 1 clip
+    Linear,
+    // Power curve x^(1/g); values above 1 clip
+    Gamma(f64),
+    // The exact piecewise sRGB encoding, for color-accurate output; values above 1 clip.
+    // Gamma(2.0) is a cheaper approximation that runs slightly dark in the midtones.
+    Srgb,
+    // x / (1 + x) then gamma 2, compressing highlights smoothly toward white
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve then gamma 2, with a slight toe and a
+    // softer shoulder than Reinhard
+    AcesFilmic,
+}
+
+impl ToneMap {
+    pub fn apply(&self, x: f64) -> f64 {
+        let x = x.max(0.0);
+        return match self {
+            ToneMap::Linear => x,
+            ToneMap::Gamma(g) => x.powf(1.0 / g),
+            ToneMap::Srgb => linear_to_srgb(x),
+            ToneMap::Reinhard => liner_to_gamma(x / (1.0 + x)),
+            ToneMap::AcesFilmic => {
+                let mapped = (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+                liner_to_gamma(mapped.min(1.0))
+            }
+        };
+    }
+}
+
+// Display values are clamped to this range and scaled by 256, so every 8-bit level gets
+// an equal share of [0, 1)
+const INTENSITY: Interval = Interval::new(0.0, 0.999);
+
+// Out-of-range channels clamp to black or white
+pub fn process_rgb(color: Vec3) -> [u8; 3] {
+    return process_rgb_with(color, ToneMap::Gamma(2.0));
+}
+
+pub fn process_rgb_with(color: Vec3, tonemap: ToneMap) -> [u8; 3] {
+    let byte = |x: f64| {
+        let display = INTENSITY.clamp(tonemap.apply(x));
+        return (256.0 * display) as u8;
+    };
+    return [byte(color.x()), byte(color.y()), byte(color.z())];
+}
+
+// 16-bit samples are rounded rather than truncated, so the range runs all the way to 1
+const INTENSITY16: Interval = Interval::new(0.0, 1.0);
+
+// Like `process_rgb_with` at 16 bits per channel, with 1.0 mapping to 65535
+pub fn process_rgb16_with(color: Vec3, tonemap: ToneMap) -> [u16; 3] {
+    let sample = |x: f64| {
+        let display = INTENSITY16.clamp(tonemap.apply(x));
+        return (65535.0 * display).round() as u16;
+    };
+    return [sample(color.x()), sample(color.y()), sample(color.z())];
+}
+
+// Gaussian blur with a standard deviation of `sigma` pixels, on the linear values.
+// Smooths noise and edges alike.
+pub fn denoise_gaussian(img: &PixelBuffer, sigma: f64) -> PixelBuffer {
+    return denoise::filter(img, sigma, &[]);
+}
+
+// Edge-preserving blur: like `denoise_gaussian` with `sigma_space`, but neighbors whose
+// linear color differs from the center by much more than `sigma_color` barely count
+pub fn denoise_bilateral(img: &PixelBuffer, sigma_space: f64, sigma_color: f64) -> PixelBuffer {
+    return denoise::filter(img, sigma_space, &[(img, sigma_color)]);
+}
+
+// Writes `img` as an 8-bit, gamma-corrected PNG file
+pub fn save_png(img: &PixelBuffer, path: impl AsRef<Path>) -> io::Result<()> {
+    return save_png_with(img, ToneMap::Gamma(2.0), path);
+}
+
+pub fn save_png_with(
+    img: &PixelBuffer,
+    tonemap: ToneMap,
+    path: impl AsRef<Path>,
+) -> io::Result<()> {
+    let too_large = |_| io::Error::new(io::ErrorKind::InvalidInput, "image too large for PNG");
+    let width = u32::try_from(img.width).map_err(too_large)?;
+    let height = u32::try_from(img.height).map_err(too_large)?;
+    return fs::write(
+        path,
+        encode_png_rgb8(width, height, &img.to_rgb8_with(tonemap))?,
+    );
+}
+
+// PNG bytes for 8-bit RGB pixels, row-major with three bytes per pixel
+pub fn encode_png_rgb8(width: u32, height: u32, pixels: &[u8]) -> io::Result<Vec<u8>> {
+    return encode_png(width, height, pixels, ExtendedColorType::Rgb8);
+}
+
+// Writes `img` as a plain-text PPM file
+pub fn save_ppm(img: &PixelBuffer, path: impl AsRef<Path>) -> io::Result<()> {
+    return save_ppm_with(img, ToneMap::Gamma(2.0), path);
+}
+
+pub fn save_ppm_with(
+    img: &PixelBuffer,
+    tonemap: ToneMap,
+    path: impl AsRef<Path>,
+) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_ppm_with(img, tonemap, &mut file)?;
+    return file.flush();
+}
+
+// Writes `img` as a 16-bit, gamma-corrected PNG file. Smooth gradients such as skies
+// keep 256 times as many levels as with `save_png`, so they don't band.
+pub fn save_png16(img: &PixelBuffer, path: impl AsRef<Path>) -> io::Result<()> {
+    return save_png16_with(img, ToneMap::Gamma(2.0), path);
+}
+
+pub fn save_png16_with(
+    img: &PixelBuffer,
+    tonemap: ToneMap,
+    path: impl AsRef<Path>,
+) -> io::Result<()> {
+    let too_large = |_| io::Error::new(io::ErrorKind::InvalidInput, "image too large for PNG");
+    let width = u32::try_from(img.width).map_err(too_large)?;
+    let height = u32::try_from(img.height).map_err(too_large)?;
+    // The encoder takes 16-bit samples in native byte order and writes them big-endian
+    let bytes: Vec<u8> = img
+        .to_rgb16_with(tonemap)
+        .iter()
+        .flat_map(|sample| sample.to_ne_bytes())
+        .collect();
+    return fs::write(
+        path,
+        encode_png(width, height, &bytes, ExtendedColorType::Rgb16)?,
+    );
+}
+
+fn encode_png(
+    width: u32,
+    height: u32,
+    bytes: &[u8],
+    color: ExtendedColorType,
+) -> io::Result<Vec<u8>> {
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(bytes, width, height, color)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    return Ok(png);
+}
+
+// Writes `img` to an OpenEXR file as 32-bit floats with no tone mapping, keeping values
+// above 1 for grading and compositing in other tools
+pub fn write_exr(img: &PixelBuffer, path: impl AsRef<Path>) -> io::Result<()> {
+    let (width, height) = (img.width as usize, img.height as usize);
+    return exr::prelude::write_rgb_file(path, width, height, |x, y| {
+        let c = img.get(x as i64, y as i64);
+        return (c.x() as f32, c.y() as f32, c.z() as f32);
+    })
+    .map_err(exr_io_error);
+}
+
+// File system errors pass through; anything the exr crate rejects is invalid data
+pub(crate) fn exr_io_error(e: exr::error::Error) -> io::Error {
+    return match e {
+        exr::error::Error::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    };
+}
+
+// Plain-text PPM encoding of `img`, one line of gamma-corrected pixels per scanline
+pub fn write_ppm(img: &PixelBuffer, w: &mut impl Write) -> io::Result<()> {
+    return write_ppm_with(img, ToneMap::Gamma(2.0), w);
+}
+
+pub fn write_ppm_with(img: &PixelBuffer, tonemap: ToneMap, w: &mut impl Write) -> io::Result<()> {
+    write!(w, "P3\n{} {}\n255\n", img.width, img.height)?;
+    let mut line = String::new();
+    for y in 0..img.height {
+        line.clear();
+        for x in 0..img.width {
+            let [r, g, b] = process_rgb_with(img.get(x, y), tonemap);
+            line.push_str(format!("{} {} {} ", r, g, b).as_str());
+        }
+        write_new_line(&mut line);
+        w.write_all(line.as_bytes())?;
+    }
+    return Ok(());
+}
 
-// Assumes [0,1] input
 pub fn write_color(buf: &mut String, color: Vec3) {
-    let r: i64 = (255.0 * liner_to_gamma(color.x())).trunc() as i64;
-    let g: i64 = (255.0 * liner_to_gamma(color.y())).trunc() as i64;
-    let b: i64 = (255.0 * liner_to_gamma(color.z())).trunc() as i64;
+    let [r, g, b] = process_rgb(color);
     buf.push_str(format!("{} {} {} ", r, g, b).as_str());
 }
 
 pub fn write_new_line(buf: &mut String) {
-    buf.push_str("\n");
+    buf.push('\n');
 }
 
 pub fn liner_to_gamma(x: f64) -> f64 {
     return if x > 0.0 { x.sqrt() } else { 0.0 };
 }
+
+// Encodes one linear channel in [0, 1] with the sRGB transfer function: linear near black,
+// then a 1/2.4 power curve
+pub fn linear_to_srgb(x: f64) -> f64 {
+    return if x <= 0.0031308 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    };
+}
+
+// Decodes one sRGB-encoded channel in [0, 1] to linear light
+pub fn srgb_to_linear(c: f64) -> f64 {
+    return if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    };
+}
+
+// Approximate linear RGB color of a blackbody at the given temperature, normalized so the
+// brightest channel is 1. Fits the Planckian locus between roughly 1000K and 40000K
+// (Tanner Helland's curve fit), then undoes the display gamma applied in `write_color`.
+pub fn blackbody_to_rgb(kelvin: f64) -> Vec3 {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+
+    let r = if t <= 66.0 {
+        255.0
+    } else {
+        329.698727446 * (t - 60.0).powf(-0.1332047592)
+    };
+    let g = if t <= 66.0 {
+        99.4708025861 * t.ln() - 161.1195681661
+    } else {
+        288.1221695283 * (t - 60.0).powf(-0.0755148492)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.5177312231 * (t - 10.0).ln() - 305.0447927307
+    };
+
+    let to_linear = |c: f64| {
+        let c = c.clamp(0.0, 255.0) / 255.0;
+        return c * c;
+    };
+    return Vec3::new(to_linear(r), to_linear(g), to_linear(b));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blackbody_temperatures_map_to_expected_hues() {
+        let warm = blackbody_to_rgb(3200.0);
+        assert!(warm.x() > warm.y() && warm.y() > warm.z(), "3200K");
+
+        let neutral = blackbody_to_rgb(6500.0);
+        let spread = neutral.x().max(neutral.y()).max(neutral.z())
+            - neutral.x().min(neutral.y()).min(neutral.z());
+        assert!(spread < 0.05, "6500K spread {}", spread);
+
+        let cool = blackbody_to_rgb(10000.0);
+        assert!(cool.z() > cool.y() && cool.y() > cool.x(), "10000K");
+    }
+
+    #[test]
+    fn tone_maps_compare_at_four() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-4;
+        // The plain curves clip, the filmic ones keep some headroom below white
+        assert_eq!(
+            process_rgb_with(Vec3::new(4.0, 4.0, 4.0), ToneMap::Linear),
+            [255; 3]
+        );
+        assert_eq!(
+            process_rgb_with(Vec3::new(4.0, 4.0, 4.0), ToneMap::Gamma(2.0)),
+            [255; 3]
+        );
+        assert!(close(ToneMap::Reinhard.apply(4.0), 0.8f64.sqrt()));
+        assert!(close(ToneMap::AcesFilmic.apply(4.0), 0.98662));
+        assert_eq!(
+            process_rgb_with(Vec3::new(4.0, 4.0, 4.0), ToneMap::Reinhard),
+            [228; 3]
+        );
+        assert_eq!(
+            process_rgb_with(Vec3::new(4.0, 4.0, 4.0), ToneMap::AcesFilmic),
+            [252; 3]
+        );
+        assert!(ToneMap::Reinhard.apply(4.0) < ToneMap::AcesFilmic.apply(4.0));
+    }
+
+    #[test]
+    fn out_of_range_channels_clamp_before_scaling() {
+        assert_eq!(process_rgb(Vec3::new(-1.0, 0.5, 10.0)), [0, 181, 255]);
+        assert_eq!(
+            process_rgb(Vec3::new(f64::NEG_INFINITY, 0.0, f64::INFINITY)),
+            [0, 0, 255]
+        );
+    }
+
+    #[test]
+    fn ppm_header_and_first_pixel() {
+        let mut img = PixelBuffer::new(2, 1);
+        img.set(0, 0, Vec3::new(1.0, 0.25, 0.0));
+        let mut out = Vec::new();
+        write_ppm(&img, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(
+            text.starts_with("P3\n2 1\n255\n255 128 0 0 0 0 \n"),
+            "{:?}",
+            text
+        );
+    }
+
+    #[test]
+    fn bilateral_keeps_checker_edges_sharper_than_gaussian() {
+        let mut checker = PixelBuffer::new(16, 16);
+        for y in 0..16 {
+            for x in 0..16 {
+                let v = ((x / 4 + y / 4) % 2) as f64;
+                checker.set(x, y, Vec3::new(v, v, v));
+            }
+        }
+        let error = |img: &PixelBuffer| {
+            let mut total = 0.0;
+            for y in 0..16 {
+                for x in 0..16 {
+                    total += (img.get(x, y) - checker.get(x, y)).length();
+                }
+            }
+            return total;
+        };
+
+        let bilateral = denoise_bilateral(&checker, 1.5, 0.1);
+        let gaussian = denoise_gaussian(&checker, 1.5);
+        assert!(
+            error(&bilateral) < error(&gaussian) / 10.0,
+            "{} vs {}",
+            error(&bilateral),
+            error(&gaussian)
+        );
+        // Either side of an edge stays black and white
+        assert!(bilateral.get(3, 0).x() < 0.01 && bilateral.get(4, 0).x() > 0.99);
+        assert!(gaussian.get(3, 0).x() > 0.1 && gaussian.get(4, 0).x() < 0.9);
+    }
+
+    #[test]
+    fn gamma_one_passes_values_through() {
+        for x in [0.0, 0.01, 0.25, 0.5, 0.9, 1.0] {
+            assert_eq!(ToneMap::Gamma(1.0).apply(x), x);
+        }
+        let color = Vec3::new(0.1, 0.5, 0.75);
+        assert_eq!(
+            process_rgb_with(color, ToneMap::Gamma(1.0)),
+            process_rgb_with(color, ToneMap::Linear)
+        );
+        // Gamma 2 is the square root the default output uses
+        assert_eq!(ToneMap::Gamma(2.0).apply(0.25), 0.5);
+    }
+
+    #[test]
+    fn srgb_is_continuous_at_its_breakpoint_and_brighter_than_sqrt() {
+        let knee = 0.0031308;
+        // Both pieces meet at the breakpoint
+        let (below, above) = (linear_to_srgb(knee - 1e-9), linear_to_srgb(knee + 1e-9));
+        assert!((below - above).abs() < 1e-6, "{} vs {}", below, above);
+        assert!((linear_to_srgb(knee) - 12.92 * knee).abs() < 1e-12);
+        // The linear toe sits well below the square root there
+        assert!(linear_to_srgb(knee) < liner_to_gamma(knee) / 1.3);
+        // Midtones come out brighter than with the square root, and both reach white
+        assert!(linear_to_srgb(0.2) > liner_to_gamma(0.2));
+        assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-12);
+        assert_eq!(liner_to_gamma(1.0), 1.0);
+        // Decoding undoes the encoding on both sides of the breakpoint
+        for x in [knee / 2.0, knee * 2.0, 0.5] {
+            assert!((srgb_to_linear(linear_to_srgb(x)) - x).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn smooth_ramps_keep_more_levels_at_16_bits() {
+        // A dim gradient across 1024 pixels, like a dusk sky
+        let mut ramp = PixelBuffer::new(1024, 1);
+        for x in 0..1024 {
+            let v = 0.1 * x as f64 / 1023.0;
+            ramp.set(x, 0, Vec3::new(v, v, v));
+        }
+        // Red channels only, in order along the ramp
+        let rgb8 = ramp.to_rgb8_with(ToneMap::Gamma(2.0));
+        let rgb16 = ramp.to_rgb16_with(ToneMap::Gamma(2.0));
+        let mut levels8: Vec<u8> = rgb8.into_iter().step_by(3).collect();
+        let mut levels16: Vec<u16> = rgb16.into_iter().step_by(3).collect();
+        levels8.dedup();
+        levels16.dedup();
+        // Fewer than a hundred steps survive at 8 bits, many times that at 16
+        assert!(levels8.len() < 100, "{} 8-bit levels", levels8.len());
+        assert!(levels16.len() > 900, "{} 16-bit levels", levels16.len());
+    }
+
+    #[test]
+    fn rgb16_channels_clamp_like_the_8_bit_ones() {
+        let color = Vec3::new(-1.0, 0.25, 10.0);
+        assert_eq!(
+            process_rgb16_with(color, ToneMap::Gamma(2.0)),
+            [0, 32768, 65535]
+        );
+        assert_eq!(
+            process_rgb16_with(color, ToneMap::Linear),
+            [0, 16384, 65535]
+        );
+    }
+
+    #[test]
+    fn png16_files_decode_to_the_same_samples() {
+        let mut image = PixelBuffer::new(3, 2);
+        image.set(0, 0, Vec3::new(0.25, 0.5, 1.0));
+        image.set(1, 0, Vec3::new(-1.0, 2.0, 0.001));
+        image.set(2, 1, Vec3::new(0.7, 0.3, 0.123));
+        let path = std::env::temp_dir().join(format!("png16-{}.png", std::process::id()));
+        save_png16(&image, &path).unwrap();
+        let decoded = image::open(&path).map(|d| d.into_rgb16());
+        fs::remove_file(&path).unwrap();
+
+        let decoded = decoded.unwrap();
+        assert_eq!(decoded.dimensions(), (3, 2));
+        assert_eq!(decoded.into_raw(), image.to_rgb16_with(ToneMap::Gamma(2.0)));
+    }
+}
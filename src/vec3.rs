@@ -1,11 +1,16 @@
 // Operator overloading
 // https://doc.rust-lang.org/rust-by-example/trait/ops.html
-use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::ops::{
+    Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
+};
 
-use rand;
-use rand::{thread_rng, Rng};
+use rand::Rng;
+use std::fmt;
 
-#[derive(Copy, Clone)]
+// `==` compares components exactly, which rarely holds after float arithmetic;
+// prefer `approx_eq` with a tolerance
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "simd", repr(C, align(16)))]
 pub struct Vec3(pub f64, pub f64, pub f64);
 
 impl Vec3 {
@@ -38,7 +43,7 @@ impl Vec3 {
     }
 
     pub fn unit(&self) -> Vec3 {
-        return (*self).clone() / self.length();
+        return *self / self.length();
     }
 
     pub fn near_zero(&self) -> bool {
@@ -46,6 +51,50 @@ impl Vec3 {
         return self.x().abs() < eps && self.y().abs() < eps && self.z().abs() < eps;
     }
 
+    // Whether every component differs from `other` by at most `eps`
+    pub fn approx_eq(&self, other: Vec3, eps: f64) -> bool {
+        return (self.x() - other.x()).abs() <= eps
+            && (self.y() - other.y()).abs() <= eps
+            && (self.z() - other.z()).abs() <= eps;
+    }
+
+    // Element-wise smaller of each pair of components
+    pub fn min(a: Vec3, b: Vec3) -> Vec3 {
+        return Self::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+    }
+
+    // Element-wise larger of each pair of components
+    pub fn max(a: Vec3, b: Vec3) -> Vec3 {
+        return Self::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+    }
+
+    // Linear blend: `a` at t = 0, `b` at t = 1
+    pub fn lerp(a: Vec3, b: Vec3, t: f64) -> Vec3 {
+        return a * (1.0 - t) + b * t;
+    }
+
+    // Each component bounded to [lo, hi]
+    pub fn clamp(self, lo: f64, hi: f64) -> Vec3 {
+        return Self::new(
+            self.x().clamp(lo, hi),
+            self.y().clamp(lo, hi),
+            self.z().clamp(lo, hi),
+        );
+    }
+
+    pub fn min_component(&self) -> f64 {
+        return self.x().min(self.y()).min(self.z());
+    }
+
+    pub fn max_component(&self) -> f64 {
+        return self.x().max(self.y()).max(self.z());
+    }
+
+    // Perceived brightness of a linear RGB color, with Rec. 709 weights
+    pub fn luminance(&self) -> f64 {
+        return 0.2126 * self.x() + 0.7152 * self.y() + 0.0722 * self.z();
+    }
+
     pub fn dot(a: Vec3, b: Vec3) -> f64 {
         return (a * b).sum();
     }
@@ -65,43 +114,112 @@ impl Vec3 {
         return v - n * Self::dot(v, n) * 2.0;
     }
 
-    pub fn random() -> Vec3 {
+    // Snell's law refraction of the unit vector `uv` through a surface with normal `n`
+    pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: f64) -> Vec3 {
+        let cos_theta = Self::dot(-uv, n).min(1.0);
+        let r_out_perp = (uv + n * cos_theta) * etai_over_etat;
+        let r_out_parallel = n * -(1.0 - r_out_perp.length_squared()).abs().sqrt();
+        return r_out_perp + r_out_parallel;
+    }
+
+    // The random helpers draw from the caller's generator, so a seeded generator makes
+    // their results reproducible
+    pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
         return Self::new(
-            rand::random::<f64>(),
-            rand::random::<f64>(),
-            rand::random::<f64>(),
+            rng.random::<f64>(),
+            rng.random::<f64>(),
+            rng.random::<f64>(),
         );
     }
 
-    pub fn random_range(min: f64, max: f64) -> Vec3 {
-        let mut rng = thread_rng();
+    pub fn random_range<R: Rng + ?Sized>(rng: &mut R, min: f64, max: f64) -> Vec3 {
         return Self::new(
-            rng.gen_range(min..max),
-            rng.gen_range(min..max),
-            rng.gen_range(min..max),
+            rng.random_range(min..max),
+            rng.random_range(min..max),
+            rng.random_range(min..max),
         );
     }
 
-    // Returns a random vector on the unit sphere
-    // Slightly different probability distribution than random_range(0, 1)
-    // achieved by rejection technique.
-    pub fn random_on_sphere() -> Vec3 {
-        while true {
-            let p = Self::random_range(-1.0, 1.0);
+    // Returns a uniformly distributed vector on the unit sphere.
+    // Rejection-samples a point in the unit ball, then normalizes it; points too close
+    // to the center are rejected so the normalization stays finite.
+    pub fn random_unit_vector<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
+        loop {
+            let p = Self::random_range(rng, -1.0, 1.0);
+            let lensq = p.length_squared();
+            if 1e-160 < lensq && lensq <= 1.0 {
+                return p / lensq.sqrt();
+            }
+        }
+    }
+
+    pub fn random_on_hemisphere_vec3<R: Rng + ?Sized>(rng: &mut R, normal: Vec3) -> Vec3 {
+        let r = Self::random_unit_vector(rng);
+        return if Self::dot(r, normal) > 0.0 { r } else { -r };
+    }
+
+    // Random point inside the unit disk in the xy plane, by rejection
+    pub fn random_in_unit_disk<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
+        loop {
+            let p = Self::new(
+                rng.random_range(-1.0..1.0),
+                rng.random_range(-1.0..1.0),
+                0.0,
+            );
             if p.length_squared() < 1.0 {
                 return p;
             }
         }
-        // This never runs, but the Rust compiler gets mad not included
-        return Self::new(0.0, 0.0, 0.0);
     }
 
-    pub fn random_on_hemisphere_vec3(normal: Vec3) -> Vec3 {
-        let r = Self::random_on_sphere();
-        return if Self::dot(r, normal) > 0.0 { r } else { -r };
+    // Random direction on the +z hemisphere with density proportional to cos(theta)
+    pub fn random_cosine_direction<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
+        let r1 = rng.random::<f64>();
+        let r2 = rng.random::<f64>();
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        let x = phi.cos() * r2.sqrt();
+        let y = phi.sin() * r2.sqrt();
+        let z = (1.0 - r2).sqrt();
+        return Self::new(x, y, z);
     }
 
     pub const EMPTY: Vec3 = Self::new(0.0, 0.0, 0.0);
+    pub const ONE: Vec3 = Self::new(1.0, 1.0, 1.0);
+}
+
+// Orthonormal basis with w aligned to a given direction
+#[derive(Copy, Clone)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn new(n: Vec3) -> Self {
+        let w = n.unit();
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = Vec3::cross(w, a).unit();
+        let u = Vec3::cross(w, v);
+        return Self { u, v, w };
+    }
+
+    // Maps local (u, v, w) coordinates into world space
+    pub fn transform(&self, a: Vec3) -> Vec3 {
+        return self.u * a.x() + self.v * a.y() + self.w * a.z();
+    }
+}
+
+// Prints `(x, y, z)`, with three decimals unless a precision is given
+impl fmt::Display for Vec3 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let p = f.precision().unwrap_or(3);
+        return write!(f, "({:.*}, {:.*}, {:.*})", p, self.0, p, self.1, p, self.2);
+    }
 }
 
 impl Add for Vec3 {
@@ -152,3 +270,198 @@ impl Neg for Vec3 {
         return Vec3(-self.x(), -self.y(), -self.z());
     }
 }
+
+impl AddAssign for Vec3 {
+    fn add_assign(&mut self, rhs: Vec3) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Vec3 {
+    fn sub_assign(&mut self, rhs: Vec3) {
+        *self = *self - rhs;
+    }
+}
+
+impl MulAssign<f64> for Vec3 {
+    fn mul_assign(&mut self, rhs: f64) {
+        *self = *self * rhs;
+    }
+}
+
+impl DivAssign<f64> for Vec3 {
+    fn div_assign(&mut self, rhs: f64) {
+        *self = *self / rhs;
+    }
+}
+
+impl Index<usize> for Vec3 {
+    type Output = f64;
+    fn index(&self, i: usize) -> &f64 {
+        return match i {
+            0 => &self.0,
+            1 => &self.1,
+            2 => &self.2,
+            _ => panic!("Vec3 index {} out of range 0..3", i),
+        };
+    }
+}
+
+impl IndexMut<usize> for Vec3 {
+    fn index_mut(&mut self, i: usize) -> &mut f64 {
+        return match i {
+            0 => &mut self.0,
+            1 => &mut self.1,
+            2 => &mut self.2,
+            _ => panic!("Vec3 index {} out of range 0..3", i),
+        };
+    }
+}
+
+impl From<[f64; 3]> for Vec3 {
+    fn from([x, y, z]: [f64; 3]) -> Self {
+        return Self::new(x, y, z);
+    }
+}
+
+impl From<(f64, f64, f64)> for Vec3 {
+    fn from((x, y, z): (f64, f64, f64)) -> Self {
+        return Self::new(x, y, z);
+    }
+}
+
+impl From<Vec3> for [f64; 3] {
+    fn from(v: Vec3) -> Self {
+        return [v.x(), v.y(), v.z()];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!(
+            (a - b).length() < 1e-12,
+            "({}, {}, {}) != ({}, {}, {})",
+            a.x(),
+            a.y(),
+            a.z(),
+            b.x(),
+            b.y(),
+            b.z()
+        );
+    }
+
+    #[test]
+    fn refract_leaves_normal_incidence_unchanged() {
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        for eta in [1.0 / 1.5, 1.0, 1.5] {
+            assert_close(Vec3::refract(down, up, eta), down);
+        }
+    }
+
+    #[test]
+    fn refract_bends_by_snells_law() {
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        let eta = 1.0 / 1.5;
+        // 60 degrees from the normal, then grazing along the surface
+        for angle in [60f64.to_radians(), 90f64.to_radians()] {
+            let uv = Vec3::new(angle.sin(), -angle.cos(), 0.0);
+            let out = Vec3::refract(uv, n, eta);
+            let sin_out = eta * angle.sin();
+            assert_close(
+                out,
+                Vec3::new(sin_out, -(1.0 - sin_out * sin_out).sqrt(), 0.0),
+            );
+        }
+    }
+
+    #[test]
+    fn assign_operators_match_the_plain_ones() {
+        let a = Vec3::new(1.0, -2.0, 3.5);
+        let b = Vec3::new(0.5, 4.0, -1.0);
+        let mut v = a;
+        v += b;
+        assert_close(v, a + b);
+        let mut v = a;
+        v -= b;
+        assert_close(v, a - b);
+        let mut v = a;
+        v *= 3.0;
+        assert_close(v, a * 3.0);
+        let mut v = a;
+        v /= 4.0;
+        assert_close(v, a / 4.0);
+    }
+
+    #[test]
+    fn index_reads_and_writes_each_component() {
+        let mut v = Vec3::new(1.0, 2.0, 3.0);
+        assert_eq!([v[0], v[1], v[2]], [1.0, 2.0, 3.0]);
+        for i in 0..3 {
+            v[i] *= 10.0;
+        }
+        assert_eq!([v.x(), v.y(), v.z()], [10.0, 20.0, 30.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Vec3 index 3 out of range 0..3")]
+    fn index_past_z_panics() {
+        let v = Vec3::new(1.0, 2.0, 3.0);
+        let _ = v[3];
+    }
+
+    #[test]
+    fn display_prints_components_to_three_places() {
+        let v = Vec3::new(1.0, -0.5, 2.0 / 3.0);
+        assert_eq!(v.to_string(), "(1.000, -0.500, 0.667)");
+        assert_eq!(format!("{:.1}", v), "(1.0, -0.5, 0.7)");
+    }
+
+    #[test]
+    fn random_unit_vectors_lie_on_the_sphere() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..1000 {
+            let v = Vec3::random_unit_vector(&mut rng);
+            assert!((v.length() - 1.0).abs() < 1e-12, "{:?}", v);
+        }
+    }
+
+    #[test]
+    fn min_and_max_work_per_component() {
+        let a = Vec3::new(1.0, -2.0, 3.0);
+        let b = Vec3::new(0.0, 5.0, 3.5);
+        assert_eq!(Vec3::min(a, b), Vec3::new(0.0, -2.0, 3.0));
+        assert_eq!(Vec3::max(a, b), Vec3::new(1.0, 5.0, 3.5));
+        assert_eq!(a.min_component(), -2.0);
+        assert_eq!(a.max_component(), 3.0);
+    }
+
+    #[test]
+    fn lerp_hits_both_ends() {
+        let a = Vec3::new(1.0, 1.0, 1.0);
+        let b = Vec3::new(0.5, 0.7, 1.0);
+        assert_eq!(Vec3::lerp(a, b, 0.0), a);
+        assert_eq!(Vec3::lerp(a, b, 1.0), b);
+        assert_close(Vec3::lerp(a, b, 0.5), Vec3::new(0.75, 0.85, 1.0));
+    }
+
+    #[test]
+    fn clamp_bounds_each_component() {
+        let v = Vec3::new(-0.5, 0.5, 7.0);
+        assert_eq!(v.clamp(0.0, 1.0), Vec3::new(0.0, 0.5, 1.0));
+    }
+
+    #[test]
+    fn array_and_tuple_conversions_round_trip() {
+        let v = Vec3::new(1.5, -2.0, 0.25);
+        let array: [f64; 3] = v.into();
+        assert_eq!(array, [1.5, -2.0, 0.25]);
+        assert_eq!(Vec3::from(array), v);
+        assert_eq!(Vec3::from((1.5, -2.0, 0.25)), v);
+    }
+}
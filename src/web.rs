@@ -0,0 +1,197 @@
+use crate::buffer::PixelBuffer;
+use crate::camera::Camera;
+use crate::scene::{Scene, SceneError};
+use crate::util::{self, ToneMap};
+use std::path::Path;
+
+// Entry points for a browser build. They are plain functions taking and returning
+// types wasm-bindgen can pass across, so the `#[wasm_bindgen]` exports in lib.rs, built
+// with the `wasm` feature, only forward to them and turn errors into JS exceptions.
+// Rendering stays on the calling thread, as wasm has no threads by default, and blocks
+// it until done: run renders in a Web Worker to keep the page responsive.
+
+// Renders the built-in demo scene (`Scene::demo`) with the default camera, as the native
+// binary does with no arguments, and returns the image as PNG bytes
+pub fn render() -> Result<Vec<u8>, SceneError> {
+    let (image, tonemap) = render_demo(Camera::new())?;
+    return Ok(util::encode_png_rgb8(
+        image.width as u32,
+        image.height as u32,
+        &image.to_rgb8_with(tonemap),
+    )?);
+}
+
+// Like `render`, but returns raw pixels laid out as for `render_scene_rgba`
+pub fn render_rgba() -> Result<Vec<u8>, SceneError> {
+    let (image, tonemap) = render_demo(Camera::new())?;
+    return Ok(image.to_rgba8_with(tonemap));
+}
+
+// Renders a JSON scene (see `scenes/example.json`) at the given size and sample count,
+// overriding the scene's own camera settings, and returns the image as PNG bytes encoded
+// with the given display gamma (2.0 matches the native renderer; 1.0 keeps linear
+// values). OBJ and image files referenced by the scene will not load, as there is no
+// file system. Sizes over `camera::DEFAULT_MAX_PIXELS` fail with an error instead of
+// exhausting the tab's memory.
+pub fn render_scene(
+    json: &str,
+    width: u32,
+    height: u32,
+    samples: u32,
+    gamma: f64,
+) -> Result<Vec<u8>, SceneError> {
+    return render_scene_with_progress(json, width, height, samples, gamma, |_| {});
+}
+
+// Like `render_scene`, calling `progress` with the fraction of the image finished each
+// time a tile completes
+pub fn render_scene_with_progress(
+    json: &str,
+    width: u32,
+    height: u32,
+    samples: u32,
+    gamma: f64,
+    progress: impl FnMut(f64),
+) -> Result<Vec<u8>, SceneError> {
+    let (image, tonemap) = render_buffer(json, width, height, samples, gamma, progress)?;
+    return Ok(util::encode_png_rgb8(
+        width,
+        height,
+        &image.to_rgb8_with(tonemap),
+    )?);
+}
+
+// Like `render_scene`, but returns raw pixels ready for `putImageData`: row-major from
+// the top row, 4 bytes per pixel in R, G, B, A order with alpha always 255, so
+// width * height * 4 bytes in all. Skips PNG encoding here and decoding in the browser.
+pub fn render_scene_rgba(
+    json: &str,
+    width: u32,
+    height: u32,
+    samples: u32,
+    gamma: f64,
+) -> Result<Vec<u8>, SceneError> {
+    let (image, tonemap) = render_buffer(json, width, height, samples, gamma, |_| {})?;
+    return Ok(image.to_rgba8_with(tonemap));
+}
+
+fn render_demo(camera: Camera) -> Result<(PixelBuffer, ToneMap), SceneError> {
+    let scene = Scene::demo(camera);
+    let camera = scene.render_camera();
+    let image = camera
+        .render_serial_with_progress(&scene.world, |_| {})
+        .map_err(SceneError::Invalid)?;
+    return Ok((image, camera.tonemap));
+}
+
+fn render_buffer(
+    json: &str,
+    width: u32,
+    height: u32,
+    samples: u32,
+    gamma: f64,
+    progress: impl FnMut(f64),
+) -> Result<(PixelBuffer, ToneMap), SceneError> {
+    if gamma.is_nan() || gamma <= 0.0 {
+        return Err(SceneError::Invalid(format!(
+            "gamma must be positive, got {}",
+            gamma
+        )));
+    }
+    let scene = Scene::from_json(json, Path::new(""))?;
+    let camera = scene
+        .render_camera()
+        .with_resolution(width as i64, height as i64)
+        .with_samples(samples as i64)
+        .with_gamma(gamma);
+    let image = camera
+        .render_serial_with_progress(&scene.world, progress)
+        .map_err(SceneError::Invalid)?;
+    return Ok((image, camera.tonemap));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // No OBJ objects, as those load from disk
+    const SCENE: &str = r#"{
+        "materials": { "blue": { "type": "lambertian", "albedo": [0.1, 0.2, 0.5] } },
+        "objects": [
+            { "type": "sphere", "center": [0.0, 0.0, -1.0], "radius": 0.5, "material": "blue" }
+        ]
+    }"#;
+
+    #[test]
+    fn scene_renders_a_png_of_the_requested_size() {
+        let png = render_scene(SCENE, 16, 9, 1, 2.0).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        // IHDR is the first chunk, so its width and height sit at fixed offsets
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(u32::from_be_bytes(png[16..20].try_into().unwrap()), 16);
+        assert_eq!(u32::from_be_bytes(png[20..24].try_into().unwrap()), 9);
+    }
+
+    #[test]
+    fn bad_input_is_an_error() {
+        assert!(matches!(
+            render_scene("{ not json", 16, 9, 1, 2.0),
+            Err(SceneError::Json(_))
+        ));
+        assert!(render_scene(SCENE, 0, 9, 1, 2.0).is_err());
+    }
+
+    #[test]
+    fn progress_rises_to_one_once_per_tile() {
+        let mut fractions = Vec::new();
+        // 70x40 at the default 32-pixel tile size is three tiles by two
+        render_scene_with_progress(SCENE, 70, 40, 1, 2.0, |f| fractions.push(f)).unwrap();
+        assert_eq!(fractions.len(), 6);
+        assert!(fractions.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(fractions.last(), Some(&1.0));
+    }
+
+    #[test]
+    fn rgba_output_is_four_bytes_per_pixel_with_opaque_alpha() {
+        let empty = r#"{ "background": [1.0, 0.25, 0.0], "materials": {}, "objects": [] }"#;
+        let rgba = render_scene_rgba(empty, 5, 3, 1, 2.0).unwrap();
+        assert_eq!(rgba.len(), 60);
+        assert_eq!(&rgba[..4], &[255, 128, 0, 255]);
+    }
+
+    #[test]
+    fn oversized_renders_return_an_error() {
+        match render_scene("{}", 16000, 16000, 1, 2.0) {
+            Err(SceneError::Invalid(message)) => assert_eq!(
+                message,
+                "image size 16000x16000 is 256000000 pixels, over the limit of 16777216"
+            ),
+            _ => panic!("oversized render was not rejected"),
+        }
+    }
+
+    #[test]
+    fn demo_renders_as_opaque_rgba() {
+        let camera = Camera::new()
+            .with_resolution(6, 4)
+            .with_samples(1)
+            .with_seed(1);
+        let (image, tonemap) = render_demo(camera).unwrap();
+        let rgba = image.to_rgba8_with(tonemap);
+        assert_eq!(rgba.len(), 6 * 4 * 4);
+        assert!(rgba.chunks(4).all(|pixel| pixel[3] == 255));
+        // The top row looks up into the sky, not at black
+        assert!(rgba[..4].iter().take(3).all(|&c| c > 0));
+    }
+
+    #[test]
+    fn demo_errors_are_returned_rather_than_panicking() {
+        let camera = Camera::new().with_resolution(20, 20).with_max_pixels(100);
+        match render_demo(camera) {
+            Err(SceneError::Invalid(message)) => {
+                assert!(message.contains("over the limit of 100"), "{}", message)
+            }
+            _ => panic!("oversized demo render was not rejected"),
+        }
+    }
+}
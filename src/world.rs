@@ -1,17 +1,33 @@
+use crate::aabb::Aabb;
+use crate::batch::TriangleBatch;
+use crate::bvh::BvhNode;
 use crate::interval::Interval;
 use crate::material::{Lambertian, Material};
-use crate::vec3::Vec3;
+use crate::stats::{self, Counter};
+use crate::vec3::{Onb, Vec3};
+use rand::{Rng, RngCore};
+use std::f64::consts::PI;
+use std::fmt;
+use std::io::{self, BufRead};
 use std::sync::Arc;
 
+#[derive(Copy, Clone)]
 pub struct Ray {
     pub origin: Vec3,
     pub dir: Vec3,
+    // Moment within the camera shutter interval the ray samples, for motion blur
+    pub time: f64,
 }
 
 impl Ray {
     pub const fn new(origin: Vec3, dir: Vec3) -> Self {
-        return Self { origin, dir };
+        return Self::with_time(origin, dir, 0.0);
     }
+
+    pub const fn with_time(origin: Vec3, dir: Vec3, time: f64) -> Self {
+        return Self { origin, dir, time };
+    }
+
     pub fn at(&self, t: f64) -> Vec3 {
         return self.origin + self.dir * t;
     }
@@ -26,6 +42,12 @@ pub struct HitRecord {
     pub normal: Vec3,
     pub front_face: bool,
     pub material: Arc<dyn Material>,
+    // Surface coordinates for texture lookups, each in [0, 1]
+    pub u: f64,
+    pub v: f64,
+    // Rough width in world units of the surface patch one camera sample covers, for
+    // choosing texture mip levels; 0 when unknown
+    pub footprint: f64,
 }
 
 impl HitRecord {
@@ -36,6 +58,9 @@ impl HitRecord {
             normal: Vec3(0.0, 0.0, 0.0),
             front_face: false,
             material: Arc::new(Lambertian::new(Vec3::EMPTY)),
+            u: 0.0,
+            v: 0.0,
+            footprint: 0.0,
         };
     }
 
@@ -49,6 +74,12 @@ impl HitRecord {
     }
 }
 
+impl Default for HitRecord {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
 pub enum HitResult {
     Hit(HitRecord),
     Miss,
@@ -56,23 +87,119 @@ pub enum HitResult {
 
 pub trait Hittable: Send + Sync {
     fn hit(&self, ray: &Ray, interval: Interval) -> HitResult;
+    fn bounding_box(&self) -> Aabb;
+
+    // Whether anything is hit within `interval`, for occlusion tests. Aggregates stop at
+    // the first hit they find rather than looking for the nearest.
+    fn hit_any(&self, ray: &Ray, interval: Interval) -> bool {
+        return matches!(self.hit(ray, interval), HitResult::Hit(_));
+    }
+
+    // Density, per steradian, of `random` returning `direction` from `origin`. Objects
+    // that do not implement `random` are never sampled and report 0.
+    fn pdf_value(&self, _origin: Vec3, _direction: Vec3) -> f64 {
+        return 0.0;
+    }
+
+    // Random direction from `origin` toward the object, for sampling it as a light
+    fn random(&self, _origin: Vec3, _rng: &mut dyn RngCore) -> Vec3 {
+        return Vec3::new(1.0, 0.0, 0.0);
+    }
+}
+
+// Infinitesimal light used for direct lighting; radiated power falls off with the
+// square of the distance
+#[derive(Copy, Clone)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub color: Vec3,
+    pub intensity: f64,
+}
+
+impl PointLight {
+    pub const fn new(position: Vec3, color: Vec3, intensity: f64) -> Self {
+        return Self {
+            position,
+            color,
+            intensity,
+        };
+    }
 }
 
 #[derive(Clone)]
 pub struct HittableList {
     pub vec: Vec<Arc<dyn Hittable>>,
+    // Point lights shading diffuse surfaces directly; empty unless lights are added
+    pub lights: Vec<PointLight>,
+    // Emissive objects that bounces off diffuse surfaces are partly aimed at. Each must
+    // also be in `vec` to be seen, and implement `Hittable::random`.
+    pub area_lights: Vec<Arc<dyn Hittable>>,
+    // Tree over `vec` from `build_bvh`, answering `hit` and `hit_any` while set
+    bvh: Option<Arc<BvhNode>>,
 }
 
 impl HittableList {
     pub fn new() -> Self {
-        return Self { vec: Vec::new() };
+        return Self {
+            vec: Vec::new(),
+            lights: Vec::new(),
+            area_lights: Vec::new(),
+            bvh: None,
+        };
     }
 
     pub fn add(&mut self, s: Arc<dyn Hittable>) {
+        self.bvh = None;
         self.vec.push(s);
     }
 
+    // Builds a BVH over the objects to speed up `hit` and `hit_any`. Object indices are
+    // unchanged. Adding or removing objects drops the tree, so call this once the list is
+    // complete; edits made through `vec` directly need another call.
+    pub fn build_bvh(&mut self) {
+        let objects = self.vec.iter().cloned().collect();
+        self.bvh = Some(Arc::new(BvhNode::new(objects)));
+    }
+
+    pub fn add_light(&mut self, light: PointLight) {
+        self.lights.push(light);
+    }
+
+    // Registers an object already added with `add` for light sampling
+    pub fn add_area_light(&mut self, light: Arc<dyn Hittable>) {
+        self.area_lights.push(light);
+    }
+
+    // Number of objects; lights are not counted
+    pub fn len(&self) -> usize {
+        return self.vec.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.vec.is_empty();
+    }
+
+    // Takes out the object at `index` and returns it. Later objects move down by one, so
+    // indices held elsewhere, such as `Camera::isolate`, may need updating. Panics if
+    // `index` is out of range.
+    pub fn remove(&mut self, index: usize) -> Arc<dyn Hittable> {
+        self.bvh = None;
+        return self.vec.remove(index);
+    }
+
+    // Removes every object and light
+    pub fn clear(&mut self) {
+        self.bvh = None;
+        self.vec.clear();
+        self.lights.clear();
+        self.area_lights.clear();
+    }
+
     pub fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
+        if let Some(bvh) = &self.bvh {
+            return bvh.hit(ray, interval);
+        }
+
         let mut rec = HitRecord::new();
         let mut hit_anything = false;
         let mut closest_so_far = interval.max;
@@ -92,6 +219,84 @@ impl HittableList {
             HitResult::Miss
         };
     }
+
+    // Whether any object is hit within `interval`, stopping at the first one found
+    pub fn hit_any(&self, ray: &Ray, interval: Interval) -> bool {
+        if let Some(bvh) = &self.bvh {
+            return bvh.hit_any(ray, interval);
+        }
+        return self.vec.iter().any(|s| s.hit_any(ray, interval));
+    }
+
+    // Like `hit`, but only considers the objects at the given indices
+    pub fn hit_subset(&self, ray: &Ray, interval: Interval, ids: &[usize]) -> HitResult {
+        let mut rec = HitRecord::new();
+        let mut hit_anything = false;
+        let mut closest_so_far = interval.max;
+
+        for s in ids.iter().filter_map(|&id| self.vec.get(id)) {
+            let hit = (*s).hit(ray, Interval::new(interval.min, closest_so_far));
+            if let HitResult::Hit(temp_rec) = hit {
+                hit_anything = true;
+                closest_so_far = temp_rec.t;
+                rec = temp_rec.clone();
+            }
+        }
+
+        return if hit_anything {
+            HitResult::Hit(rec)
+        } else {
+            HitResult::Miss
+        };
+    }
+
+    // Like `hit_any`, but only considers the objects at the given indices
+    pub fn hit_any_subset(&self, ray: &Ray, interval: Interval, ids: &[usize]) -> bool {
+        return ids
+            .iter()
+            .filter_map(|&id| self.vec.get(id))
+            .any(|s| s.hit_any(ray, interval));
+    }
+}
+
+impl Hittable for HittableList {
+    fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
+        return HittableList::hit(self, ray, interval);
+    }
+
+    fn hit_any(&self, ray: &Ray, interval: Interval) -> bool {
+        return HittableList::hit_any(self, ray, interval);
+    }
+
+    // Union of the children's boxes
+    fn bounding_box(&self) -> Aabb {
+        return self
+            .vec
+            .iter()
+            .fold(Aabb::EMPTY, |b, s| Aabb::surrounding(&b, &s.bounding_box()));
+    }
+}
+
+impl Default for HittableList {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl Extend<Arc<dyn Hittable>> for HittableList {
+    fn extend<I: IntoIterator<Item = Arc<dyn Hittable>>>(&mut self, iter: I) {
+        self.bvh = None;
+        self.vec.extend(iter);
+    }
+}
+
+// A list of the given objects, with no lights
+impl FromIterator<Arc<dyn Hittable>> for HittableList {
+    fn from_iter<I: IntoIterator<Item = Arc<dyn Hittable>>>(iter: I) -> Self {
+        let mut list = Self::new();
+        list.extend(iter);
+        return list;
+    }
 }
 
 #[derive(Clone)]
@@ -109,40 +314,159 @@ impl Sphere {
             material: Arc::clone(material),
         };
     }
+
+    // Spherical coordinates of a point on the unit sphere. u runs around the y axis
+    // starting from -x, v runs from the pole at y = -1 up to y = 1.
+    fn uv(p: Vec3) -> (f64, f64) {
+        let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+        return (phi / (2.0 * PI), theta / PI);
+    }
 }
 
 impl Hittable for Sphere {
     fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
-        let oc = self.center - ray.origin;
+        return hit_sphere(self.center, self.radius, &self.material, ray, interval);
+    }
 
-        let a = ray.dir.length_squared();
-        let h = Vec3::dot(ray.dir, oc);
-        let c = oc.length_squared() - self.radius * self.radius;
-        let discriminant = h * h - a * c;
+    fn bounding_box(&self) -> Aabb {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        return Aabb::from_points(self.center - r, self.center + r);
+    }
 
-        if discriminant < 0.0 {
-            return HitResult::Miss;
+    // Uniform over the cone of directions the sphere covers, or over all directions
+    // from inside it
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        let ray = Ray::new(origin, direction);
+        if !self.hit_any(&ray, Interval::ALMOST_FORWARD) {
+            return 0.0;
         }
+        return match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => 1.0 / (2.0 * PI * (1.0 - cos_theta_max)),
+            None => 1.0 / (4.0 * PI),
+        };
+    }
 
-        let sqrtd = discriminant.sqrt();
-        let mut root = (h - sqrtd) / a;
-        if !interval.surrounds(root) {
-            root = (h + sqrtd) / a;
-            if !interval.surrounds(root) {
-                return HitResult::Miss;
-            }
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        let cos_theta_max = match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => cos_theta_max,
+            None => return Vec3::random_unit_vector(rng),
+        };
+        let phi = 2.0 * PI * rng.random::<f64>();
+        let z = 1.0 + rng.random::<f64>() * (cos_theta_max - 1.0);
+        let r = (1.0 - z * z).sqrt();
+        let local = Vec3::new(phi.cos() * r, phi.sin() * r, z);
+        return Onb::new(self.center - origin).transform(local);
+    }
+}
+
+impl Sphere {
+    // Cosine of the half-angle of the cone the sphere fills as seen from `origin`; None
+    // when `origin` is inside
+    fn cos_theta_max(&self, origin: Vec3) -> Option<f64> {
+        let distance_squared = (self.center - origin).length_squared();
+        let ratio = self.radius * self.radius / distance_squared;
+        return if ratio < 1.0 {
+            Some((1.0 - ratio).sqrt())
+        } else {
+            None
+        };
+    }
+}
+
+// Sphere whose center moves in a straight line from `center0` at `time0` to `center1`
+// at `time1`, continuing at the same speed outside that range
+#[derive(Clone)]
+pub struct MovingSphere {
+    pub center0: Vec3,
+    pub center1: Vec3,
+    pub time0: f64,
+    pub time1: f64,
+    pub radius: f64,
+    pub material: Arc<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Vec3,
+        center1: Vec3,
+        time0: f64,
+        time1: f64,
+        radius: f64,
+        material: &Arc<dyn Material>,
+    ) -> Self {
+        return Self {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material: Arc::clone(material),
+        };
+    }
+
+    pub fn center(&self, time: f64) -> Vec3 {
+        if self.time1 == self.time0 {
+            return self.center0;
         }
+        let t = (time - self.time0) / (self.time1 - self.time0);
+        return Vec3::lerp(self.center0, self.center1, t);
+    }
+}
 
-        let mut rec = HitRecord::new();
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
+        let center = self.center(ray.time);
+        return hit_sphere(center, self.radius, &self.material, ray, interval);
+    }
 
-        rec.t = root;
-        rec.point = ray.at(rec.t);
-        let outward_normal = (rec.point - self.center) / self.radius;
-        rec.set_face_normal(ray, outward_normal);
-        rec.material = Arc::clone(&self.material);
+    // Encloses the sphere over [time0, time1], which is what the camera shutter covers
+    fn bounding_box(&self) -> Aabb {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        let start = Aabb::from_points(self.center0 - r, self.center0 + r);
+        let end = Aabb::from_points(self.center1 - r, self.center1 + r);
+        return Aabb::surrounding(&start, &end);
+    }
+}
 
-        return HitResult::Hit(rec);
+fn hit_sphere(
+    center: Vec3,
+    radius: f64,
+    material: &Arc<dyn Material>,
+    ray: &Ray,
+    interval: Interval,
+) -> HitResult {
+    stats::record(Counter::RaySphereTests, 1);
+    let oc = center - ray.origin;
+
+    let a = ray.dir.length_squared();
+    let h = Vec3::dot(ray.dir, oc);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = h * h - a * c;
+
+    if discriminant < 0.0 {
+        return HitResult::Miss;
+    }
+
+    let sqrtd = discriminant.sqrt();
+    let mut root = (h - sqrtd) / a;
+    if !interval.surrounds(root) {
+        root = (h + sqrtd) / a;
+        if !interval.surrounds(root) {
+            return HitResult::Miss;
+        }
     }
+
+    let mut rec = HitRecord::new();
+
+    rec.t = root;
+    rec.point = ray.at(rec.t);
+    let outward_normal = (rec.point - center) / radius;
+    rec.set_face_normal(ray, outward_normal);
+    (rec.u, rec.v) = Sphere::uv(outward_normal);
+    rec.material = Arc::clone(material);
+
+    return HitResult::Hit(rec);
 }
 
 #[derive(Clone)]
@@ -150,6 +474,13 @@ pub struct Triangle {
     pub a: Vec3,
     pub b: Vec3,
     pub c: Vec3,
+    // Per-vertex normals for smooth shading; the flat face normal is used without them
+    pub normals: Option<[Vec3; 3]>,
+    // Per-vertex texture coordinates; the barycentric coordinates are used without them
+    pub uvs: Option<[(f64, f64); 3]>,
+    // When set, rays reaching the back of the triangle pass through. The front is the side
+    // a, b, c wind counterclockwise around.
+    pub cull_backfaces: bool,
     pub material: Arc<dyn Material>,
 }
 
@@ -159,38 +490,1312 @@ impl Triangle {
             a,
             b,
             c,
+            normals: None,
+            uvs: None,
+            cull_backfaces: false,
             material: Arc::clone(material),
         };
     }
+
+    // Triangle shaded by interpolating the normals given at a, b and c
+    pub fn with_normals(
+        a: Vec3,
+        b: Vec3,
+        c: Vec3,
+        normals: [Vec3; 3],
+        material: &Arc<dyn Material>,
+    ) -> Self {
+        return Self {
+            normals: Some(normals),
+            ..Self::new(a, b, c, material)
+        };
+    }
+
+    // Same triangle with texture coordinates given at a, b and c
+    pub fn with_uvs(self, uvs: [(f64, f64); 3]) -> Self {
+        return Self {
+            uvs: Some(uvs),
+            ..self
+        };
+    }
+
+    // Single-sided triangles for closed meshes, whose back faces are never visible
+    pub fn with_backface_culling(self, cull_backfaces: bool) -> Self {
+        return Self {
+            cull_backfaces,
+            ..self
+        };
+    }
 }
 
-impl Hittable for Triangle {
-    fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
+impl Triangle {
+    // `hit` without counting a ray-triangle test, for `TriangleBatch`, which has already
+    // counted this triangle while finding the nearest one
+    pub(crate) fn hit_uncounted(&self, ray: &Ray, interval: Interval) -> HitResult {
+        // Möller–Trumbore: solve origin + t dir = a + u ab + v ac, leaving as soon as
+        // one barycentric coordinate falls outside the triangle
         let ab = self.b - self.a;
         let ac = self.c - self.a;
-        let normal = Vec3::cross(ab, ac).unit();
 
-        if Vec3::dot(ray.dir, normal) == 0.0 {
+        let p = Vec3::cross(ray.dir, ac);
+        // Positive when the ray meets the front face
+        let det = Vec3::dot(ab, p);
+        if self.cull_backfaces && det < 0.0 {
+            return HitResult::Miss;
+        }
+        // Parallel to the triangle, or close enough that the solution is unstable
+        if det.abs() < 1e-12 {
             return HitResult::Miss;
         }
+        let inv_det = 1.0 / det;
 
-        let b = ray.origin - self.a;
+        let s = ray.origin - self.a;
+        let u = Vec3::dot(s, p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return HitResult::Miss;
+        }
 
-        let det_a = Vec3::det(-ray.dir, ab, ac);
-        let t = Vec3::det(b, ab, ac) / det_a;
-        let u = Vec3::det(-ray.dir, b, ac) / det_a;
-        let v = Vec3::det(-ray.dir, ab, b) / det_a;
+        let q = Vec3::cross(s, ab);
+        let v = Vec3::dot(ray.dir, q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return HitResult::Miss;
+        }
 
-        if u < 0.0 || v < 0.0 || u + v > 1.0 || !interval.contains(t) {
+        let t = Vec3::dot(ac, q) * inv_det;
+        if !interval.contains(t) {
             return HitResult::Miss;
         }
 
+        let normal = Vec3::cross(ab, ac).unit();
         let mut rec = HitRecord::new();
         rec.t = t;
         rec.point = ray.at(rec.t);
         rec.set_face_normal(ray, normal);
+        if let Some([na, nb, nc]) = self.normals {
+            // Barycentric blend, kept on the same side as the geometric normal. Zero or
+            // opposing vertex normals can cancel out, leaving the geometric normal.
+            let blend = na * (1.0 - u - v) + nb * u + nc * v;
+            if blend.length() > 1e-8 {
+                let shading = blend.unit();
+                rec.normal = if rec.front_face { shading } else { -shading };
+            }
+        }
+        (rec.u, rec.v) = match self.uvs {
+            Some([ta, tb, tc]) => (
+                ta.0 * (1.0 - u - v) + tb.0 * u + tc.0 * v,
+                ta.1 * (1.0 - u - v) + tb.1 * u + tc.1 * v,
+            ),
+            None => (u, v),
+        };
+        rec.material = Arc::clone(&self.material);
+
+        return HitResult::Hit(rec);
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
+        stats::record(Counter::RayTriangleTests, 1);
+        return self.hit_uncounted(ray, interval);
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let b = Aabb::from_points(self.a, self.b);
+        let b = Aabb::surrounding(&b, &Aabb::from_points(self.a, self.c));
+        // Axis-aligned triangles are flat in one axis
+        return b.padded();
+    }
+}
+
+// Parallelogram with corner `q` and edges `u` and `v`
+#[derive(Clone)]
+pub struct Quad {
+    pub q: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: Arc<dyn Material>,
+    normal: Vec3,
+    area: f64,
+    // Plane offset, so points p on the plane satisfy dot(normal, p) = d
+    d: f64,
+    // Maps plane points to (alpha, beta) coordinates along u and v
+    w: Vec3,
+}
+
+impl Quad {
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, material: &Arc<dyn Material>) -> Self {
+        let n = Vec3::cross(u, v);
+        let normal = n.unit();
+        return Self {
+            q,
+            u,
+            v,
+            material: Arc::clone(material),
+            normal,
+            area: n.length(),
+            d: Vec3::dot(normal, q),
+            w: n / Vec3::dot(n, n),
+        };
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
+        let denom = Vec3::dot(self.normal, ray.dir);
+
+        // Parallel to the plane
+        if denom.abs() < 1e-8 {
+            return HitResult::Miss;
+        }
+
+        let t = (self.d - Vec3::dot(self.normal, ray.origin)) / denom;
+        if !interval.contains(t) {
+            return HitResult::Miss;
+        }
+
+        let point = ray.at(t);
+        let planar = point - self.q;
+        let alpha = Vec3::dot(self.w, Vec3::cross(planar, self.v));
+        let beta = Vec3::dot(self.w, Vec3::cross(self.u, planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return HitResult::Miss;
+        }
+
+        let mut rec = HitRecord::new();
+        rec.t = t;
+        rec.point = point;
+        rec.set_face_normal(ray, self.normal);
+        (rec.u, rec.v) = (alpha, beta);
+        rec.material = Arc::clone(&self.material);
+
+        return HitResult::Hit(rec);
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let diagonal1 = Aabb::from_points(self.q, self.q + self.u + self.v);
+        let diagonal2 = Aabb::from_points(self.q + self.u, self.q + self.v);
+        return Aabb::surrounding(&diagonal1, &diagonal2).padded();
+    }
+
+    // Uniform over the quad's area, converted to solid angle as seen from `origin`
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        let ray = Ray::new(origin, direction);
+        if let HitResult::Hit(rec) = self.hit(&ray, Interval::ALMOST_FORWARD) {
+            let distance_squared = rec.t * rec.t * direction.length_squared();
+            let cosine = Vec3::dot(direction, self.normal).abs() / direction.length();
+            return distance_squared / (cosine * self.area);
+        }
+        return 0.0;
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        let point = self.q + self.u * rng.random::<f64>() + self.v * rng.random::<f64>();
+        return point - origin;
+    }
+}
+
+// Axis-aligned box made of six quads, with each face's normal pointing outward
+pub struct BoxPrim {
+    pub sides: HittableList,
+}
+
+impl BoxPrim {
+    pub fn new(min: Vec3, max: Vec3, material: &Arc<dyn Material>) -> Self {
+        let lo = Vec3::min(min, max);
+        let hi = Vec3::max(min, max);
+
+        let dx = Vec3::new(hi.x() - lo.x(), 0.0, 0.0);
+        let dy = Vec3::new(0.0, hi.y() - lo.y(), 0.0);
+        let dz = Vec3::new(0.0, 0.0, hi.z() - lo.z());
+
+        // Edge order is chosen so cross(u, v) faces out of the box
+        let mut sides = HittableList::new();
+        let faces = [
+            (Vec3::new(lo.x(), lo.y(), hi.z()), dx, dy),  // front
+            (Vec3::new(hi.x(), lo.y(), hi.z()), -dz, dy), // right
+            (Vec3::new(hi.x(), lo.y(), lo.z()), -dx, dy), // back
+            (Vec3::new(lo.x(), lo.y(), lo.z()), dz, dy),  // left
+            (Vec3::new(lo.x(), hi.y(), hi.z()), dx, -dz), // top
+            (Vec3::new(lo.x(), lo.y(), lo.z()), dx, dz),  // bottom
+        ];
+        for (q, u, v) in faces {
+            sides.add(Arc::new(Quad::new(q, u, v, material)));
+        }
+
+        return Self { sides };
+    }
+}
+
+impl Hittable for BoxPrim {
+    fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
+        return self.sides.hit(ray, interval);
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        return self.sides.bounding_box();
+    }
+}
+
+// Infinite plane through `point`
+#[derive(Clone)]
+pub struct Plane {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub material: Arc<dyn Material>,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, material: &Arc<dyn Material>) -> Self {
+        return Self {
+            point,
+            normal: normal.unit(),
+            material: Arc::clone(material),
+        };
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
+        let denom = Vec3::dot(self.normal, ray.dir);
+
+        // Parallel to the plane
+        if denom.abs() < 1e-8 {
+            return HitResult::Miss;
+        }
+
+        let t = Vec3::dot(self.normal, self.point - ray.origin) / denom;
+        if !interval.contains(t) {
+            return HitResult::Miss;
+        }
+
+        let mut rec = HitRecord::new();
+        rec.t = t;
+        rec.point = ray.at(t);
+        rec.set_face_normal(ray, self.normal);
+        rec.material = Arc::clone(&self.material);
+
+        return HitResult::Hit(rec);
+    }
+
+    // A plane has no finite bounds; the BVH keeps infinite boxes out of its tree
+    fn bounding_box(&self) -> Aabb {
+        return Aabb::UNIVERSE;
+    }
+}
+
+// Flat circular disk facing `normal`
+#[derive(Clone)]
+pub struct Disk {
+    pub center: Vec3,
+    pub normal: Vec3,
+    pub radius: f64,
+    pub material: Arc<dyn Material>,
+    // In-plane frame for the angular texture coordinate
+    frame: Onb,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, material: &Arc<dyn Material>) -> Self {
+        return Self {
+            center,
+            normal: normal.unit(),
+            radius,
+            material: Arc::clone(material),
+            frame: Onb::new(normal),
+        };
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
+        let denom = Vec3::dot(self.normal, ray.dir);
+
+        // Parallel to the disk
+        if denom.abs() < 1e-8 {
+            return HitResult::Miss;
+        }
+
+        let t = Vec3::dot(self.normal, self.center - ray.origin) / denom;
+        if !interval.contains(t) {
+            return HitResult::Miss;
+        }
+        let offset = ray.at(t) - self.center;
+        let distance = offset.length();
+        if distance > self.radius {
+            return HitResult::Miss;
+        }
+
+        let mut rec = HitRecord::new();
+        rec.t = t;
+        rec.point = ray.at(t);
+        rec.set_face_normal(ray, self.normal);
+        // u runs once around the disk, v out from the center to the rim
+        let angle = Vec3::dot(offset, self.frame.v).atan2(Vec3::dot(offset, self.frame.u));
+        rec.u = (angle + PI) / (2.0 * PI);
+        rec.v = distance / self.radius;
         rec.material = Arc::clone(&self.material);
 
         return HitResult::Hit(rec);
     }
+
+    // How far the rim reaches along each world axis
+    fn bounding_box(&self) -> Aabb {
+        let reach = |n: f64| self.radius * (1.0 - n * n).max(0.0).sqrt();
+        let r = Vec3::new(
+            reach(self.normal.x()),
+            reach(self.normal.y()),
+            reach(self.normal.z()),
+        );
+        return Aabb::from_points(self.center - r, self.center + r).padded();
+    }
+}
+
+// Finite cylinder standing on the disk at `base` and reaching `height` along `axis`.
+// Capped cylinders are closed by flat disks at both ends; open ones are bare tubes.
+#[derive(Clone)]
+pub struct Cylinder {
+    pub base: Vec3,
+    pub axis: Vec3,
+    pub radius: f64,
+    pub height: f64,
+    pub capped: bool,
+    pub material: Arc<dyn Material>,
+    // Frame around the axis for the angular texture coordinate
+    frame: Onb,
+}
+
+impl Cylinder {
+    pub fn new(
+        base: Vec3,
+        axis: Vec3,
+        radius: f64,
+        height: f64,
+        material: &Arc<dyn Material>,
+    ) -> Self {
+        return Self {
+            base,
+            axis: axis.unit(),
+            radius,
+            height,
+            capped: true,
+            material: Arc::clone(material),
+            frame: Onb::new(axis),
+        };
+    }
+
+    pub fn with_caps(mut self, capped: bool) -> Self {
+        self.capped = capped;
+        return self;
+    }
+
+    // Nearest intersection with the curved side within `interval`, and the point's
+    // height along the axis
+    fn hit_side(&self, ray: &Ray, interval: Interval) -> Option<(f64, f64)> {
+        // Solve in the plane perpendicular to the axis, where the side is a circle
+        let oc = ray.origin - self.base;
+        let d_perp = ray.dir - self.axis * Vec3::dot(ray.dir, self.axis);
+        let o_perp = oc - self.axis * Vec3::dot(oc, self.axis);
+
+        let a = d_perp.length_squared();
+        if a < 1e-12 {
+            // Parallel to the axis
+            return None;
+        }
+        let h = Vec3::dot(d_perp, o_perp);
+        let c = o_perp.length_squared() - self.radius * self.radius;
+        let discriminant = h * h - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let sqrtd = discriminant.sqrt();
+        for t in [(-h - sqrtd) / a, (-h + sqrtd) / a] {
+            let y = Vec3::dot(oc + ray.dir * t, self.axis);
+            if interval.surrounds(t) && (0.0..=self.height).contains(&y) {
+                return Some((t, y));
+            }
+        }
+        return None;
+    }
+
+    // Intersection with the cap at height `y` (0 or `height`) within `interval`
+    fn hit_cap(&self, ray: &Ray, interval: Interval, y: f64) -> Option<f64> {
+        let denom = Vec3::dot(ray.dir, self.axis);
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let center = self.base + self.axis * y;
+        let t = Vec3::dot(center - ray.origin, self.axis) / denom;
+        if !interval.surrounds(t)
+            || (ray.at(t) - center).length_squared() > self.radius * self.radius
+        {
+            return None;
+        }
+        return Some(t);
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
+        let mut rec = HitRecord::new();
+        let mut closest = interval.max;
+
+        if let Some((t, y)) = self.hit_side(ray, interval) {
+            closest = t;
+            rec.t = t;
+            rec.point = ray.at(t);
+            let outward_normal = (rec.point - self.base - self.axis * y) / self.radius;
+            rec.set_face_normal(ray, outward_normal);
+            let angle = Vec3::dot(outward_normal, self.frame.v)
+                .atan2(Vec3::dot(outward_normal, self.frame.u));
+            rec.u = (angle + PI) / (2.0 * PI);
+            rec.v = y / self.height;
+        }
+
+        if self.capped {
+            for (y, outward_normal) in [(0.0, -self.axis), (self.height, self.axis)] {
+                let within = Interval::new(interval.min, closest);
+                if let Some(t) = self.hit_cap(ray, within, y) {
+                    closest = t;
+                    rec.t = t;
+                    rec.point = ray.at(t);
+                    rec.set_face_normal(ray, outward_normal);
+                    // Caps map their disk onto the unit square
+                    let offset = (rec.point - self.base - self.axis * y) / self.radius;
+                    rec.u = 0.5 + 0.5 * Vec3::dot(offset, self.frame.u);
+                    rec.v = 0.5 + 0.5 * Vec3::dot(offset, self.frame.v);
+                }
+            }
+        }
+
+        if closest == interval.max {
+            return HitResult::Miss;
+        }
+        rec.material = Arc::clone(&self.material);
+        return HitResult::Hit(rec);
+    }
+
+    // Both end disks, each padded by how far a disk perpendicular to the axis reaches
+    // along every world axis
+    fn bounding_box(&self) -> Aabb {
+        let reach = |a: f64| self.radius * (1.0 - a * a).max(0.0).sqrt();
+        let r = Vec3::new(
+            reach(self.axis.x()),
+            reach(self.axis.y()),
+            reach(self.axis.z()),
+        );
+        let top = self.base + self.axis * self.height;
+        let bottom_box = Aabb::from_points(self.base - r, self.base + r);
+        let top_box = Aabb::from_points(top - r, top + r);
+        return Aabb::surrounding(&bottom_box, &top_box).padded();
+    }
+}
+
+// Why an OBJ file could not be loaded. Line numbers start at 1.
+#[derive(Debug)]
+pub enum ObjError {
+    Io(io::Error),
+    Parse { line: usize, token: String },
+    IndexOutOfRange { line: usize, index: i64 },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            ObjError::Io(e) => write!(f, "could not read OBJ: {}", e),
+            ObjError::Parse { line, token } => {
+                write!(f, "line {}: could not parse {:?}", line, token)
+            }
+            ObjError::IndexOutOfRange { line, index } => {
+                write!(f, "line {}: index {} is out of range", line, index)
+            }
+        };
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+impl From<io::Error> for ObjError {
+    fn from(e: io::Error) -> Self {
+        return ObjError::Io(e);
+    }
+}
+
+// Triangles per BVH leaf in a loaded mesh. Each leaf is one box test followed by a
+// tight loop over packed triangles, which beats a box per triangle this deep in the tree.
+const MESH_BATCH_SIZE: usize = 8;
+
+// Triangle mesh loaded from a Wavefront OBJ file
+#[derive(Clone)]
+pub struct Polygon {
+    // The mesh's only copy of its triangles, shared with the BVH leaves
+    batches: Vec<Arc<TriangleBatch>>,
+    // Built on load over `batches`, and used for every hit test
+    bvh: Arc<BvhNode>,
+}
+
+// Position, texture coordinates and normal of one face corner
+type Corner = (Vec3, Option<(f64, f64)>, Option<Vec3>);
+
+impl Polygon {
+    // Reads `v`, `vt`, `vn` and `f` lines. Faces where every vertex has a normal are
+    // smooth shaded, and faces where every vertex has a `vt` carry texture coordinates.
+    // Faces with more than three vertices are split into a triangle fan.
+    pub fn from_reader(
+        mut input: impl BufRead,
+        material: &Arc<dyn Material>,
+    ) -> Result<Self, ObjError> {
+        let mut vertices: Vec<Vec3> = Vec::new();
+        let mut normals: Vec<Vec3> = Vec::new();
+        let mut texcoords: Vec<(f64, f64)> = Vec::new();
+        let mut triangles: Vec<Triangle> = Vec::new();
+
+        // Reused across lines to keep allocations down on large meshes
+        let mut line = String::new();
+        let mut corners: Vec<Corner> = Vec::new();
+        let mut line_no = 0;
+
+        while input.read_line(&mut line)? > 0 {
+            line_no += 1;
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("v") => vertices.push(Self::parse_vec3(tokens, line_no)?),
+                Some("vn") => normals.push(Self::parse_vec3(tokens, line_no)?),
+                Some("vt") => texcoords.push(Self::parse_uv(tokens, line_no)?),
+                Some("f") => {
+                    corners.clear();
+                    for token in tokens {
+                        let (v, t, n) = Self::parse_face_vertex(token, line_no)?;
+                        let vertex = Self::lookup(&vertices, v, line_no)?;
+                        let uv = match t {
+                            Some(t) => Some(Self::lookup(&texcoords, t, line_no)?),
+                            None => None,
+                        };
+                        let normal = match n {
+                            Some(n) => Some(Self::lookup(&normals, n, line_no)?),
+                            None => None,
+                        };
+                        corners.push((vertex, uv, normal));
+                    }
+                    // Fan out from the first corner so quads and larger faces become
+                    // triangles; faces with fewer than three corners add nothing
+                    for i in 1..corners.len().saturating_sub(1) {
+                        let (a, b, c) = (corners[0], corners[i], corners[i + 1]);
+                        let mut triangle = match (a.2, b.2, c.2) {
+                            (Some(na), Some(nb), Some(nc)) => {
+                                Triangle::with_normals(a.0, b.0, c.0, [na, nb, nc], material)
+                            }
+                            _ => Triangle::new(a.0, b.0, c.0, material),
+                        };
+                        if let (Some(ta), Some(tb), Some(tc)) = (a.1, b.1, c.1) {
+                            triangle = triangle.with_uvs([ta, tb, tc]);
+                        }
+                        triangles.push(triangle);
+                    }
+                }
+                _ => {}
+            }
+            line.clear();
+        }
+
+        let batches: Vec<Arc<TriangleBatch>> = TriangleBatch::grouped(triangles, MESH_BATCH_SIZE)
+            .into_iter()
+            .map(Arc::new)
+            .collect();
+        let mut leaves = HittableList::new();
+        for batch in &batches {
+            leaves.add(Arc::clone(batch) as Arc<dyn Hittable>);
+        }
+        let bvh = Arc::new(BvhNode::new(leaves));
+        return Ok(Self { batches, bvh });
+    }
+
+    // Every triangle in the mesh, in BVH leaf order rather than file order
+    pub fn triangles(&self) -> impl Iterator<Item = &Triangle> {
+        return self.batches.iter().flat_map(|b| b.triangles());
+    }
+
+    fn parse_vec3<'a>(
+        mut tokens: impl Iterator<Item = &'a str>,
+        line: usize,
+    ) -> Result<Vec3, ObjError> {
+        let mut next = || {
+            let token = tokens.next().unwrap_or("");
+            return token.parse::<f64>().map_err(|_| ObjError::Parse {
+                line,
+                token: token.to_string(),
+            });
+        };
+        return Ok(Vec3::new(next()?, next()?, next()?));
+    }
+
+    // Texture coordinates; an optional third `w` component is ignored
+    fn parse_uv<'a>(
+        mut tokens: impl Iterator<Item = &'a str>,
+        line: usize,
+    ) -> Result<(f64, f64), ObjError> {
+        let mut next = || {
+            let token = tokens.next().unwrap_or("");
+            return token.parse::<f64>().map_err(|_| ObjError::Parse {
+                line,
+                token: token.to_string(),
+            });
+        };
+        return Ok((next()?, next()?));
+    }
+
+    // Vertex and optional texture and normal indices from `v`, `v/vt`, `v//vn` or
+    // `v/vt/vn`, as written; see `lookup` for how they are resolved
+    fn parse_face_vertex(
+        token: &str,
+        line: usize,
+    ) -> Result<(i64, Option<i64>, Option<i64>), ObjError> {
+        let parse = |s: &str| {
+            return s.parse::<i64>().map_err(|_| ObjError::Parse {
+                line,
+                token: token.to_string(),
+            });
+        };
+        let mut parts = token.split('/');
+        let v = parse(parts.next().unwrap_or(""))?;
+        let mut optional = || match parts.next() {
+            Some(i) if !i.is_empty() => parse(i).map(Some),
+            _ => Ok(None),
+        };
+        let t = optional()?;
+        let n = optional()?;
+        return Ok((v, t, n));
+    }
+
+    // OBJ indices count from 1, or back from the last element read so far when negative,
+    // so -1 is the latest. 0 refers to nothing.
+    fn lookup<T: Copy>(list: &[T], index: i64, line: usize) -> Result<T, ObjError> {
+        let position = match index {
+            1.. => usize::try_from(index - 1).ok(),
+            0 => None,
+            _ => usize::try_from(index.unsigned_abs())
+                .ok()
+                .and_then(|back| list.len().checked_sub(back)),
+        };
+        return position
+            .and_then(|i| list.get(i))
+            .copied()
+            .ok_or(ObjError::IndexOutOfRange { line, index });
+    }
+}
+
+impl Hittable for Polygon {
+    fn hit(&self, ray: &Ray, interval: Interval) -> HitResult {
+        return self.bvh.hit(ray, interval);
+    }
+
+    fn hit_any(&self, ray: &Ray, interval: Interval) -> bool {
+        return self.bvh.hit_any(ray, interval);
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        return self.bvh.bounding_box();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const QUAD_CUBE: &str = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+v 0 0 1
+v 1 0 1
+v 1 1 1
+v 0 1 1
+f 1 4 3 2
+f 5 6 7 8
+f 1 2 6 5
+f 2 3 7 6
+f 3 4 8 7
+f 4 1 5 8
+";
+
+    fn gray() -> Arc<dyn Material> {
+        return Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    }
+
+    // Hit record for a ray from `origin` along `dir`, failing the test on a miss
+    fn hit(object: &dyn Hittable, origin: Vec3, dir: Vec3) -> HitRecord {
+        return match object.hit(&Ray::new(origin, dir), Interval::ALMOST_FORWARD) {
+            HitResult::Hit(rec) => rec,
+            HitResult::Miss => panic!("ray from {:?} along {:?} missed", origin, dir),
+        };
+    }
+
+    #[test]
+    fn quad_faces_split_into_triangle_fans() {
+        let cube = Polygon::from_reader(QUAD_CUBE.as_bytes(), &gray()).unwrap();
+        assert_eq!(cube.triangles().count(), 12);
+        let bbox = cube.bounding_box();
+        for axis in 0..3 {
+            let extent = bbox.axis_interval(axis);
+            assert!(extent.min <= 0.0 && extent.min > -0.01, "axis {}", axis);
+            assert!(extent.max >= 1.0 && extent.max < 1.01, "axis {}", axis);
+        }
+
+        let ray = Ray::new(Vec3::new(0.5, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        match cube.hit(&ray, Interval::ALMOST_FORWARD) {
+            HitResult::Hit(rec) => assert!((rec.t - 4.0).abs() < 1e-12),
+            HitResult::Miss => panic!("ray missed the cube"),
+        }
+    }
+
+    #[test]
+    fn batched_mesh_matches_its_plain_triangle_list() {
+        // A bumpy 10 by 10 height field, 200 triangles
+        let mut obj = String::new();
+        for z in 0..=10 {
+            for x in 0..=10 {
+                let y = ((x * 7 + z * 3) % 5) as f64 * 0.1;
+                obj += &format!("v {} {} {}\n", x, y, -z);
+            }
+        }
+        for z in 0..10 {
+            for x in 0..10 {
+                let i = z * 11 + x + 1;
+                obj += &format!("f {} {} {} {}\n", i, i + 1, i + 12, i + 11);
+            }
+        }
+        let mesh = Polygon::from_reader(obj.as_bytes(), &gray()).unwrap();
+        assert_eq!(mesh.triangles().count(), 200);
+        let mut plain = HittableList::new();
+        for triangle in mesh.triangles() {
+            plain.add(Arc::new(triangle.clone()));
+        }
+
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..500 {
+            let origin = Vec3::new(
+                rng.random_range(-1.0..11.0),
+                2.0,
+                -rng.random_range(-1.0..11.0),
+            );
+            let dir = Vec3::new(
+                rng.random_range(-0.5..0.5),
+                -1.0,
+                rng.random_range(-0.5..0.5),
+            );
+            let ray = Ray::new(origin, dir);
+            match (
+                mesh.hit(&ray, Interval::ALMOST_FORWARD),
+                plain.hit(&ray, Interval::ALMOST_FORWARD),
+            ) {
+                (HitResult::Hit(a), HitResult::Hit(b)) => assert!((a.t - b.t).abs() < 1e-9),
+                (HitResult::Miss, HitResult::Miss) => {}
+                _ => panic!("batched and plain mesh disagree from {:?}", origin),
+            }
+            assert_eq!(
+                mesh.hit_any(&ray, Interval::ALMOST_FORWARD),
+                plain.hit_any(&ray, Interval::ALMOST_FORWARD)
+            );
+        }
+    }
+
+    #[test]
+    fn faces_with_too_few_vertices_add_nothing() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2\nf 1\nf 1 2 3\n";
+        let mesh = Polygon::from_reader(obj.as_bytes(), &gray()).unwrap();
+        assert_eq!(mesh.triangles().count(), 1);
+    }
+
+    #[test]
+    fn negative_obj_indices_count_back_from_the_latest_element() {
+        let relative = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 9 9 9\nf 1 -3 -2\n";
+        let mesh = Polygon::from_reader(relative.as_bytes(), &gray()).unwrap();
+        for triangle in mesh.triangles() {
+            assert_eq!(
+                [triangle.a, triangle.b, triangle.c],
+                [
+                    Vec3::EMPTY,
+                    Vec3::new(1.0, 0.0, 0.0),
+                    Vec3::new(0.0, 1.0, 0.0)
+                ]
+            );
+        }
+        assert_eq!(mesh.triangles().count(), 2);
+    }
+
+    fn obj_error(obj: &str) -> ObjError {
+        return match Polygon::from_reader(obj.as_bytes(), &gray()) {
+            Ok(_) => panic!("{:?} loaded without an error", obj),
+            Err(e) => e,
+        };
+    }
+
+    #[test]
+    fn malformed_obj_numbers_report_their_line_and_token() {
+        let vertices = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+        let cases = [
+            ("v 1 x 3\n".to_string(), 1, "x"),
+            ("v 1 2\n".to_string(), 1, ""),
+            (format!("{}vn 0 0 1e\n", vertices), 4, "1e"),
+            (format!("{}vt 0.5\n", vertices), 4, ""),
+            (format!("{}f 1 a 3\n", vertices), 4, "a"),
+            (format!("{}f 1 2/x 3\n", vertices), 4, "2/x"),
+            (format!("{}f 1 2 3.0\n", vertices), 4, "3.0"),
+        ];
+        for (obj, expected_line, expected_token) in cases {
+            match obj_error(&obj) {
+                ObjError::Parse { line, token } => {
+                    assert_eq!((line, token.as_str()), (expected_line, expected_token))
+                }
+                e => panic!("{:?} gave {}", obj, e),
+            }
+        }
+    }
+
+    #[test]
+    fn obj_indices_outside_their_lists_are_rejected() {
+        let vertices = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+        for (face, expected) in [
+            ("f 1 2 9", 9),
+            ("f 0 1 2", 0),
+            ("f -4 -2 -1", -4),
+            ("f 1/1 2/2 3/3", 1),
+            ("f 1//2 2//2 3//2", 2),
+        ] {
+            match obj_error(&format!("{}{}\n", vertices, face)) {
+                ObjError::IndexOutOfRange { line, index } => {
+                    assert_eq!((line, index), (4, expected), "{}", face)
+                }
+                e => panic!("{:?} gave {}", face, e),
+            }
+        }
+        assert_eq!(
+            obj_error(&format!("{}f 1 2 9\n", vertices)).to_string(),
+            "line 4: index 9 is out of range"
+        );
+    }
+
+    #[test]
+    fn quad_is_hit_inside_and_missed_just_past_an_edge() {
+        let quad = Quad::new(
+            Vec3::EMPTY,
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            &gray(),
+        );
+        let down = Vec3::new(0.0, 0.0, -1.0);
+        let rec = hit(&quad, Vec3::new(0.5, 0.5, 1.0), down);
+        assert_eq!(rec.t, 1.0);
+        assert_eq!((rec.u, rec.v), (0.5, 0.5));
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+
+        let past_edge = Ray::new(Vec3::new(1.01, 0.5, 1.0), down);
+        assert!(matches!(
+            quad.hit(&past_edge, Interval::ALMOST_FORWARD),
+            HitResult::Miss
+        ));
+    }
+
+    #[test]
+    fn box_faces_point_outward() {
+        let cube = BoxPrim::new(
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            &gray(),
+        );
+        let rec = hit(&cube, Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(rec.t, 4.0);
+        assert_eq!(rec.point, Vec3::new(0.0, 0.0, 1.0));
+
+        // Coming in along each axis, the face met first must face the ray
+        for axis in 0..3 {
+            for sign in [-1.0, 1.0] {
+                let mut dir = Vec3::EMPTY;
+                dir[axis] = sign;
+                let rec = hit(&cube, -dir * 5.0, dir);
+                assert!(rec.front_face, "face hit along {:?} faces inward", dir);
+                assert_eq!(rec.normal, -dir);
+            }
+        }
+    }
+
+    #[test]
+    fn sphere_uvs_map_poles_and_equator() {
+        let sphere = Sphere::new(Vec3::EMPTY, 1.0, &gray());
+        // From outside, straight toward the center, so each ray meets the point facing it
+        let uv_at = |p: Vec3| {
+            let rec = hit(&sphere, p * 3.0, -p);
+            return (rec.u, rec.v);
+        };
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+        assert!(close(uv_at(Vec3::new(0.0, 1.0, 0.0)).1, 1.0));
+        assert!(close(uv_at(Vec3::new(0.0, -1.0, 0.0)).1, 0.0));
+        for (p, u) in [
+            (Vec3::new(0.0, 0.0, 1.0), 0.25),
+            (Vec3::new(1.0, 0.0, 0.0), 0.5),
+            (Vec3::new(0.0, 0.0, -1.0), 0.75),
+        ] {
+            let (pu, pv) = uv_at(p);
+            assert!(
+                close(pu, u) && close(pv, 0.5),
+                "{:?} maps to ({}, {})",
+                p,
+                pu,
+                pv
+            );
+        }
+    }
+
+    #[test]
+    fn moving_sphere_is_hit_at_its_interpolated_center() {
+        let sphere = MovingSphere::new(
+            Vec3::new(0.0, 0.0, -5.0),
+            Vec3::new(4.0, 0.0, -5.0),
+            0.0,
+            1.0,
+            0.5,
+            &gray(),
+        );
+        assert_eq!(sphere.center(0.25), Vec3::new(1.0, 0.0, -5.0));
+
+        // Aimed at where the center is a quarter of the way through the shutter
+        let down = Vec3::new(0.0, 0.0, -1.0);
+        let origin = Vec3::new(1.0, 0.0, 0.0);
+        let at_quarter = Ray::with_time(origin, down, 0.25);
+        match sphere.hit(&at_quarter, Interval::ALMOST_FORWARD) {
+            HitResult::Hit(rec) => {
+                assert!((rec.t - 4.5).abs() < 1e-12);
+                assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+            }
+            HitResult::Miss => panic!("missed the sphere at t = 0.25"),
+        }
+        // By the end of the shutter it has moved out of the way
+        let at_end = Ray::with_time(origin, down, 1.0);
+        assert!(matches!(
+            sphere.hit(&at_end, Interval::ALMOST_FORWARD),
+            HitResult::Miss
+        ));
+    }
+
+    #[test]
+    fn static_spheres_ignore_ray_time() {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, -5.0), 1.0, &gray());
+        let down = Vec3::new(0.0, 0.0, -1.0);
+        for time in [0.0, 0.5, 100.0] {
+            let rec = hit(&sphere, Vec3::EMPTY, down);
+            let timed = match sphere.hit(
+                &Ray::with_time(Vec3::EMPTY, down, time),
+                Interval::ALMOST_FORWARD,
+            ) {
+                HitResult::Hit(rec) => rec,
+                HitResult::Miss => panic!("missed at time {}", time),
+            };
+            assert_eq!(timed.t, rec.t);
+        }
+    }
+
+    #[test]
+    fn cylinder_is_hit_on_its_side_and_missed_above_the_top() {
+        let pillar = Cylinder::new(Vec3::EMPTY, Vec3::new(0.0, 1.0, 0.0), 1.0, 2.0, &gray());
+        let rec = hit(&pillar, Vec3::new(0.0, 1.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!((rec.t - 4.0).abs() < 1e-12);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+
+        let above = Ray::new(Vec3::new(0.0, 2.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(matches!(
+            pillar.hit(&above, Interval::ALMOST_FORWARD),
+            HitResult::Miss
+        ));
+
+        // Looking down the axis meets the top cap only when the ends are closed
+        let rec = hit(&pillar, Vec3::new(0.2, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!((rec.t - 3.0).abs() < 1e-12);
+        assert_eq!(rec.normal, Vec3::new(0.0, 1.0, 0.0));
+        let tube = pillar.with_caps(false);
+        let down_the_middle = Ray::new(Vec3::new(0.2, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(matches!(
+            tube.hit(&down_the_middle, Interval::ALMOST_FORWARD),
+            HitResult::Miss
+        ));
+    }
+
+    #[test]
+    fn disk_is_hit_inside_the_radius_and_missed_just_outside() {
+        let disk = Disk::new(Vec3::EMPTY, Vec3::new(0.0, 1.0, 0.0), 1.0, &gray());
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let rec = hit(&disk, Vec3::new(0.5, 2.0, 0.0), down);
+        assert_eq!(rec.t, 2.0);
+        assert_eq!(rec.normal, Vec3::new(0.0, 1.0, 0.0));
+        assert!((rec.v - 0.5).abs() < 1e-12);
+
+        let outside = Ray::new(Vec3::new(1.01, 2.0, 0.0), down);
+        assert!(matches!(
+            disk.hit(&outside, Interval::ALMOST_FORWARD),
+            HitResult::Miss
+        ));
+    }
+
+    #[test]
+    fn disk_u_runs_once_around_the_rim() {
+        let disk = Disk::new(Vec3::EMPTY, Vec3::new(0.0, 0.0, 1.0), 2.0, &gray());
+        let mut us: Vec<f64> = (0..8)
+            .map(|k| {
+                let angle = k as f64 * PI / 4.0;
+                let p = Vec3::new(angle.cos(), angle.sin(), 1.0);
+                let rec = hit(&disk, p, Vec3::new(0.0, 0.0, -1.0));
+                assert!((rec.v - 0.5).abs() < 1e-12);
+                assert!((0.0..=1.0).contains(&rec.u));
+                return rec.u;
+            })
+            .collect();
+        // Eight evenly spaced angles give eight evenly spaced u values
+        us.sort_by(f64::total_cmp);
+        for pair in us.windows(2) {
+            assert!((pair[1] - pair[0] - 0.125).abs() < 1e-9, "{:?}", us);
+        }
+    }
+
+    // Unit right triangle at z = -1, its front facing the origin
+    fn facing_triangle() -> Triangle {
+        return Triangle::new(
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, -1.0),
+            &gray(),
+        );
+    }
+
+    #[test]
+    fn triangle_hit_reports_distance_and_front_normal() {
+        let rec = hit(&facing_triangle(), Vec3::EMPTY, Vec3::new(0.25, 0.25, -1.0));
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!(rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn triangle_misses_just_past_an_edge_and_when_parallel() {
+        let triangle = facing_triangle();
+        let ray = |x: f64, y: f64| Ray::new(Vec3::EMPTY, Vec3::new(x, y, -1.0));
+        let interval = Interval::ALMOST_FORWARD;
+        // Either side of the long edge from (1, 0) to (0, 1)
+        assert!(matches!(
+            triangle.hit(&ray(0.5 - 1e-9, 0.5 - 1e-9), interval),
+            HitResult::Hit(_)
+        ));
+        assert!(matches!(
+            triangle.hit(&ray(0.5 + 1e-9, 0.5 + 1e-9), interval),
+            HitResult::Miss
+        ));
+        // Outside the short edges
+        assert!(matches!(
+            triangle.hit(&ray(-1e-9, 0.5), interval),
+            HitResult::Miss
+        ));
+        assert!(matches!(
+            triangle.hit(&ray(0.5, -1e-9), interval),
+            HitResult::Miss
+        ));
+        // In the plane of the triangle, grazing it edge on
+        let grazing = Ray::new(Vec3::new(-1.0, 0.2, -1.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(matches!(triangle.hit(&grazing, interval), HitResult::Miss));
+    }
+
+    #[test]
+    fn triangle_back_is_hit_with_a_flipped_normal() {
+        let rec = hit(
+            &facing_triangle(),
+            Vec3::new(0.25, 0.25, -2.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!(!rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn smooth_triangles_blend_vertex_normals_barycentrically() {
+        let normals = [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0).unit(),
+            Vec3::new(0.0, 1.0, 1.0).unit(),
+        ];
+        let t = facing_triangle();
+        let smooth = Triangle::with_normals(t.a, t.b, t.c, normals, &gray());
+        // A quarter of the way along each edge from a, so weights of 1/2, 1/4 and 1/4
+        let expected = (normals[0] * 0.5 + normals[1] * 0.25 + normals[2] * 0.25).unit();
+
+        let front = hit(
+            &smooth,
+            Vec3::new(0.25, 0.25, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        assert!(
+            (front.normal - expected).length() < 1e-12,
+            "{:?}",
+            front.normal
+        );
+        let back = hit(
+            &smooth,
+            Vec3::new(0.25, 0.25, -2.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        assert!(
+            (back.normal + expected).length() < 1e-12,
+            "{:?}",
+            back.normal
+        );
+    }
+
+    #[test]
+    fn cancelling_vertex_normals_fall_back_to_the_geometric_normal() {
+        let t = facing_triangle();
+        let up = Vec3::new(0.0, 0.0, 1.0);
+        for normals in [[Vec3::EMPTY; 3], [up, -up, -up]] {
+            let smooth = Triangle::with_normals(t.a, t.b, t.c, normals, &gray());
+            let rec = hit(&smooth, Vec3::new(0.25, 0.25, 1.0), -up);
+            assert_eq!(rec.normal, up);
+        }
+    }
+
+    #[test]
+    fn obj_vertex_normals_and_texture_coordinates_are_read_in_every_face_form() {
+        let obj = "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 0 1
+vn 0 0 1
+vn 1 0 1
+vn 0 1 1
+f 1//1 2//2 3//3
+f 1/1/1 2/2/2 3/3/3
+f 1/1 2/2 3/3
+f 1 2 3
+";
+        let mesh = Polygon::from_reader(obj.as_bytes(), &gray()).unwrap();
+        let mut triangles = mesh.triangles().collect::<Vec<_>>();
+        triangles.sort_by_key(|t| (t.normals.is_none(), t.uvs.is_none()));
+        let vn = [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(0.0, 1.0, 1.0),
+        ];
+        let vt = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)];
+        let forms: Vec<_> = triangles.iter().map(|t| (t.normals, t.uvs)).collect();
+        assert_eq!(
+            forms,
+            [
+                (Some(vn), Some(vt)),
+                (Some(vn), None),
+                (None, Some(vt)),
+                (None, None)
+            ]
+        );
+
+        // The blended normal comes back from a hit on a mesh of just the smooth face
+        let smooth = obj
+            .lines()
+            .filter(|l| !l.starts_with("f ") || l.contains("//"));
+        let smooth = smooth.collect::<Vec<_>>().join("\n");
+        let mesh = Polygon::from_reader(smooth.as_bytes(), &gray()).unwrap();
+        let rec = hit(&mesh, Vec3::new(0.25, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let expected = (vn[0] * 0.5 + vn[1] * 0.25 + vn[2] * 0.25).unit();
+        assert!((rec.normal - expected).length() < 1e-12, "{:?}", rec.normal);
+    }
+
+    #[test]
+    fn culled_triangles_miss_from_behind_only() {
+        let behind = Ray::new(Vec3::new(0.25, 0.25, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let front = Ray::new(Vec3::EMPTY, Vec3::new(0.25, 0.25, -1.0));
+        let interval = Interval::ALMOST_FORWARD;
+
+        let two_sided = facing_triangle();
+        assert!(matches!(
+            two_sided.hit(&behind, interval),
+            HitResult::Hit(_)
+        ));
+
+        let culled = facing_triangle().with_backface_culling(true);
+        assert!(matches!(culled.hit(&behind, interval), HitResult::Miss));
+        assert!(matches!(culled.hit(&front, interval), HitResult::Hit(_)));
+    }
+
+    fn sphere_at(x: f64) -> Arc<dyn Hittable> {
+        return Arc::new(Sphere::new(Vec3::new(x, 0.0, -3.0), 0.5, &gray()));
+    }
+
+    #[test]
+    fn list_add_remove_and_clear() {
+        let mut world = HittableList::new();
+        assert!(world.is_empty());
+        world.add(sphere_at(0.0));
+        world.add(sphere_at(2.0));
+        world.add_light(PointLight::new(Vec3::EMPTY, Vec3::new(1.0, 1.0, 1.0), 1.0));
+        assert_eq!(world.len(), 2);
+
+        let ray = Ray::new(Vec3::EMPTY, Vec3::new(0.0, 0.0, -1.0));
+        assert!(matches!(
+            world.hit(&ray, Interval::ALMOST_FORWARD),
+            HitResult::Hit(_)
+        ));
+        // Removing the sphere in front of the camera clears the view straight ahead
+        let removed = world.remove(0);
+        assert!((removed.bounding_box().x.min + 0.5).abs() < 1e-9);
+        assert_eq!(world.len(), 1);
+        assert!(matches!(
+            world.hit(&ray, Interval::ALMOST_FORWARD),
+            HitResult::Miss
+        ));
+
+        world.clear();
+        assert!(world.is_empty());
+        assert!(world.lights.is_empty());
+    }
+
+    #[test]
+    fn list_collects_and_extends_from_iterators() {
+        let mut world: HittableList = (0..3).map(|i| sphere_at(i as f64 * 2.0)).collect();
+        assert_eq!(world.len(), 3);
+        assert!(world.lights.is_empty());
+
+        world.extend([sphere_at(-2.0), sphere_at(-4.0)]);
+        assert_eq!(world.len(), 5);
+        // The last sphere added is the one found down its line of sight
+        let rec = hit(&world, Vec3::new(-4.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!((rec.point.x() + 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn hit_any_agrees_with_hit() {
+        let mut world: HittableList = (0..4).map(|i| sphere_at(i as f64 - 1.5)).collect();
+        world.add(Arc::new(facing_triangle()));
+        world.add(Arc::new(Quad::new(
+            Vec3::new(-2.0, -1.0, -4.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.5, 0.0),
+            &gray(),
+        )));
+
+        let mut rng = StdRng::seed_from_u64(6);
+        for _ in 0..1000 {
+            let dir = Vec3::new(
+                rng.random_range(-1.0..1.0),
+                rng.random_range(-1.0..1.0),
+                -1.0,
+            );
+            let ray = Ray::new(Vec3::EMPTY, dir);
+            // Short limits stop some rays before what they would hit
+            let interval = Interval::new(0.001, rng.random_range(1.0..6.0));
+            let hit = matches!(world.hit(&ray, interval), HitResult::Hit(_));
+            assert_eq!(world.hit_any(&ray, interval), hit, "{:?}", dir);
+        }
+    }
 }
@@ -0,0 +1,60 @@
+// The counters are shared by the whole process, so this lives apart from the unit tests,
+// whose renders would otherwise be counted too. Keep it to a single test for the same
+// reason.
+#![cfg(feature = "stats")]
+
+use ray_trace_rs::batch::TriangleBatch;
+use ray_trace_rs::camera::Camera;
+use ray_trace_rs::interval::Interval;
+use ray_trace_rs::material::{Lambertian, Material};
+use ray_trace_rs::stats::RenderStats;
+use ray_trace_rs::vec3::Vec3;
+use ray_trace_rs::world::{HitResult, Hittable, HittableList, Ray, Sphere, Triangle};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[test]
+fn renders_and_batches_count_every_test_once() {
+    let material: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    let mut world = HittableList::new();
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(0.0, 0.0, -2.0),
+        1.0,
+        &material,
+    )));
+    let camera = Camera::new()
+        .with_resolution(7, 5)
+        .with_samples(3)
+        .with_seed(1);
+
+    for threads in [1, 3] {
+        let (_, stats) = camera.render_with_stats(&world, threads).unwrap();
+        assert_eq!(stats.primary_rays, 7 * 5 * 3);
+        // Bounces and shadow rays come on top of the camera rays
+        assert!(stats.rays_cast > stats.primary_rays);
+        assert!(stats.ray_sphere_tests >= stats.primary_rays);
+        assert_eq!(stats.ray_triangle_tests, 0);
+    }
+
+    // A batch hit counts each of its triangles once, the nearest included
+    let triangles: Vec<Triangle> = (0..4)
+        .map(|k| {
+            let z = -1.0 - k as f64;
+            Triangle::new(
+                Vec3::new(-1.0, -1.0, z),
+                Vec3::new(1.0, -1.0, z),
+                Vec3::new(0.0, 1.0, z),
+                &material,
+            )
+        })
+        .collect();
+    let batch = TriangleBatch::new(triangles);
+    let before = RenderStats::totals();
+    let ray = Ray::new(Vec3::EMPTY, Vec3::new(0.0, 0.0, -1.0));
+    assert!(matches!(
+        batch.hit(&ray, Interval::ALMOST_FORWARD),
+        HitResult::Hit(_)
+    ));
+    let counted = RenderStats::totals().since(&before, Duration::ZERO);
+    assert_eq!(counted.ray_triangle_tests, 4);
+}